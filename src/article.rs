@@ -0,0 +1,363 @@
+use regex::Regex;
+use reqwest::Url;
+use scraper::{ElementRef, Html, Node, Selector};
+use std::collections::HashMap;
+
+/// Paragraphs shorter than this are likely captions or bylines, not article.
+const MIN_PARAGRAPH_CHARS: usize = 25;
+
+/// Elements which are never part of an article.
+const SKIPPED: &[&str] = &[
+    "script", "style", "noscript", "nav", "header", "footer", "aside", "form", "iframe", "button",
+    "svg", "template", "img", "figure", "select",
+];
+
+/// Class or id hinting that element holds the article, or that it does not.
+const POSITIVE: &str = r"(?i)article|body|content|entry|main|page|post|text|blog|story";
+const NEGATIVE: &str = r"(?i)comment|meta|footer|footnote|sidebar|sponsor|share|social|related|nav|menu|promo|banner|^ad-|widget";
+
+fn is_skipped(element: &ElementRef<'_>) -> bool {
+    element
+        .ancestors()
+        .chain(std::iter::once(**element))
+        .filter_map(|n| n.value().as_element())
+        .any(|e| SKIPPED.contains(&e.name()))
+}
+
+fn class_weight(element: &ElementRef<'_>, positive: &Regex, negative: &Regex) -> f64 {
+    let value = element.value();
+    let names = format!(
+        "{} {}",
+        value.attr("class").unwrap_or_default(),
+        value.id().unwrap_or_default()
+    );
+    let mut weight = 0.0;
+    if negative.is_match(&names) {
+        weight -= 25.0;
+    }
+    if positive.is_match(&names) {
+        weight += 25.0;
+    }
+    weight
+}
+
+/// Score an element starts with, before paragraphs in it are counted.
+fn initial_score(element: &ElementRef<'_>, positive: &Regex, negative: &Regex) -> f64 {
+    let tag = match element.value().name() {
+        "article" => 10.0,
+        "div" | "main" | "section" => 5.0,
+        "pre" | "td" | "blockquote" => 3.0,
+        "ol" | "ul" | "dl" | "li" | "form" => -3.0,
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "th" => -5.0,
+        _ => 0.0,
+    };
+    tag + class_weight(element, positive, negative)
+}
+
+fn text_len(element: &ElementRef<'_>) -> usize {
+    element.text().map(|t| t.trim().chars().count()).sum()
+}
+
+/// Share of text of element which is in links, menus are mostly links.
+fn link_density(element: &ElementRef<'_>) -> f64 {
+    let links = Selector::parse("a").unwrap();
+    let total = text_len(element).max(1);
+    let linked: usize = element.select(&links).map(|a| text_len(&a)).sum();
+    linked as f64 / total as f64
+}
+
+/// Finds element holding the main article, readability style: paragraphs
+/// score their parent and, by half, their grandparent, on their length and
+/// number of commas. Scores are weighed by class names hinting at content or
+/// boilerplate, and by how little of their text is links.
+fn main_element(document: &Html) -> Option<ElementRef<'_>> {
+    let positive = Regex::new(POSITIVE).unwrap();
+    let negative = Regex::new(NEGATIVE).unwrap();
+    let paragraphs = Selector::parse("p, pre, td, blockquote").unwrap();
+    let mut scores = HashMap::new();
+    for paragraph in document.select(&paragraphs) {
+        if is_skipped(&paragraph) {
+            continue;
+        }
+        let text = paragraph.text().collect::<String>();
+        let len = text.trim().chars().count();
+        if len < MIN_PARAGRAPH_CHARS {
+            continue;
+        }
+        let score = 1.0 + text.matches(',').count() as f64 + (len / 100).min(3) as f64;
+        let parent = paragraph.parent().and_then(ElementRef::wrap);
+        let grandparent = parent.and_then(|p| p.parent()).and_then(ElementRef::wrap);
+        for (ancestor, share) in parent
+            .into_iter()
+            .map(|p| (p, 1.0))
+            .chain(grandparent.map(|g| (g, 0.5)))
+        {
+            *scores
+                .entry(ancestor.id())
+                .or_insert_with(|| initial_score(&ancestor, &positive, &negative)) += score * share;
+        }
+    }
+    let best = scores
+        .into_iter()
+        .filter_map(|(id, score)| {
+            let element = ElementRef::wrap(document.tree.get(id)?)?;
+            Some((element, score * (1.0 - link_density(&element))))
+        })
+        .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(element, _)| element);
+    best.or_else(|| {
+        let fallback = Selector::parse("article, main, body").unwrap();
+        document.select(&fallback).next()
+    })
+}
+
+/// Writes an html subtree as markdown.
+struct Markdown {
+    out: String,
+    base: Option<Url>,
+    /// Kinds of lists being written, innermost last, with their item count.
+    lists: Vec<(bool, usize)>,
+}
+
+impl Markdown {
+    fn text(&mut self, text: &str) {
+        let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        if collapsed.is_empty() {
+            if !self.out.is_empty() && !self.out.ends_with(char::is_whitespace) {
+                self.out.push(' ');
+            }
+            return;
+        }
+        if text.starts_with(char::is_whitespace) && !self.out.ends_with(char::is_whitespace) {
+            self.out.push(' ');
+        }
+        self.out.push_str(&collapsed);
+        if text.ends_with(char::is_whitespace) {
+            self.out.push(' ');
+        }
+    }
+
+    fn block_break(&mut self) {
+        let trimmed = self.out.trim_end_matches([' ', '\t']).len();
+        self.out.truncate(trimmed);
+        if !self.out.is_empty() && !self.out.ends_with("\n\n") {
+            self.out.push_str(if self.out.ends_with('\n') {
+                "\n"
+            } else {
+                "\n\n"
+            });
+        }
+    }
+
+    fn children(&mut self, element: &ElementRef<'_>) {
+        for child in element.children() {
+            match child.value() {
+                Node::Text(text) => self.text(text),
+                Node::Element(_) => {
+                    if let Some(child) = ElementRef::wrap(child) {
+                        self.element(&child);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Content of element written on its own, e.g. to quote it.
+    fn nested(&self, element: &ElementRef<'_>) -> String {
+        let mut nested = Markdown {
+            out: String::new(),
+            base: self.base.clone(),
+            lists: vec![],
+        };
+        nested.children(element);
+        nested.out.trim().to_string()
+    }
+
+    fn element(&mut self, element: &ElementRef<'_>) {
+        let name = element.value().name();
+        if SKIPPED.contains(&name) {
+            return;
+        }
+        match name {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let level = name[1..].parse().unwrap_or(1);
+                self.block_break();
+                self.out.push_str(&"#".repeat(level));
+                self.out.push(' ');
+                self.out.push_str(&self.nested(element).replace('\n', " "));
+                self.block_break();
+            }
+            "br" => self.out.push('\n'),
+            "hr" => {
+                self.block_break();
+                self.out.push_str("---");
+                self.block_break();
+            }
+            "pre" => {
+                self.block_break();
+                let code = element.text().collect::<String>();
+                self.out.push_str("```\n");
+                self.out.push_str(code.trim_end());
+                self.out.push_str("\n```");
+                self.block_break();
+            }
+            "code" => {
+                self.out.push('`');
+                self.out.push_str(&element.text().collect::<String>());
+                self.out.push('`');
+            }
+            "strong" | "b" => {
+                let text = self.nested(element);
+                if !text.is_empty() {
+                    self.out.push_str(&format!("**{}**", text));
+                }
+            }
+            "em" | "i" => {
+                let text = self.nested(element);
+                if !text.is_empty() {
+                    self.out.push_str(&format!("*{}*", text));
+                }
+            }
+            "a" => {
+                let text = self.nested(element);
+                let href = element
+                    .value()
+                    .attr("href")
+                    .filter(|h| !h.starts_with('#') && !h.starts_with("javascript:"))
+                    .map(|h| match &self.base {
+                        Some(base) => base.join(h).map_or(h.to_string(), String::from),
+                        None => h.to_string(),
+                    });
+                match href {
+                    Some(href) if !text.is_empty() => {
+                        self.out.push_str(&format!("[{}]({})", text, href))
+                    }
+                    _ => self.out.push_str(&text),
+                }
+            }
+            "blockquote" => {
+                let quote = self.nested(element);
+                self.block_break();
+                let quoted = quote
+                    .lines()
+                    .map(|l| format!("> {}", l).trim_end().to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                self.out.push_str(&quoted);
+                self.block_break();
+            }
+            "ul" | "ol" => {
+                if self.lists.is_empty() {
+                    self.block_break();
+                }
+                self.lists.push((name == "ol", 0));
+                self.children(element);
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.block_break();
+                }
+            }
+            "li" => {
+                let depth = self.lists.len().saturating_sub(1);
+                let marker = match self.lists.last_mut() {
+                    Some((true, count)) => {
+                        *count += 1;
+                        format!("{}.", count)
+                    }
+                    _ => "-".to_string(),
+                };
+                let trimmed = self.out.trim_end_matches([' ', '\t']).len();
+                self.out.truncate(trimmed);
+                if !self.out.is_empty() && !self.out.ends_with('\n') {
+                    self.out.push('\n');
+                }
+                self.out
+                    .push_str(&format!("{}{} ", "  ".repeat(depth), marker));
+                self.children(element);
+            }
+            "p" | "div" | "section" | "article" | "main" | "table" | "tr" | "dl" | "dt" | "dd" => {
+                self.block_break();
+                self.children(element);
+                self.block_break();
+            }
+            _ => self.children(element),
+        }
+    }
+}
+
+/// Converts html element to markdown, resolving links against base url.
+fn markdown(element: &ElementRef<'_>, base: &str) -> String {
+    let mut markdown = Markdown {
+        out: String::new(),
+        base: Url::parse(base).ok(),
+        lists: vec![],
+    };
+    markdown.children(element);
+    markdown
+        .out
+        .lines()
+        .map(str::trim_end)
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+/// Main article of an html page as markdown, without navigation, comments
+/// and other boilerplate. Links are resolved against url of the page.
+pub(crate) fn extract(html: &str, url: &str) -> Option<String> {
+    let document = Html::parse_document(html);
+    let article = markdown(&main_element(&document)?, url);
+    Some(article).filter(|a| !a.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"<html><head><title>t</title><script>var x = 1;</script></head>
+<body>
+<nav><ul><li><a href="/">Home</a></li><li><a href="/about">About us, the team, and more</a></li></ul></nav>
+<div class="sidebar"><p>Subscribe to our newsletter, it is great, really, trust us.</p></div>
+<div class="post-content">
+  <h1>Cancel  safety</h1>
+  <p>Futures can be dropped at any <em>await</em> point, which matters for state.</p>
+  <p>See the <a href="/docs/select">select docs</a>, which explain it, in detail.</p>
+  <pre><code>select! {
+    _ = a => {}
+}</code></pre>
+  <ul><li>first point</li><li>second <code>point</code>
+    <ol><li>nested</li></ol></li></ul>
+  <blockquote><p>Quoted text that spans a line.</p></blockquote>
+</div>
+<div class="comments"><p>Great post, thanks a lot, I learned a lot from it!</p></div>
+<footer><p>Copyright 2021, all rights reserved, do not copy.</p></footer>
+</body></html>"#;
+
+    #[test]
+    fn test_extract() {
+        let article = extract(PAGE, "https://blog.example.com/posts/1").unwrap();
+        assert_eq!(
+            "# Cancel safety\n\n\
+             Futures can be dropped at any *await* point, which matters for state.\n\n\
+             See the [select docs](https://blog.example.com/docs/select), which explain it, in detail.\n\n\
+             ```\nselect! {\n    _ = a => {}\n}\n```\n\n\
+             - first point\n- second `point`\n  1. nested\n\n\
+             > Quoted text that spans a line.",
+            article
+        );
+    }
+
+    #[test]
+    fn test_extract_without_paragraphs() {
+        assert_eq!(None, extract("<html><body></body></html>", "https://a.com"));
+        assert_eq!(
+            Some("short".to_string()),
+            extract(
+                "<html><body><span>short</span></body></html>",
+                "https://a.com"
+            )
+        );
+    }
+}
@@ -0,0 +1,372 @@
+use crate::error::{Error, Result};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Secrets by key, kept apart from config in a file only its owner can read.
+pub type Secrets = HashMap<String, String>;
+
+/// Name of the file in data directory.
+pub(crate) const FILE: &str = "secrets.json";
+
+pub(crate) fn secrets_path() -> String {
+    let secrets_path = Path::new(&crate::base_dir()).join(FILE);
+    String::from(secrets_path.to_str().unwrap())
+}
+
+/// Where a credential is read from when a feed is requested. Config only
+/// holds the reference, never the secret itself.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Secret {
+    /// Environment variable holding the secret.
+    Env(String),
+    /// Key of the secret in `secrets.json`.
+    File(String),
+    /// Value which is not secret, e.g. of a header telling who is asking.
+    /// Only taken for headers, never for passwords, tokens or cookies.
+    Value(String),
+}
+
+impl FromStr for Secret {
+    type Err = Error;
+
+    /// Parses `env:VAR`, `secret:KEY` or `value:TEXT`.
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(':') {
+            Some(("env", var)) if !var.is_empty() => Ok(Secret::Env(var.to_string())),
+            Some(("secret", key)) if !key.is_empty() => Ok(Secret::File(key.to_string())),
+            Some(("value", value)) => Ok(Secret::Value(value.to_string())),
+            _ => Err(Error::input(format!(
+                "invalid secret: {}, expected env:VAR, secret:KEY or value:TEXT",
+                s
+            ))),
+        }
+    }
+}
+
+impl Secret {
+    fn resolve(&self, secrets: &Secrets) -> Result<String> {
+        match self {
+            Secret::Env(var) => std::env::var(var)
+                .map_err(|_| Error::input(format!("environment variable {} is not set", var))),
+            Secret::File(key) => secrets.get(key).cloned().ok_or_else(|| {
+                Error::input(format!(
+                    "no secret {} in {}, add it with `secret`",
+                    key, FILE
+                ))
+            }),
+            Secret::Value(value) => Ok(value.clone()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Basic {
+    pub username: String,
+    pub password: Secret,
+}
+
+/// Credentials sent with every request for a feed, and for pages it links
+/// to on the same origin.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Auth {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub basic: Option<Basic>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bearer: Option<Secret>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, Secret>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub cookies: BTreeMap<String, Secret>,
+}
+
+impl Auth {
+    pub(crate) fn is_empty(&self) -> bool {
+        self == &Auth::default()
+    }
+
+    /// Refuses plain values for credentials which are always secret, so
+    /// that they do not end up in config.
+    pub(crate) fn validate(&self) -> Result<()> {
+        let plain = |secret: Option<&Secret>| matches!(secret, Some(Secret::Value(_)));
+        if plain(self.basic.as_ref().map(|b| &b.password)) {
+            return Err(Error::input(
+                "password must be given as env:VAR or secret:KEY",
+            ));
+        }
+        if plain(self.bearer.as_ref()) {
+            return Err(Error::input(
+                "bearer token must be given as env:VAR or secret:KEY",
+            ));
+        }
+        if let Some(name) = self
+            .cookies
+            .iter()
+            .find(|(_, s)| plain(Some(s)))
+            .map(|c| c.0)
+        {
+            return Err(Error::input(format!(
+                "cookie {} must be given as env:VAR or secret:KEY",
+                name
+            )));
+        }
+        Ok(())
+    }
+
+    /// Kinds of credentials set, to show without revealing them.
+    pub(crate) fn describe(&self) -> String {
+        let mut kinds = vec![];
+        if let Some(basic) = &self.basic {
+            kinds.push(format!("basic as {}", basic.username));
+        }
+        if self.bearer.is_some() {
+            kinds.push("bearer".to_string());
+        }
+        if !self.headers.is_empty() {
+            let names = self.headers.keys().cloned().collect::<Vec<_>>();
+            kinds.push(format!("headers {}", names.join(", ")));
+        }
+        if !self.cookies.is_empty() {
+            let names = self.cookies.keys().cloned().collect::<Vec<_>>();
+            kinds.push(format!("cookies {}", names.join(", ")));
+        }
+        kinds.join("; ")
+    }
+
+    /// Request headers carrying the credentials, with secrets read.
+    pub(crate) fn headers(&self, secrets: &Secrets) -> Result<Vec<(String, String)>> {
+        let mut headers = vec![];
+        if let Some(basic) = &self.basic {
+            let credentials = format!("{}:{}", basic.username, basic.password.resolve(secrets)?);
+            let encoded = base64::engine::general_purpose::STANDARD.encode(credentials);
+            headers.push(("Authorization".to_string(), format!("Basic {}", encoded)));
+        }
+        if let Some(token) = &self.bearer {
+            headers.push((
+                "Authorization".to_string(),
+                format!("Bearer {}", token.resolve(secrets)?),
+            ));
+        }
+        for (name, value) in &self.headers {
+            headers.push((name.clone(), value.resolve(secrets)?));
+        }
+        if !self.cookies.is_empty() {
+            let cookies = self
+                .cookies
+                .iter()
+                .map(|(name, value)| Ok(format!("{}={}", name, value.resolve(secrets)?)))
+                .collect::<Result<Vec<_>>>()?;
+            headers.push(("Cookie".to_string(), cookies.join("; ")));
+        }
+        Ok(headers)
+    }
+}
+
+/// Compares secrets in time independent of where they differ, so that
+/// guesses cannot be refined by timing responses.
+pub(crate) fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Secrets file others can read, whose secrets are then refused.
+#[cfg(unix)]
+fn readable_by_others(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o077 != 0
+}
+
+#[cfg(not(unix))]
+fn readable_by_others(_metadata: &fs::Metadata) -> bool {
+    false
+}
+
+/// Reads secrets, refusing a file others can read or write. Missing file
+/// means no secrets were added yet.
+pub(crate) fn _get(path: &str) -> Result<Secrets> {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Secrets::new()),
+        Err(e) => return Err(Error::io(path, e)),
+    };
+    if readable_by_others(&metadata) {
+        return Err(Error::input(format!(
+            "{} can be read by others, restrict it with `chmod 600 {}`",
+            path, path
+        )));
+    }
+    crate::store::read(path)
+}
+
+/// Writes secrets readable by owner only. Unlike other data files no
+/// backups are kept, so that old secrets do not linger around.
+pub(crate) fn _replace(path: &str, secrets: &Secrets) -> Result<()> {
+    if let Some(dir) = Path::new(path).parent() {
+        fs::create_dir_all(dir).map_err(|e| Error::io(&dir.to_string_lossy(), e))?;
+    }
+    let data = serde_json::to_string_pretty(secrets).map_err(|e| Error::serialize(path, e))?;
+    let tmp = format!("{}.tmp", path);
+    // Mode only applies to new files, so one left by an earlier run goes.
+    let _ = fs::remove_file(&tmp);
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&tmp).map_err(|e| Error::io(&tmp, e))?;
+    file.write_all(data.as_bytes())
+        .map_err(|e| Error::io(&tmp, e))?;
+    fs::rename(&tmp, path).map_err(|e| Error::io(path, e))
+}
+
+/// Adds or replaces secret stored under key.
+pub(crate) fn set_secret(key: &str, value: &str) -> Result<()> {
+    let path = secrets_path();
+    let mut secrets = _get(&path)?;
+    secrets.insert(key.to_string(), value.to_string());
+    _replace(&path, &secrets)
+}
+
+pub(crate) fn remove_secret(key: &str) -> Result<()> {
+    let path = secrets_path();
+    let mut secrets = _get(&path)?;
+    if secrets.remove(key).is_none() {
+        return Err(Error::input(format!("no secret {} in {}", key, FILE)));
+    }
+    _replace(&path, &secrets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_secret() {
+        assert_eq!(
+            Secret::Env("TOKEN".to_string()),
+            "env:TOKEN".parse().unwrap()
+        );
+        assert_eq!(
+            Secret::File("gitlab".to_string()),
+            "secret:gitlab".parse().unwrap()
+        );
+        assert_eq!(
+            Secret::Value("a:b".to_string()),
+            "value:a:b".parse().unwrap()
+        );
+        assert!("TOKEN".parse::<Secret>().is_err());
+        assert!("env:".parse::<Secret>().is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let value = || Secret::Value("t0ken".to_string());
+        let header = Auth {
+            headers: vec![("X-Client".to_string(), value())]
+                .into_iter()
+                .collect(),
+            ..Auth::default()
+        };
+        assert!(header.validate().is_ok());
+        let basic = Auth {
+            basic: Some(Basic {
+                username: "ann".to_string(),
+                password: value(),
+            }),
+            ..Auth::default()
+        };
+        assert!(basic.validate().is_err());
+        let bearer = Auth {
+            bearer: Some(value()),
+            ..Auth::default()
+        };
+        assert!(bearer.validate().is_err());
+        let cookie = Auth {
+            cookies: vec![("session".to_string(), value())].into_iter().collect(),
+            ..Auth::default()
+        };
+        assert!(cookie.validate().is_err());
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq("t0ken", "t0ken"));
+        assert!(!constant_time_eq("t0ken", "t0kem"));
+        assert!(!constant_time_eq("t0ken", "t0ke"));
+    }
+
+    #[test]
+    fn test_headers() {
+        let var = "RSS_UPDATE_TEST_AUTH_TOKEN";
+        std::env::set_var(var, "t0ken");
+        let secrets = vec![("pass".to_string(), "s3cret".to_string())]
+            .into_iter()
+            .collect();
+        let auth = Auth {
+            basic: Some(Basic {
+                username: "ann".to_string(),
+                password: Secret::File("pass".to_string()),
+            }),
+            bearer: Some(Secret::Env(var.to_string())),
+            headers: vec![("Private-Token".to_string(), Secret::Env(var.to_string()))]
+                .into_iter()
+                .collect(),
+            cookies: vec![
+                ("a".to_string(), Secret::Value("1".to_string())),
+                ("b".to_string(), Secret::File("pass".to_string())),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        assert_eq!(
+            vec![
+                (
+                    "Authorization".to_string(),
+                    "Basic YW5uOnMzY3JldA==".to_string()
+                ),
+                ("Authorization".to_string(), "Bearer t0ken".to_string()),
+                ("Private-Token".to_string(), "t0ken".to_string()),
+                ("Cookie".to_string(), "a=1; b=s3cret".to_string()),
+            ],
+            auth.headers(&secrets).unwrap()
+        );
+        assert_eq!(
+            "basic as ann; bearer; headers Private-Token; cookies a, b",
+            auth.describe()
+        );
+        assert!(auth.headers(&Secrets::new()).is_err());
+    }
+
+    #[test]
+    fn test_secrets_file() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(FILE).to_string_lossy().into_owned();
+        assert!(_get(&path).unwrap().is_empty());
+        let secrets = vec![("k".to_string(), "v".to_string())]
+            .into_iter()
+            .collect();
+        _replace(&path, &secrets).unwrap();
+        assert_eq!(secrets, _get(&path).unwrap());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+            assert!(_get(&path).is_err());
+        }
+        // A file that cannot be looked up is not taken for no secrets.
+        let unreachable = dir.path().join(FILE).join(FILE);
+        assert!(_get(&unreachable.to_string_lossy()).is_err());
+    }
+}
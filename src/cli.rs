@@ -0,0 +1,1155 @@
+use crate::auth::{self, Auth, Secret};
+use crate::error::{Error, Result};
+use crate::feed_store::{FeedStore, FileStore};
+use crate::fetcher::HttpFetcher;
+use crate::network::Network;
+use crate::{
+    config, digest, display, doctor, download, export, feeds, health, notify, onboarding, opml,
+    parse_user_date, posts, readlist, render, repair, rules, search, serve, settings, starred,
+    terminal,
+};
+use chrono::Utc;
+use clap::{App, AppSettings, Arg, ArgMatches};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+
+// App level cli constants
+const APP: &str = "rss-update";
+const VERSION: &str = "0.1";
+const ABOUT: &str = "To track and fetch updates on rss feeds.";
+
+// Cli constants for action: generate pretty read list.
+const UNREAD: &str = "unread";
+const UNREAD_ABOUT: &str = "Display contents of read list on terminal.";
+
+// Cli constants for action: add new source
+const ADD: &str = "add";
+const ADD_ABOUT: &str = "Add new feed source to track.";
+
+// Cli constants for action: setup
+const SETUP: &str = "setup";
+const SETUP_ABOUT: &str =
+    "Create data files, optionally importing OPML or walking through feeds and settings.";
+
+// Cli constants for action: tracking feeds
+const TRACKING: &str = "tracking";
+const TRACKING_ABOUT: &str =
+    "Lists feeds that are currently being tracked along with its metadata.";
+
+// Cli constants for action: remove
+const REMOVE: &str = "remove";
+const REMOVE_ABOUT: &str = "to remove feed from tracking";
+
+// Cli constants for action: read
+const READ: &str = "read";
+const READ_ABOUT: &str = "to mark post as read.";
+
+// Cli constants for action: star
+const STAR: &str = "star";
+const STAR_ABOUT: &str = "Star posts to keep them around after they are read.";
+const UNSTAR: &str = "unstar";
+const UNSTAR_ABOUT: &str = "Remove star from posts.";
+const STARRED: &str = "starred";
+const STARRED_ABOUT: &str = "List starred posts, read or not.";
+
+// Cli constants for action: show
+const SHOW: &str = "show";
+const SHOW_ABOUT: &str = "Show content of a post, paged through $PAGER.";
+const SHOW_DEFAULT_WIDTH: usize = 80;
+
+// Cli constants for action: search
+const SEARCH: &str = "search";
+const SEARCH_ABOUT: &str = "Search stored posts, both read and unread.";
+const SEARCH_DEFAULT_LIMIT: usize = 20;
+
+// Cli constants for action: rules
+const RULES: &str = "rules";
+const RULES_ABOUT: &str = "Manage rules to skip, mark read or highlight matching posts.";
+const RULES_LIST: &str = "list";
+const RULES_LIST_ABOUT: &str = "Lists configured rules.";
+const RULES_ADD: &str = "add";
+const RULES_ADD_ABOUT: &str = "Add new rule.";
+const RULES_REMOVE: &str = "remove";
+const RULES_REMOVE_ABOUT: &str = "Remove rule by its index in list.";
+const RULES_TEST: &str = "test";
+const RULES_TEST_ABOUT: &str = "Preview posts in read list that a rule would match.";
+
+// Cli constants for action: tag
+const TAG: &str = "tag";
+const TAG_ABOUT: &str = "Add tags to tracked feed.";
+
+// Cli constants for action: untag
+const UNTAG: &str = "untag";
+const UNTAG_ABOUT: &str = "Remove tags from tracked feed.";
+
+// Cli constants for action: fetch
+const FETCH: &str = "fetch";
+const FETCH_ABOUT: &str = "Fetch new posts of tracked feeds. Same as running without subcommand.";
+
+// Cli constants for action: auth
+const AUTH: &str = "auth";
+const AUTH_ABOUT: &str =
+    "Set credentials of a private feed, referring to secrets kept outside config.";
+const SECRET: &str = "secret";
+const SECRET_ABOUT: &str = "Store a secret read from stdin in the secrets file only you can read.";
+
+// Cli constants for action: network
+const NETWORK: &str = "network";
+const NETWORK_ABOUT: &str =
+    "Set proxies and certificates feeds are requested with, for all feeds or one of them.";
+
+// Cli constants for action: health
+const HEALTH: &str = "health";
+const HEALTH_ABOUT: &str = "Report dead, erroring and dormant feeds with suggested actions.";
+
+// Cli constants for action: edit
+const EDIT: &str = "edit";
+const EDIT_ABOUT: &str = "Edit url, name, tracking date or status of tracked feed.";
+
+// Cli constants for action: digest
+const DIGEST: &str = "digest";
+const DIGEST_ABOUT: &str = "Email unread posts as a digest, as set up in settings.";
+
+// Cli constants for action: download
+const DOWNLOAD: &str = "download";
+const DOWNLOAD_ABOUT: &str = "Download enclosures of unread posts, like podcast episodes.";
+
+// Cli constants for action: export
+const EXPORT: &str = "export";
+const EXPORT_ABOUT: &str = "Write unread or recent posts of tracked feeds as an atom or rss feed.";
+
+// Cli constants for action: render
+const RENDER: &str = "render";
+const RENDER_ABOUT: &str = "Generate a static html page of recent posts, grouped by day and feed.";
+const RENDER_DEFAULT_DAYS: i64 = 7;
+
+// Cli constants for action: serve
+const SERVE: &str = "serve";
+const SERVE_ABOUT: &str = "Serve a json api and web ui to manage feeds and read posts.";
+
+// Cli constants for action: doctor
+const DOCTOR: &str = "doctor";
+const DOCTOR_ABOUT: &str = "Check version, permissions and integrity of data files.";
+
+// Cli constants for action: repair
+const REPAIR: &str = "repair";
+const REPAIR_ABOUT: &str =
+    "Salvage valid entries of damaged data files, keeping damaged ones as backup.";
+
+fn rule_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::from_usage("--field [FIELD] 'part of post to match'")
+            .possible_values(&["title", "author", "category", "link"])
+            .default_value("title"),
+        Arg::from_usage("--pattern [PATTERN] 'case insensitive substring to match'").required(true),
+        Arg::from_usage("--regex 'treat pattern as regular expression'"),
+        Arg::from_usage("--feed [FEED] 'only match posts of this feed'"),
+    ]
+}
+
+fn tag_arg(about: &'static str) -> Arg<'static, 'static> {
+    Arg::with_name("tag")
+        .long("tag")
+        .value_name("TAG")
+        .help(about)
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+}
+
+fn post_arg(about: &'static str) -> Arg<'static, 'static> {
+    Arg::with_name("post")
+        .long("post")
+        .value_name("URL")
+        .help(about)
+        .required(true)
+        .multiple(true)
+        .number_of_values(1)
+}
+
+fn dry_run_arg() -> Arg<'static, 'static> {
+    Arg::from_usage("--dry-run 'show what fetching would change without writing anything'")
+}
+
+fn parse_args() -> ArgMatches<'static> {
+    App::new(APP)
+        .version(VERSION)
+        .about(ABOUT)
+        .arg(dry_run_arg())
+        .subcommand(
+            App::new(UNREAD)
+                .about(UNREAD_ABOUT)
+                .arg(tag_arg("only show feeds with this tag")),
+        )
+        .subcommand(
+            App::new(ADD)
+                .about(ADD_ABOUT)
+                .arg(Arg::from_usage(
+                    "--from [DATE] 'date to start tracking in YYYY-MM-DD (remember to pad with 0)'",
+                ))
+                .arg(Arg::from_usage("--feed [FEED] 'rss feed to track'").required(true))
+                .arg(Arg::from_usage(
+                    "--name [NAME] 'name to display instead of feed url'",
+                ))
+                .arg(Arg::from_usage(
+                    "--full-content 'fetch linked page of new posts and keep its article'",
+                ))
+                .arg(tag_arg("tag to group feed under")),
+        )
+        .subcommand(
+            App::new(SETUP)
+                .about(SETUP_ABOUT)
+                .arg(Arg::from_usage(
+                    "--opml [FILE] 'import feeds exported by another reader, folders become tags'",
+                ))
+                .arg(Arg::from_usage(
+                    "-i, --interactive 'ask for feeds to import or add, and settings'",
+                )),
+        )
+        .subcommand(
+            App::new(TRACKING)
+                .about(TRACKING_ABOUT)
+                .arg(tag_arg("only list feeds with this tag")),
+        )
+        .subcommand(App::new(REMOVE).about(REMOVE_ABOUT).arg(
+            Arg::from_usage("--feed [FEED] `rss feed to remove from tracking.`").required(true),
+        ))
+        .subcommand(
+            App::new(READ)
+                .about(READ_ABOUT)
+                .arg(
+                    Arg::from_usage("--post [URL] `post url to mark as read.`")
+                        .required_unless("all"),
+                )
+                .arg(Arg::from_usage("--all 'mark all posts as read'").conflicts_with("post"))
+                .arg(tag_arg("with --all, only mark posts of feeds with this tag").requires("all")),
+        )
+        .subcommand(
+            App::new(STAR)
+                .about(STAR_ABOUT)
+                .arg(post_arg("unread or stored post to star")),
+        )
+        .subcommand(
+            App::new(UNSTAR)
+                .about(UNSTAR_ABOUT)
+                .arg(post_arg("starred post to unstar")),
+        )
+        .subcommand(
+            App::new(STARRED)
+                .about(STARRED_ABOUT)
+                .arg(tag_arg("only list feeds with this tag")),
+        )
+        .subcommand(
+            App::new(SHOW)
+                .about(SHOW_ABOUT)
+                .arg(Arg::from_usage("<POST> 'id listed by unread, starred or search, or url of post to show'"))
+                .arg(Arg::from_usage(
+                    "--width [N] 'columns to wrap text at, defaults to terminal width up to 80'",
+                ))
+                .arg(Arg::from_usage(
+                    "--read 'mark post as read after showing it'",
+                )),
+        )
+        .subcommand(
+            App::new(SEARCH)
+                .about(SEARCH_ABOUT)
+                .arg(Arg::from_usage(
+                    "<QUERY>... 'words, \"phrases\" and feed:, author:, before:, after: filters'",
+                ))
+                .arg(Arg::from_usage(
+                    "--limit [N] 'maximum number of results to show'",
+                )),
+        )
+        .subcommand(
+            App::new(RULES)
+                .about(RULES_ABOUT)
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(App::new(RULES_LIST).about(RULES_LIST_ABOUT))
+                .subcommand(
+                    App::new(RULES_ADD)
+                        .about(RULES_ADD_ABOUT)
+                        .args(&rule_args())
+                        .arg(
+                            Arg::from_usage("--action [ACTION] 'what to do with matching posts'")
+                                .possible_values(&["skip", "mark_read", "highlight"])
+                                .required(true),
+                        ),
+                )
+                .subcommand(App::new(RULES_REMOVE).about(RULES_REMOVE_ABOUT).arg(
+                    Arg::from_usage("--index [INDEX] 'index of rule to remove'").required(true),
+                ))
+                .subcommand(
+                    App::new(RULES_TEST)
+                        .about(RULES_TEST_ABOUT)
+                        .args(&rule_args()),
+                ),
+        )
+        .subcommand(
+            App::new(TAG)
+                .about(TAG_ABOUT)
+                .arg(Arg::from_usage("--feed [FEED] 'tracked feed to tag'").required(true))
+                .arg(tag_arg("tag to add").required(true)),
+        )
+        .subcommand(
+            App::new(UNTAG)
+                .about(UNTAG_ABOUT)
+                .arg(Arg::from_usage("--feed [FEED] 'tracked feed to untag'").required(true))
+                .arg(tag_arg("tag to remove").required(true)),
+        )
+        .subcommand(
+            App::new(FETCH)
+                .about(FETCH_ABOUT)
+                .arg(tag_arg("only fetch feeds with this tag"))
+                .arg(
+                    Arg::with_name("feed")
+                        .long("feed")
+                        .value_name("FEED")
+                        .help("only fetch this feed, even if disabled or dead")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .arg(Arg::from_usage(
+                    "--failed 'only fetch feeds which failed last time'",
+                ))
+                .arg(dry_run_arg()),
+        )
+        .subcommand(
+            App::new(EDIT)
+                .about(EDIT_ABOUT)
+                .arg(Arg::from_usage("--feed [FEED] 'tracked feed to edit'").required(true))
+                .arg(Arg::from_usage(
+                    "--url [URL] 'new feed url, unread posts are moved over'",
+                ))
+                .arg(Arg::from_usage(
+                    "--name [NAME] 'name to display instead of feed url, empty to clear'",
+                ))
+                .arg(Arg::from_usage(
+                    "--from [DATE] 'fetch posts after this date in YYYY-MM-DD on next run'",
+                ))
+                .arg(Arg::from_usage("--disable 'stop fetching feed'").conflicts_with("enable"))
+                .arg(Arg::from_usage("--enable 'resume fetching feed'"))
+                .arg(
+                    Arg::from_usage("--full-content 'fetch linked page of new posts'")
+                        .conflicts_with("no-full-content"),
+                )
+                .arg(Arg::from_usage(
+                    "--no-full-content 'only keep what the feed carries'",
+                )),
+        )
+        .subcommand(
+            App::new(AUTH)
+                .about(AUTH_ABOUT)
+                .arg(Arg::from_usage("--feed [FEED] 'tracked feed to set credentials of'").required(true))
+                .arg(
+                    Arg::from_usage("--basic [USER] 'user for basic auth, with --password'")
+                        .requires("password"),
+                )
+                .arg(Arg::from_usage(
+                    "--password [SECRET] 'password for basic auth, as env:VAR or secret:KEY'",
+                ))
+                .arg(Arg::from_usage(
+                    "--bearer [SECRET] 'bearer token, as env:VAR or secret:KEY'",
+                ))
+                .arg(
+                    Arg::from_usage("--header [NAME=SECRET] 'header to send, value as env:VAR, secret:KEY or value:TEXT'")
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::from_usage("--cookie [NAME=SECRET] 'cookie to send, value as env:VAR or secret:KEY'")
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::from_usage("--clear 'remove credentials of feed'")
+                        .conflicts_with_all(&["basic", "bearer", "header", "cookie"]),
+                ),
+        )
+        .subcommand(
+            App::new(SECRET)
+                .about(SECRET_ABOUT)
+                .arg(Arg::from_usage("--key [KEY] 'key to refer to secret by'").required(true))
+                .arg(Arg::from_usage("--remove 'remove secret instead'")),
+        )
+        .subcommand(
+            App::new(NETWORK)
+                .about(NETWORK_ABOUT)
+                .arg(Arg::from_usage(
+                    "--feed [FEED] 'tracked feed to set network of, over settings for all feeds'",
+                ))
+                .arg(Arg::from_usage(
+                    "--proxy [URL] 'proxy for all requests, http://, https:// or socks5://'",
+                ))
+                .arg(Arg::from_usage("--http-proxy [URL] 'proxy for http requests'"))
+                .arg(Arg::from_usage("--https-proxy [URL] 'proxy for https requests'"))
+                .arg(Arg::from_usage(
+                    "--no-proxy [HOSTS] 'comma separated hosts to request without proxy'",
+                ))
+                .arg(
+                    Arg::from_usage("--ca-bundle [PATH] 'pem file of root certificates to trust'")
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::from_usage(
+                        "--insecure-host [HOST] 'host whose invalid certificates are accepted'",
+                    )
+                    .multiple(true)
+                    .number_of_values(1),
+                )
+                .arg(
+                    Arg::from_usage("--clear 'remove network settings'").conflicts_with_all(&[
+                        "proxy",
+                        "http-proxy",
+                        "https-proxy",
+                        "no-proxy",
+                        "ca-bundle",
+                        "insecure-host",
+                    ]),
+                ),
+        )
+        .subcommand(
+            App::new(HEALTH)
+                .about(HEALTH_ABOUT)
+                .arg(Arg::from_usage("--all 'also list healthy feeds'")),
+        )
+        .subcommand(
+            App::new(DIGEST)
+                .about(DIGEST_ABOUT)
+                .arg(tag_arg("only include feeds with this tag"))
+                .arg(
+                    Arg::from_usage("--mark-read 'mark posts included in the digest as read, once sent'")
+                        .conflicts_with("print"),
+                )
+                .arg(Arg::from_usage(
+                    "--print 'print the email instead of sending it'",
+                )),
+        )
+        .subcommand(
+            App::new(DOWNLOAD)
+                .about(DOWNLOAD_ABOUT)
+                .arg(Arg::from_usage(
+                    "--dir [DIR] 'directory to save to, instead of the one in settings'",
+                ))
+                .arg(Arg::from_usage(
+                    "--template [TEMPLATE] 'path of files, with {{name}}, {{title}}, {{date}}, {{filename}} and {{ext}}'",
+                ))
+                .arg(Arg::from_usage(
+                    "--concurrency [N] 'downloads to run at once'",
+                ))
+                .arg(tag_arg("only download enclosures of feeds with this tag")),
+        )
+        .subcommand(
+            App::new(EXPORT)
+                .about(EXPORT_ABOUT)
+                .arg(
+                    Arg::from_usage("--atom [FILE] 'write atom 1.0 feed to file'")
+                        .required_unless("rss")
+                        .conflicts_with("rss"),
+                )
+                .arg(Arg::from_usage("--rss [FILE] 'write rss 2.0 feed to file'"))
+                .arg(Arg::from_usage(
+                    "--since [DATE] 'export posts published since date in YYYY-MM-DD, read or not'",
+                ))
+                .arg(tag_arg("only export feeds with this tag")),
+        )
+        .subcommand(
+            App::new(RENDER)
+                .about(RENDER_ABOUT)
+                .arg(
+                    Arg::from_usage("--html [DIR] 'directory to write index.html to'")
+                        .required(true),
+                )
+                .arg(Arg::from_usage(
+                    "--days [N] 'include posts published in the last N days'",
+                ))
+                .arg(tag_arg("only include feeds with this tag")),
+        )
+        .subcommand(
+            App::new(SERVE).about(SERVE_ABOUT).arg(
+                Arg::from_usage("--listen [ADDR] 'address to listen on'")
+                    .default_value("127.0.0.1:8080"),
+            ),
+        )
+        .subcommand(App::new(DOCTOR).about(DOCTOR_ABOUT))
+        .subcommand(App::new(REPAIR).about(REPAIR_ABOUT))
+        .get_matches()
+}
+
+fn tags(args: &ArgMatches<'_>) -> Vec<String> {
+    args.values_of("tag")
+        .map(|tags| tags.map(String::from).collect())
+        .unwrap_or_default()
+}
+
+fn display_readlist(feeds: readlist::ReadList) -> Result<()> {
+    let highlighted = rules::highlighted(&settings::get()?.rules, &feeds, &posts::get()?)?;
+    let starred = starred::links(&starred::get()?);
+    let names = config::names(config::get()?);
+    display::display_feeds(feeds, &highlighted, &starred, &names, &posts::get()?)
+}
+
+/// Restricts readlist to feeds carrying any of the tags, if tags are given.
+fn filter_by_tags(readlist: readlist::ReadList, tags: &[String]) -> Result<readlist::ReadList> {
+    if tags.is_empty() {
+        return Ok(readlist);
+    }
+    let feeds = config::feeds_with_tags(&config::get()?, tags);
+    Ok(readlist::filter(readlist, &feeds))
+}
+
+fn unread(args: &ArgMatches<'_>) -> Result<()> {
+    display_readlist(filter_by_tags(readlist::unread()?, &tags(args))?)
+}
+
+fn add_feed(args: &ArgMatches<'_>) -> Result<()> {
+    let tracking_date = match args.value_of("from") {
+        Some(d) => parse_user_date(d)?,
+        None => Utc::now(),
+    };
+    config::update(config::Config {
+        feed: args.value_of("feed").unwrap().to_string(),
+        updated: Some(tracking_date),
+        tags: tags(args),
+        name: args.value_of("name").map(String::from),
+        full_content: args.is_present("full-content"),
+        ..config::Config::default()
+    })?;
+    Ok(())
+}
+
+fn setup(args: &ArgMatches<'_>) -> Result<()> {
+    let settings = {
+        let _lock = FileStore::default().lock()?;
+        config::setup()?;
+        readlist::setup()?;
+        posts::setup()?;
+        settings::setup()?;
+        health::setup()?;
+        starred::setup()?;
+        if let Some(path) = args.value_of("opml") {
+            import_opml(path)?;
+        }
+        settings::get()?
+    };
+    if args.is_present("interactive") {
+        let stdin = io::stdin();
+        let onboarding =
+            onboarding::ask(&mut stdin.lock(), &mut io::stdout(), settings, Utc::now())?;
+        let _lock = FileStore::default().lock()?;
+        if let Some(path) = onboarding.opml {
+            import_opml(&path)?;
+        }
+        config::add_all(onboarding.feeds)?;
+        settings::replace(onboarding.settings)?;
+    }
+    Ok(())
+}
+
+/// Tracks feeds listed in OPML file from now on.
+fn import_opml(path: &str) -> Result<()> {
+    let now = Utc::now();
+    let configs = opml::read(path)?
+        .into_iter()
+        .map(|outline| config::Config {
+            feed: outline.feed,
+            updated: Some(now),
+            tags: outline.tags,
+            name: outline.name,
+            ..config::Config::default()
+        })
+        .collect();
+    config::add_all(configs)?;
+    Ok(())
+}
+
+fn digest(args: &ArgMatches<'_>) -> Result<()> {
+    let (settings, readlist, store, configs) = {
+        let _lock = FileStore::default().lock()?;
+        (
+            settings::get()?.digest.ok_or_else(|| {
+                Error::input(
+                    "digest is not set up, add `digest` with `from` and `to` to settings.json",
+                )
+            })?,
+            filter_by_tags(readlist::unread()?, &tags(args))?,
+            posts::get()?,
+            config::get()?,
+        )
+    };
+    if readlist.is_empty() {
+        println!("no unread posts, digest not sent.");
+        return Ok(());
+    }
+    let included = readlist.values().flatten().cloned().collect::<HashSet<_>>();
+    let sections = digest::sections(readlist, &store, &configs);
+    let message = digest::message(&settings, &sections, Utc::now())?;
+    if args.is_present("print") {
+        io::stdout().write_all(&message.formatted())?;
+    } else {
+        digest::send(&settings.mailer, &message)?;
+        println!("digest of {} posts sent.", included.len());
+    }
+    if args.is_present("mark-read") {
+        let _lock = FileStore::default().lock()?;
+        readlist::mark_posts_read(&included)?;
+    }
+    Ok(())
+}
+
+async fn download_enclosures(args: &ArgMatches<'_>) -> Result<()> {
+    // Data files are only read, so downloads do not keep fetches waiting.
+    let (readlist, store, configs, settings) = {
+        let _lock = FileStore::default().lock()?;
+        (
+            filter_by_tags(readlist::unread()?, &tags(args))?,
+            posts::get()?,
+            config::get()?,
+            settings::get()?,
+        )
+    };
+    let network = settings.network;
+    let settings = settings.download;
+    let dir = args
+        .value_of("dir")
+        .map(String::from)
+        .or(settings.dir)
+        .unwrap_or_else(download::default_dir);
+    let template = args.value_of("template").unwrap_or(&settings.template);
+    let concurrency = match args.value_of("concurrency") {
+        Some(n) => n
+            .parse()
+            .ok()
+            .filter(|n| *n > 0)
+            .ok_or_else(|| Error::input(format!("invalid concurrency: {}", n)))?,
+        None => settings.concurrency,
+    };
+    let jobs = download::jobs(
+        &readlist,
+        &store,
+        &configs,
+        &network,
+        Path::new(&dir),
+        template,
+    );
+    let clients = download::Clients::default();
+    let outcomes = download::download_all(&clients, jobs, concurrency).await;
+    display::display_downloads(&outcomes)?;
+    match outcomes.into_iter().find_map(|(_, outcome)| outcome.err()) {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+fn export(args: &ArgMatches<'_>) -> Result<()> {
+    let (format, path) = match args.value_of("atom") {
+        Some(path) => (export::Format::Atom, path),
+        None => (export::Format::Rss, args.value_of("rss").unwrap()),
+    };
+    let configs = config::get()?;
+    let stored = posts::get()?;
+    let mut posts = match args.value_of("since") {
+        Some(date) => export::since(&stored, parse_user_date(date)?),
+        None => export::unread(&readlist::unread()?, &stored),
+    };
+    let tags = tags(args);
+    if !tags.is_empty() {
+        let feeds = config::feeds_with_tags(&configs, &tags);
+        posts.retain(|p| feeds.contains(&p.feed));
+    }
+    let document = export::document(format, &posts, &configs, Utc::now());
+    fs::write(path, document).map_err(|e| Error::io(path, e))?;
+    println!("exported {} posts to {}.", posts.len(), path);
+    Ok(())
+}
+
+fn render(args: &ArgMatches<'_>) -> Result<()> {
+    let dir = args.value_of("html").unwrap();
+    let days = match args.value_of("days") {
+        Some(days) => days
+            .parse()
+            .map_err(|_| Error::input(format!("invalid number of days: {}", days)))?,
+        None => RENDER_DEFAULT_DAYS,
+    };
+    let configs = config::get()?;
+    let mut stored = posts::get()?;
+    let tags = tags(args);
+    if !tags.is_empty() {
+        let feeds = config::feeds_with_tags(&configs, &tags);
+        stored.retain(|_, p| feeds.contains(&p.feed));
+    }
+    let unread = readlist::unread()?.into_values().flatten().collect();
+    let now = Utc::now();
+    let days = render::days(
+        &stored,
+        &unread,
+        &configs,
+        now - chrono::Duration::days(days),
+    );
+    fs::create_dir_all(dir).map_err(|e| Error::io(dir, e))?;
+    let path = Path::new(dir).join("index.html");
+    let path = path.to_string_lossy();
+    fs::write(path.as_ref(), render::page(&days, now)).map_err(|e| Error::io(&path, e))?;
+    println!("rendered {} days of posts to {}.", days.len(), path);
+    Ok(())
+}
+
+fn doctor() -> Result<()> {
+    display::display_checks(doctor::checks())
+}
+
+fn repair() -> Result<()> {
+    display::display_repairs(repair::repair())
+}
+
+fn tracking(args: &ArgMatches<'_>) -> Result<()> {
+    let tags = tags(args);
+    let configs = config::get()?
+        .into_iter()
+        .filter(|c| c.has_any_tag(&tags))
+        .collect();
+    display::display_configs(configs)
+}
+
+fn remove_feed(args: &ArgMatches<'_>) -> Result<()> {
+    let feed = args.value_of("feed").unwrap().to_string();
+    config::remove(&feed)?;
+    Ok(())
+}
+
+fn mark_read(args: &ArgMatches<'_>) -> Result<()> {
+    if args.is_present("all") {
+        let tags = tags(args);
+        if tags.is_empty() {
+            readlist::mark_feeds_read(None)?;
+        } else {
+            let feeds = config::feeds_with_tags(&config::get()?, &tags);
+            readlist::mark_feeds_read(Some(&feeds))?;
+        }
+        return Ok(());
+    }
+    let post = args.value_of("post").unwrap().to_string();
+    readlist::mark_read(&post)?;
+    Ok(())
+}
+
+fn posts_of(args: &ArgMatches<'_>) -> Vec<String> {
+    args.values_of("post")
+        .map(|v| v.map(String::from).collect())
+        .unwrap_or_default()
+}
+
+fn star(args: &ArgMatches<'_>) -> Result<()> {
+    starred::star(&posts_of(args))?;
+    Ok(())
+}
+
+fn unstar(args: &ArgMatches<'_>) -> Result<()> {
+    starred::unstar(&posts_of(args))?;
+    Ok(())
+}
+
+fn list_starred(args: &ArgMatches<'_>) -> Result<()> {
+    let starred = filter_by_tags(starred::get()?, &tags(args))?;
+    display::display_starred(starred, &config::names(config::get()?))
+}
+
+fn show(args: &ArgMatches<'_>) -> Result<()> {
+    let link = args.value_of("POST").unwrap();
+    let width = match args.value_of("width") {
+        Some(width) => width
+            .parse()
+            .map_err(|_| Error::input(format!("invalid width: {}", width)))?,
+        None => env::var("COLUMNS")
+            .ok()
+            .and_then(|c| c.parse().ok())
+            .map_or(SHOW_DEFAULT_WIDTH, |c: usize| c.min(SHOW_DEFAULT_WIDTH)),
+    };
+    let (store, names) = {
+        let _lock = FileStore::default().lock()?;
+        (posts::get()?, config::names(config::get()?))
+    };
+    let post = posts::find(&store, link)?;
+    display::display_post(post, names.get(&post.feed).unwrap_or(&post.feed), width)?;
+    if args.is_present("read") {
+        let _lock = FileStore::default().lock()?;
+        readlist::mark_read(&post.link)?;
+    }
+    Ok(())
+}
+
+fn edit_feed(args: &ArgMatches<'_>) -> Result<()> {
+    let feed = args.value_of("feed").unwrap();
+    let url = args.value_of("url").map(String::from);
+    let disabled = if args.is_present("disable") {
+        Some(true)
+    } else if args.is_present("enable") {
+        Some(false)
+    } else {
+        None
+    };
+    let full_content = if args.is_present("full-content") {
+        Some(true)
+    } else if args.is_present("no-full-content") {
+        Some(false)
+    } else {
+        None
+    };
+    config::edit(
+        feed,
+        config::Edit {
+            url: url.clone(),
+            name: args.value_of("name").map(String::from),
+            updated: args.value_of("from").map(parse_user_date).transpose()?,
+            disabled,
+            full_content,
+            ..config::Edit::default()
+        },
+    )?;
+    if let Some(url) = url.filter(|u| u != feed) {
+        FileStore::default().rename_feed(feed, &url)?;
+    }
+    Ok(())
+}
+
+/// Parses `NAME=SECRET` pairs given for option.
+fn named_secrets(args: &ArgMatches<'_>, name: &str) -> Result<BTreeMap<String, Secret>> {
+    args.values_of(name)
+        .into_iter()
+        .flatten()
+        .map(|pair| match pair.split_once('=') {
+            Some((key, secret)) if !key.is_empty() => Ok((key.to_string(), secret.parse()?)),
+            _ => Err(Error::input(format!(
+                "invalid --{}: {}, expected NAME=SECRET",
+                name, pair
+            ))),
+        })
+        .collect()
+}
+
+fn set_auth(args: &ArgMatches<'_>) -> Result<()> {
+    let feed = args.value_of("feed").unwrap();
+    let auth = if args.is_present("clear") {
+        None
+    } else {
+        let basic = match (args.value_of("basic"), args.value_of("password")) {
+            (Some(username), Some(password)) => Some(auth::Basic {
+                username: username.to_string(),
+                password: password.parse()?,
+            }),
+            _ => None,
+        };
+        let auth = Auth {
+            basic,
+            bearer: args.value_of("bearer").map(str::parse).transpose()?,
+            headers: named_secrets(args, "header")?,
+            cookies: named_secrets(args, "cookie")?,
+        };
+        if auth.is_empty() {
+            return Err(Error::input(
+                "no credentials given, use --basic, --bearer, --header, --cookie or --clear",
+            ));
+        }
+        auth.validate()?;
+        Some(auth)
+    };
+    config::edit(
+        feed,
+        config::Edit {
+            auth: Some(auth),
+            ..config::Edit::default()
+        },
+    )?;
+    Ok(())
+}
+
+fn set_network(args: &ArgMatches<'_>) -> Result<()> {
+    let values = |name: &str| -> Vec<String> {
+        args.values_of(name)
+            .map(|v| v.map(String::from).collect())
+            .unwrap_or_default()
+    };
+    let network = Network {
+        proxy: args.value_of("proxy").map(String::from),
+        http_proxy: args.value_of("http-proxy").map(String::from),
+        https_proxy: args.value_of("https-proxy").map(String::from),
+        no_proxy: args.value_of("no-proxy").map(String::from),
+        ca_bundles: values("ca-bundle"),
+        insecure_hosts: values("insecure-host"),
+    };
+    if network.is_default() && !args.is_present("clear") {
+        return Err(Error::input(
+            "no network settings given, use --proxy, --http-proxy, --https-proxy, --no-proxy, --ca-bundle, --insecure-host or --clear",
+        ));
+    }
+    // Proxies and certificates are checked now rather than on next fetch.
+    network
+        .builder(false)?
+        .build()
+        .map_err(|e| Error::http("http client", e))?;
+    match args.value_of("feed") {
+        Some(feed) => {
+            config::edit(
+                feed,
+                config::Edit {
+                    network: Some(Some(network)),
+                    ..config::Edit::default()
+                },
+            )?;
+        }
+        None => {
+            let mut settings = settings::get()?;
+            settings.network = network;
+            settings::replace(settings)?;
+        }
+    }
+    Ok(())
+}
+
+fn manage_secret(args: &ArgMatches<'_>) -> Result<()> {
+    let key = args.value_of("key").unwrap();
+    if args.is_present("remove") {
+        let _lock = FileStore::default().lock()?;
+        return auth::remove_secret(key);
+    }
+    // Reading from stdin keeps secret out of shell history and process list.
+    if io::stdin().is_terminal() {
+        eprint!("secret for {}: ", key);
+    }
+    let mut value = String::new();
+    io::stdin().read_line(&mut value)?;
+    let value = value.trim_end_matches(['\r', '\n']);
+    if value.is_empty() {
+        return Err(Error::input("empty secret"));
+    }
+    {
+        let _lock = FileStore::default().lock()?;
+        auth::set_secret(key, value)?;
+    }
+    println!("stored secret {}, refer to it as secret:{}.", key, key);
+    Ok(())
+}
+
+fn health(args: &ArgMatches<'_>) -> Result<()> {
+    let settings = settings::get()?;
+    let dormant_after = chrono::Duration::days(settings.dormant_after_days);
+    let reports = health::report(config::get()?, &health::get()?, Utc::now(), dormant_after)
+        .into_iter()
+        .filter(|r| args.is_present("all") || r.state != health::State::Healthy)
+        .collect();
+    display::display_health(reports)
+}
+
+fn tag_feed(args: &ArgMatches<'_>) -> Result<()> {
+    config::tag(args.value_of("feed").unwrap(), &tags(args))?;
+    Ok(())
+}
+
+fn untag_feed(args: &ArgMatches<'_>) -> Result<()> {
+    config::untag(args.value_of("feed").unwrap(), &tags(args))?;
+    Ok(())
+}
+
+fn search(args: &ArgMatches<'_>) -> Result<()> {
+    let query = args
+        .values_of("QUERY")
+        .unwrap()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let limit = match args.value_of("limit") {
+        Some(limit) => limit
+            .parse()
+            .map_err(|_| Error::input(format!("invalid limit: {}", limit)))?,
+        None => SEARCH_DEFAULT_LIMIT,
+    };
+    let unread = readlist::unread()?.into_values().flatten().collect();
+    let store = posts::get()?;
+    let hits = search::search(
+        &store,
+        &search::_updated(&search::index_path(), &store)?,
+        &unread,
+        &search::parse_query(&query)?,
+        limit,
+    );
+    display::display_search_results(hits)
+}
+
+fn rule_from_args(args: &ArgMatches<'_>, action: rules::Action) -> Result<rules::Rule> {
+    Ok(rules::Rule {
+        feed: args.value_of("feed").map(String::from),
+        field: args
+            .value_of("field")
+            .unwrap()
+            .parse()
+            .map_err(Error::input)?,
+        pattern: args.value_of("pattern").unwrap().to_string(),
+        regex: args.is_present("regex"),
+        action,
+    })
+}
+
+fn manage_rules(args: &ArgMatches<'_>) -> Result<()> {
+    let mut settings = settings::get()?;
+    match args.subcommand() {
+        (RULES_ADD, Some(s_args)) => {
+            let action = s_args
+                .value_of("action")
+                .unwrap()
+                .parse()
+                .map_err(Error::input)?;
+            settings.rules.push(rule_from_args(s_args, action)?);
+            settings::replace(settings)?;
+        }
+        (RULES_REMOVE, Some(s_args)) => {
+            let index = s_args.value_of("index").unwrap();
+            let index = index
+                .parse::<usize>()
+                .ok()
+                .filter(|i| *i < settings.rules.len())
+                .ok_or_else(|| Error::input(format!("no rule at index: {}", index)))?;
+            settings.rules.remove(index);
+            settings::replace(settings)?;
+        }
+        (RULES_TEST, Some(s_args)) => {
+            let rule = rule_from_args(s_args, rules::Action::Highlight)?;
+            let store = posts::get()?;
+            let matched = rules::preview(&rule, &readlist::unread()?, &store)?;
+            display::display_feeds(
+                matched,
+                &HashSet::new(),
+                &HashSet::new(),
+                &HashMap::new(),
+                &store,
+            )?;
+        }
+        _ => display::display_rules(settings.rules)?,
+    }
+    Ok(())
+}
+
+fn selection(args: &ArgMatches<'_>) -> Result<config::Selection> {
+    let failed = if args.is_present("failed") {
+        let failed = health::get()?
+            .into_iter()
+            .filter(|(_, history)| history.consecutive_failures > 0)
+            .map(|(feed, _)| feed)
+            .collect();
+        Some(failed)
+    } else {
+        None
+    };
+    Ok(config::Selection {
+        feeds: args
+            .values_of("feed")
+            .map(|feeds| feeds.map(String::from).collect())
+            .unwrap_or_default(),
+        tags: tags(args),
+        failed,
+    })
+}
+
+async fn fetch_new_feeds(selection: &config::Selection, dry_run: bool) -> Result<()> {
+    let store = FileStore::default();
+    let fetcher = HttpFetcher::new()?;
+    if dry_run {
+        let changes = feeds::fetch_and_diff(&store, &fetcher, selection, Utc::now()).await?;
+        return display::display_changes(changes);
+    }
+    let fetched = feeds::fetch(&store, &fetcher, selection, Utc::now()).await?;
+    let mut shown = HashSet::new();
+    for change in &fetched.changes {
+        shown.insert(change.feed.clone());
+        if let Some(reason) = &change.error {
+            eprintln!(
+                "failed to fetch feed: {}, {}",
+                change.feed,
+                terminal::sanitize(reason)
+            );
+        }
+    }
+    for change in &fetched.changes {
+        let url = match &change.moved_to {
+            Some(url) => url,
+            None => continue,
+        };
+        match fetched
+            .unfollowed
+            .iter()
+            .find(|(feed, _)| *feed == change.feed)
+        {
+            Some((feed, e)) => eprintln!(
+                "not following move of feed: {}, {}",
+                feed,
+                terminal::sanitize(&e.to_string())
+            ),
+            None => {
+                println!(
+                    "feed: {} moved permanently to {}",
+                    change.feed,
+                    terminal::sanitize(url)
+                );
+                shown.insert(url.clone());
+            }
+        }
+    }
+    notify::notify_added(&fetched.changes).await?;
+    let readlist = store.unread()?;
+    if selection.is_empty() {
+        display_readlist(readlist)
+    } else {
+        display_readlist(readlist::filter(readlist, &shown))
+    }
+}
+
+/// Runs subcommand given on command line.
+pub async fn run() -> Result<()> {
+    let args = parse_args();
+    // Fetching locks data files only while storing what it fetched, serve
+    // for every request, and the rest waiting on network or user only while
+    // reading or writing data files, so that they can run along with others.
+    let _lock = match args.subcommand_name() {
+        None | Some(FETCH) | Some(SERVE) | Some(DOWNLOAD) | Some(SETUP) | Some(SHOW)
+        | Some(DIGEST) | Some(SECRET) => None,
+        Some(_) => Some(FileStore::default().lock()?),
+    };
+    match args.subcommand() {
+        (UNREAD, Some(s_args)) => unread(s_args),
+        (ADD, Some(s_args)) => add_feed(s_args),
+        (SETUP, Some(s_args)) => setup(s_args),
+        (TRACKING, Some(s_args)) => tracking(s_args),
+        (REMOVE, Some(s_args)) => remove_feed(s_args),
+        (READ, Some(s_args)) => mark_read(s_args),
+        (STAR, Some(s_args)) => star(s_args),
+        (UNSTAR, Some(s_args)) => unstar(s_args),
+        (STARRED, Some(s_args)) => list_starred(s_args),
+        (SHOW, Some(s_args)) => show(s_args),
+        (SEARCH, Some(s_args)) => search(s_args),
+        (RULES, Some(s_args)) => manage_rules(s_args),
+        (TAG, Some(s_args)) => tag_feed(s_args),
+        (UNTAG, Some(s_args)) => untag_feed(s_args),
+        (EDIT, Some(s_args)) => edit_feed(s_args),
+        (AUTH, Some(s_args)) => set_auth(s_args),
+        (SECRET, Some(s_args)) => manage_secret(s_args),
+        (NETWORK, Some(s_args)) => set_network(s_args),
+        (HEALTH, Some(s_args)) => health(s_args),
+        (DIGEST, Some(s_args)) => digest(s_args),
+        (DOWNLOAD, Some(s_args)) => download_enclosures(s_args).await,
+        (EXPORT, Some(s_args)) => export(s_args),
+        (RENDER, Some(s_args)) => render(s_args),
+        (SERVE, Some(s_args)) => serve::serve(s_args.value_of("listen").unwrap()).await,
+        (DOCTOR, Some(_)) => doctor(),
+        (REPAIR, Some(_)) => repair(),
+        (FETCH, Some(s_args)) => {
+            let selection = selection(s_args)?;
+            fetch_new_feeds(&selection, s_args.is_present("dry-run")).await
+        }
+        _ => {
+            let selection = config::Selection::default();
+            fetch_new_feeds(&selection, args.is_present("dry-run")).await
+        }
+    }
+}
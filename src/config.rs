@@ -1,33 +1,110 @@
+use crate::auth::Auth;
+use crate::error::{Error, Result};
+use crate::network::Network;
+use crate::store;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::error::Error;
-use std::fs;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
 
-pub(crate) type ConfigList = Vec<Config>;
-type Result<T> = std::result::Result<T, Box<dyn Error>>;
+pub type ConfigList = Vec<Config>;
+
+/// Upgrades of config format, oldest first.
+pub(crate) const MIGRATIONS: &[store::Migration] = &[store::envelope];
+
+/// Name of the file in data directory.
+pub(crate) const FILE: &str = "config.json";
 
 /// Returns path where config should reside.
-fn config_path() -> String {
-    let config_path = Path::new(&crate::base_dir()).join("config.json");
+pub(crate) fn config_path() -> String {
+    let config_path = Path::new(&crate::base_dir()).join(FILE);
     String::from(config_path.to_str().unwrap())
 }
 
 /// Feed Configuration used to track feed status.
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
-pub(crate) struct Config {
-    pub(crate) feed: String,
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Config {
+    pub feed: String,
+    pub updated: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub disabled: bool,
+    /// Consecutive fetches which found feed gone or missing.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub failures: u32,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dead: bool,
+    /// Fetch linked page of new posts and keep its main article.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub full_content: bool,
+    /// Credentials to request feed with, referring to secrets kept elsewhere.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<Auth>,
+    /// Proxies and certificates of feed, over those of settings.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<Network>,
+}
+
+fn is_zero(n: &u32) -> bool {
+    *n == 0
+}
+
+/// Which tracked feeds to fetch. Empty selection means all enabled feeds.
+#[derive(Debug, Default)]
+pub struct Selection {
+    /// Feeds named explicitly are fetched even if disabled or dead.
+    pub feeds: Vec<String>,
+    pub tags: Vec<String>,
+    /// Only fetch these feeds, which failed last time, dead ones included.
+    pub failed: Option<HashSet<String>>,
+}
+
+impl Selection {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.feeds.is_empty() && self.tags.is_empty() && self.failed.is_none()
+    }
+}
+
+/// Changes to a tracked feed. Fields left as `None` are not touched.
+#[derive(Debug, Default)]
+pub(crate) struct Edit {
+    pub(crate) url: Option<String>,
+    pub(crate) name: Option<String>,
     pub(crate) updated: Option<DateTime<Utc>>,
+    pub(crate) disabled: Option<bool>,
+    pub(crate) full_content: Option<bool>,
+    /// Credentials to set, `Some(None)` removing them.
+    pub(crate) auth: Option<Option<Auth>>,
+    /// Network settings to set, `Some(None)` removing them.
+    pub(crate) network: Option<Option<Network>>,
+}
+
+impl Config {
+    /// Checks if config carries any of the tags. No tags means no filtering.
+    pub(crate) fn has_any_tag(&self, tags: &[String]) -> bool {
+        tags.is_empty() || self.tags.iter().any(|t| tags.contains(t))
+    }
+
+    /// Name to show for feed, falling back to feed url.
+    pub(crate) fn display_name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.feed)
+    }
 }
 
-/// Reads configs from path and parse it as `ConfigList`.
+/// Reads configs from path and parse it as `ConfigList`. Config is created
+/// empty on first use.
 pub(crate) fn get() -> Result<ConfigList> {
-    _get(&config_path())
+    let path = config_path();
+    store::init_versioned(&path, MIGRATIONS, &ConfigList::new())?;
+    _get(&path)
 }
 
-fn _get(path: &str) -> Result<ConfigList> {
-    let config = fs::read_to_string(path)?;
-    Ok(serde_json::from_str(config.as_str())?)
+pub(crate) fn _get(path: &str) -> Result<ConfigList> {
+    store::read_versioned(path, MIGRATIONS)
 }
 
 /// Replaces config with the provided `ConfigList`
@@ -35,19 +112,16 @@ pub(crate) fn replace(configs: ConfigList) -> Result<ConfigList> {
     _replace(&config_path(), configs)
 }
 
-fn _replace(path: &str, configs: ConfigList) -> Result<ConfigList> {
-    let data = serde_json::to_string_pretty(&configs)?;
-    fs::write(path, data)?;
+pub(crate) fn _replace(path: &str, configs: ConfigList) -> Result<ConfigList> {
+    store::write_versioned(path, MIGRATIONS, &configs)?;
     Ok(configs)
 }
 
 pub(crate) fn setup() -> Result<()> {
-    let config_path = config_path();
-    if Path::new(&config_path).is_file() {
-        println!("config file already exists.");
-    } else {
+    if store::init_versioned(&config_path(), MIGRATIONS, &ConfigList::new())? {
         println!("creating config path.");
-        fs::write(config_path, "[]")?;
+    } else {
+        println!("config file already exists.");
     }
     Ok(())
 }
@@ -56,6 +130,11 @@ pub(crate) fn update(config: Config) -> Result<ConfigList> {
     replace(_update(get()?, config))
 }
 
+/// Adds feeds for tracking, skipping ones already tracked.
+pub(crate) fn add_all(configs: ConfigList) -> Result<ConfigList> {
+    replace(configs.into_iter().fold(get()?, _update))
+}
+
 fn _update(mut configs: ConfigList, config: Config) -> ConfigList {
     for c in &configs {
         if c.feed == config.feed {
@@ -76,11 +155,147 @@ pub(crate) fn remove(feed: &str) -> Result<ConfigList> {
     replace(configs)
 }
 
+/// Returns feeds carrying any of the tags.
+pub(crate) fn feeds_with_tags(configs: &[Config], tags: &[String]) -> HashSet<String> {
+    configs
+        .iter()
+        .filter(|c| c.has_any_tag(tags))
+        .map(|c| c.feed.clone())
+        .collect()
+}
+
+/// Points config of a feed to its new url.
+pub(crate) fn rename(configs: ConfigList, feed: &str, url: &str) -> Result<ConfigList> {
+    let edit = Edit {
+        url: Some(url.to_string()),
+        ..Edit::default()
+    };
+    _edit(configs, feed, edit)
+}
+
+/// Returns display names of feeds which have one, keyed by feed url.
+pub(crate) fn names(configs: ConfigList) -> HashMap<String, String> {
+    configs
+        .into_iter()
+        .filter_map(|c| Some(c.feed).zip(c.name))
+        .collect()
+}
+
+/// Returns configs to fetch for the selection.
+pub(crate) fn select(configs: &[Config], selection: &Selection) -> Result<ConfigList> {
+    for feed in &selection.feeds {
+        if !configs.iter().any(|c| c.feed == *feed) {
+            return Err(Error::input(format!("feed: {} is not being tracked", feed)));
+        }
+    }
+    Ok(configs
+        .iter()
+        .filter(|c| selection.feeds.is_empty() || selection.feeds.contains(&c.feed))
+        .filter(|c| c.has_any_tag(&selection.tags))
+        .filter(|c| match &selection.failed {
+            Some(failed) => failed.contains(&c.feed) && !c.disabled,
+            None => !selection.feeds.is_empty() || (!c.disabled && !c.dead),
+        })
+        .cloned()
+        .collect())
+}
+
+/// Takes what fetching changes, i.e. when feeds were fetched and whether
+/// they are gone, from the updated configs of matching feeds. The rest, e.g.
+/// names or tags edited while fetching, is left as is.
+pub(crate) fn merge(configs: ConfigList, updated: ConfigList) -> ConfigList {
+    configs
+        .into_iter()
+        .map(|mut c| {
+            if let Some(u) = updated.iter().find(|u| u.feed == c.feed) {
+                c.updated = u.updated;
+                c.failures = u.failures;
+                c.dead = u.dead;
+            }
+            c
+        })
+        .collect()
+}
+
+pub(crate) fn tag(feed: &str, tags: &[String]) -> Result<ConfigList> {
+    replace(_retag(get()?, feed, |existing| {
+        for tag in tags {
+            if !existing.contains(tag) {
+                existing.push(tag.clone());
+            }
+        }
+    })?)
+}
+
+pub(crate) fn untag(feed: &str, tags: &[String]) -> Result<ConfigList> {
+    replace(_retag(get()?, feed, |existing| {
+        existing.retain(|t| !tags.contains(t));
+    })?)
+}
+
+pub(crate) fn edit(feed: &str, edit: Edit) -> Result<ConfigList> {
+    replace(_edit(get()?, feed, edit)?)
+}
+
+fn _edit(mut configs: ConfigList, feed: &str, edit: Edit) -> Result<ConfigList> {
+    if let Some(url) = &edit.url {
+        if url != feed && configs.iter().any(|c| &c.feed == url) {
+            return Err(Error::input(format!(
+                "feed: {} is already being tracked",
+                url
+            )));
+        }
+    }
+    let config = configs
+        .iter_mut()
+        .find(|c| c.feed == feed)
+        .ok_or_else(|| Error::input(format!("feed: {} is not being tracked", feed)))?;
+    if let Some(url) = edit.url {
+        config.feed = url;
+    }
+    if let Some(name) = edit.name {
+        config.name = Some(name).filter(|n| !n.is_empty());
+    }
+    if let Some(updated) = edit.updated {
+        config.updated = Some(updated);
+    }
+    if let Some(disabled) = edit.disabled {
+        config.disabled = disabled;
+        if !disabled {
+            config.dead = false;
+            config.failures = 0;
+        }
+    }
+    if let Some(full_content) = edit.full_content {
+        config.full_content = full_content;
+    }
+    if let Some(auth) = edit.auth {
+        config.auth = auth.filter(|a| !a.is_empty());
+    }
+    if let Some(network) = edit.network {
+        config.network = network.filter(|n| !n.is_default());
+    }
+    Ok(configs)
+}
+
+fn _retag<F>(mut configs: ConfigList, feed: &str, change: F) -> Result<ConfigList>
+where
+    F: FnOnce(&mut Vec<String>),
+{
+    let config = configs
+        .iter_mut()
+        .find(|c| c.feed == feed)
+        .ok_or_else(|| Error::input(format!("feed: {} is not being tracked", feed)))?;
+    change(&mut config.tags);
+    Ok(configs)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::io::{Read, Write};
-    use tempfile::NamedTempFile;
+    use std::fs;
+    use std::io::Write;
+    use tempfile::{NamedTempFile, TempDir};
 
     fn remove_whitespaces(s: &str) -> String {
         s.chars().filter(|c| !c.is_whitespace()).collect()
@@ -91,9 +306,12 @@ mod tests {
         assert!(config_path().starts_with('/'));
     }
 
+    // Config written by 0.1.x, before it was versioned.
     #[test]
     fn test_get() {
-        let mut file = NamedTempFile::new().unwrap();
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("config.json");
+        let path = path.to_str().unwrap();
         let feed1 = "https://satylogin.medium.com/feed".to_string();
         let feed2 = "https://motw.rs/rss.xml".to_string();
         let now = chrono::Utc::now();
@@ -111,20 +329,28 @@ mod tests {
             now = now,
             feed2 = feed2
         );
-        writeln!(file, "{}", content).unwrap();
+        fs::write(path, &content).unwrap();
 
         let expected = vec![
             Config {
                 feed: feed1,
                 updated: Some(now),
+                ..Config::default()
             },
             Config {
                 feed: feed2,
                 updated: None,
+                ..Config::default()
             },
         ];
-        let output = _get(file.path().to_str().unwrap()).unwrap();
+        let output = _get(path).unwrap();
         assert_eq!(expected, output);
+        assert_eq!(
+            content,
+            fs::read_to_string(format!("{}.v0.bak", path)).unwrap()
+        );
+        assert_eq!(1, store::inspect::<ConfigList>(path, MIGRATIONS).unwrap());
+        assert_eq!(expected, _get(path).unwrap());
     }
 
     #[test]
@@ -163,7 +389,7 @@ mod tests {
     // if dir doesn't exists that operation would fail even before reaching here.
     #[test]
     fn test_replace() {
-        let mut file = NamedTempFile::new().unwrap();
+        let file = NamedTempFile::new().unwrap();
         let feed1 = "https://satylogin.medium.com/feed";
         let feed2 = "https://motw.rs/rss.xml";
         let now = chrono::Utc::now();
@@ -171,14 +397,18 @@ mod tests {
             Config {
                 feed: feed1.to_string(),
                 updated: Some(now),
+                ..Config::default()
             },
             Config {
                 feed: feed2.to_string(),
                 updated: None,
+                ..Config::default()
             },
         ];
         let expected = format!(
-            r#"[
+            r#"{{
+            "version": 1,
+            "data": [
             {{
                 "feed": "{feed1}",
                 "updated": "{now}"
@@ -187,15 +417,15 @@ mod tests {
                 "feed": "{feed2}",
                 "updated": null
             }}
-        ]"#,
+        ]}}"#,
             feed1 = feed1,
             now = now.to_rfc3339_opts(chrono::SecondsFormat::Nanos, true),
             feed2 = feed2
         );
         let output = _replace(file.path().to_str().unwrap(), config_list.clone()).unwrap();
         assert_eq!(config_list, output);
-        let mut buf = String::new();
-        file.read_to_string(&mut buf).unwrap();
+        // Data is moved in place, so file is read again by path.
+        let buf = fs::read_to_string(file.path()).unwrap();
         assert_eq!(remove_whitespaces(&expected), remove_whitespaces(&buf));
     }
 
@@ -205,15 +435,18 @@ mod tests {
             Config {
                 feed: "feed1".to_string(),
                 updated: None,
+                ..Config::default()
             },
             Config {
                 feed: "feed3".to_string(),
                 updated: None,
+                ..Config::default()
             },
         ];
         let config = Config {
             feed: "feed2".to_string(),
             updated: None,
+            ..Config::default()
         };
         let updated = _update(configs, config);
         let feeds = updated
@@ -232,15 +465,18 @@ mod tests {
             Config {
                 feed: "feed1".to_string(),
                 updated: None,
+                ..Config::default()
             },
             Config {
                 feed: "feed3".to_string(),
                 updated: None,
+                ..Config::default()
             },
         ];
         let config = Config {
             feed: "feed3".to_string(),
             updated: None,
+            ..Config::default()
         };
         let updated = _update(configs, config);
         let feeds = updated
@@ -252,4 +488,213 @@ mod tests {
             assert!(feeds.contains(&String::from(feed)));
         }
     }
+
+    #[test]
+    fn test_merge() {
+        let now = chrono::Utc::now();
+        let configs = vec![
+            Config {
+                feed: "feed1".to_string(),
+                ..Config::default()
+            },
+            Config {
+                feed: "feed2".to_string(),
+                name: Some("renamed".to_string()),
+                ..Config::default()
+            },
+        ];
+        let updated = vec![Config {
+            feed: "feed2".to_string(),
+            updated: Some(now),
+            failures: 3,
+            dead: true,
+            ..Config::default()
+        }];
+        let merged = merge(configs, updated);
+        assert_eq!(None, merged[0].updated);
+        assert_eq!(Some(now), merged[1].updated);
+        assert_eq!((3, true), (merged[1].failures, merged[1].dead));
+        assert_eq!(Some("renamed".to_string()), merged[1].name);
+    }
+
+    #[test]
+    fn test_retag() {
+        let configs = vec![Config {
+            feed: "feed1".to_string(),
+            tags: vec!["rust".to_string()],
+            ..Config::default()
+        }];
+        let configs = _retag(configs, "feed1", |tags| tags.push("work".to_string())).unwrap();
+        assert_eq!(
+            vec!["rust".to_string(), "work".to_string()],
+            configs[0].tags
+        );
+        assert!(_retag(configs, "feed2", |tags| tags.clear()).is_err());
+    }
+
+    #[test]
+    fn test_feeds_with_tags() {
+        let configs = vec![
+            Config {
+                feed: "feed1".to_string(),
+                tags: vec!["rust".to_string()],
+                ..Config::default()
+            },
+            Config {
+                feed: "feed2".to_string(),
+                tags: vec!["work".to_string()],
+                ..Config::default()
+            },
+            Config {
+                feed: "feed3".to_string(),
+                ..Config::default()
+            },
+        ];
+        let feeds = feeds_with_tags(&configs, &["rust".to_string(), "go".to_string()]);
+        assert_eq!(1, feeds.len());
+        assert!(feeds.contains("feed1"));
+        assert_eq!(3, feeds_with_tags(&configs, &[]).len());
+    }
+
+    #[test]
+    fn test_edit() {
+        let now = chrono::Utc::now();
+        let configs = vec![
+            Config {
+                feed: "feed1".to_string(),
+                name: Some("old".to_string()),
+                ..Config::default()
+            },
+            Config {
+                feed: "feed2".to_string(),
+                ..Config::default()
+            },
+        ];
+        let edit = Edit {
+            url: Some("feed3".to_string()),
+            name: Some("new".to_string()),
+            updated: Some(now),
+            disabled: Some(true),
+            full_content: Some(true),
+            auth: Some(Some(Auth {
+                bearer: Some(crate::auth::Secret::Env("TOKEN".to_string())),
+                ..Auth::default()
+            })),
+            network: Some(Some(Network {
+                proxy: Some("http://proxy:3128".to_string()),
+                ..Network::default()
+            })),
+        };
+        let configs = _edit(configs, "feed1", edit).unwrap();
+        let expected = Config {
+            feed: "feed3".to_string(),
+            updated: Some(now),
+            name: Some("new".to_string()),
+            disabled: true,
+            full_content: true,
+            auth: Some(Auth {
+                bearer: Some(crate::auth::Secret::Env("TOKEN".to_string())),
+                ..Auth::default()
+            }),
+            network: Some(Network {
+                proxy: Some("http://proxy:3128".to_string()),
+                ..Network::default()
+            }),
+            ..Config::default()
+        };
+        assert_eq!(expected, configs[0]);
+
+        let edit = Edit {
+            auth: Some(None),
+            network: Some(Some(Network::default())),
+            ..Edit::default()
+        };
+        let configs = _edit(configs, "feed3", edit).unwrap();
+        assert_eq!(None, configs[0].auth);
+        assert_eq!(None, configs[0].network);
+
+        let edit = Edit {
+            name: Some(String::new()),
+            ..Edit::default()
+        };
+        let configs = _edit(configs, "feed3", edit).unwrap();
+        assert_eq!(None, configs[0].name);
+        assert_eq!("feed3", configs[0].display_name());
+    }
+
+    #[test]
+    fn test_edit_invalid_feed() {
+        let configs = vec![
+            Config {
+                feed: "feed1".to_string(),
+                ..Config::default()
+            },
+            Config {
+                feed: "feed2".to_string(),
+                ..Config::default()
+            },
+        ];
+        assert!(_edit(configs.clone(), "feed3", Edit::default()).is_err());
+        let edit = Edit {
+            url: Some("feed2".to_string()),
+            ..Edit::default()
+        };
+        assert!(_edit(configs, "feed1", edit).is_err());
+    }
+
+    #[test]
+    fn test_select() {
+        let config = |feed: &str, tag: &str, disabled: bool, dead: bool| Config {
+            feed: feed.to_string(),
+            tags: vec![tag.to_string()],
+            disabled,
+            dead,
+            ..Config::default()
+        };
+        let configs = vec![
+            config("feed1", "rust", false, false),
+            config("feed2", "rust", true, false),
+            config("feed3", "rust", false, true),
+            config("feed4", "work", false, false),
+        ];
+        let feeds = |selection: Selection| {
+            select(&configs, &selection)
+                .unwrap()
+                .into_iter()
+                .map(|c| c.feed)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["feed1", "feed4"], feeds(Selection::default()));
+        assert_eq!(
+            vec!["feed1"],
+            feeds(Selection {
+                tags: vec!["rust".to_string()],
+                ..Selection::default()
+            })
+        );
+        assert_eq!(
+            vec!["feed2", "feed3"],
+            feeds(Selection {
+                feeds: vec!["feed2".to_string(), "feed3".to_string()],
+                ..Selection::default()
+            })
+        );
+        let failed = vec!["feed2", "feed3", "feed4"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        assert_eq!(
+            vec!["feed3"],
+            feeds(Selection {
+                tags: vec!["rust".to_string()],
+                failed: Some(failed),
+                ..Selection::default()
+            })
+        );
+        let selection = Selection {
+            feeds: vec!["feed5".to_string()],
+            ..Selection::default()
+        };
+        assert!(select(&configs, &selection).is_err());
+    }
 }
@@ -0,0 +1,343 @@
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::html::{escape_html, link, summary, title};
+use crate::posts::{Post, PostStore};
+use crate::readlist::ReadList;
+use chrono::{DateTime, Utc};
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SendmailTransport, SmtpTransport, Transport};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::time::SystemTime;
+
+/// How digest emails are delivered.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Mailer {
+    /// Local `sendmail` compatible binary.
+    Sendmail {
+        #[serde(default = "default_sendmail")]
+        program: String,
+    },
+    /// SMTP relay. Password is read from the environment variable named by
+    /// `password_env`, to keep it out of settings.
+    Smtp {
+        host: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        port: Option<u16>,
+        #[serde(default)]
+        security: SmtpSecurity,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        username: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        password_env: Option<String>,
+    },
+}
+
+fn default_sendmail() -> String {
+    "sendmail".to_string()
+}
+
+impl Default for Mailer {
+    fn default() -> Self {
+        Mailer::Sendmail {
+            program: default_sendmail(),
+        }
+    }
+}
+
+/// Encryption of SMTP connection.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum SmtpSecurity {
+    /// Tls from the start, port 465 by default.
+    Tls,
+    /// Upgrade to tls with STARTTLS, port 587 by default.
+    #[default]
+    Starttls,
+    /// Plain text, port 25 by default. Only meant for local relays.
+    None,
+}
+
+/// Digest email settings.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Digest {
+    pub from: String,
+    pub to: Vec<String>,
+    #[serde(default = "default_subject")]
+    pub subject: String,
+    #[serde(default)]
+    pub mailer: Mailer,
+}
+
+fn default_subject() -> String {
+    "rss-update digest".to_string()
+}
+
+/// Unread posts of a feed, as listed in the digest.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Section {
+    pub(crate) name: String,
+    pub(crate) posts: Vec<Post>,
+}
+
+/// Groups unread posts by feed, ordered by feed name.
+pub(crate) fn sections(readlist: ReadList, store: &PostStore, configs: &[Config]) -> Vec<Section> {
+    let mut sections = readlist
+        .into_iter()
+        .filter(|(_, links)| !links.is_empty())
+        .map(|(feed, links)| Section {
+            name: configs
+                .iter()
+                .find(|c| c.feed == feed)
+                .map_or(feed.as_str(), Config::display_name)
+                .to_string(),
+            posts: links
+                .iter()
+                .map(|l| {
+                    store
+                        .get(l)
+                        .cloned()
+                        .unwrap_or_else(|| Post::bare(&feed, l))
+                })
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+    sections.sort_by(|a, b| a.name.cmp(&b.name));
+    sections
+}
+
+pub(crate) fn text(sections: &[Section]) -> String {
+    let mut text = String::new();
+    for section in sections {
+        text.push_str(&format!(
+            "{}\n{}\n\n",
+            section.name,
+            "=".repeat(section.name.len())
+        ));
+        for post in &section.posts {
+            text.push_str(&format!("* {}\n  {}\n", title(post), post.link));
+            if let Some(summary) = summary(post) {
+                text.push_str(&format!("  {}\n", summary));
+            }
+            text.push('\n');
+        }
+    }
+    text
+}
+
+pub(crate) fn html(sections: &[Section]) -> String {
+    let mut html = String::from("<!DOCTYPE html>\n<html><body>\n");
+    for section in sections {
+        html.push_str(&format!("<h2>{}</h2>\n<ul>\n", escape_html(&section.name)));
+        for post in &section.posts {
+            html.push_str(&format!("<li>{}", link(&post.link, title(post))));
+            if let Some(summary) = summary(post) {
+                html.push_str(&format!("<p>{}</p>", escape_html(&summary)));
+            }
+            html.push_str("</li>\n");
+        }
+        html.push_str("</ul>\n");
+    }
+    html.push_str("</body></html>\n");
+    html
+}
+
+fn mailbox(address: &str) -> Result<Mailbox> {
+    address
+        .parse()
+        .map_err(|e| Error::input(format!("invalid digest address: {}: {}", address, e)))
+}
+
+/// Builds multipart email of sections, plain text along with html.
+pub(crate) fn message(
+    digest: &Digest,
+    sections: &[Section],
+    now: DateTime<Utc>,
+) -> Result<Message> {
+    let mut builder = Message::builder()
+        .from(mailbox(&digest.from)?)
+        .subject(format!(
+            "{} {}",
+            digest.subject,
+            now.format(crate::USER_DATE_FORMAT)
+        ))
+        .date(SystemTime::from(now));
+    for to in &digest.to {
+        builder = builder.to(mailbox(to)?);
+    }
+    builder
+        .multipart(MultiPart::alternative_plain_html(
+            text(sections),
+            html(sections),
+        ))
+        .map_err(|e| Error::input(format!("invalid digest: {}", e)))
+}
+
+/// Delivers message through the configured transport.
+pub(crate) fn send(mailer: &Mailer, message: &Message) -> Result<()> {
+    match mailer {
+        Mailer::Sendmail { program } => SendmailTransport::new_with_command(program)
+            .send(message)
+            .map_err(|e| Error::delivery(program, e)),
+        Mailer::Smtp {
+            host,
+            port,
+            security,
+            username,
+            password_env,
+        } => {
+            let builder = match security {
+                SmtpSecurity::Tls => SmtpTransport::relay(host),
+                SmtpSecurity::Starttls => SmtpTransport::starttls_relay(host),
+                SmtpSecurity::None => Ok(SmtpTransport::builder_dangerous(host)),
+            };
+            let mut builder = builder.map_err(|e| Error::delivery(host, e))?;
+            if let Some(port) = port {
+                builder = builder.port(*port);
+            }
+            if let Some(username) = username {
+                let password = match password_env {
+                    Some(var) => env::var(var).map_err(|_| {
+                        Error::input(format!("environment variable {} is not set", var))
+                    })?,
+                    None => String::new(),
+                };
+                builder = builder.credentials(Credentials::new(username.clone(), password));
+            }
+            builder
+                .build()
+                .send(message)
+                .map_err(|e| Error::delivery(host, e))?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    fn post(feed: &str, link: &str, title: &str, summary: &str) -> Post {
+        Post {
+            title: Some(title.to_string()),
+            summary: Some(summary.to_string()),
+            ..Post::bare(feed, link)
+        }
+    }
+
+    fn digest_sections() -> Vec<Section> {
+        let readlist = vec![
+            ("feed1".to_string(), vec!["post1".to_string()]),
+            ("feed2".to_string(), vec!["post2".to_string()]),
+            ("feed3".to_string(), vec![]),
+        ]
+        .into_iter()
+        .collect();
+        let store = vec![post(
+            "feed1",
+            "post1",
+            "a < b",
+            "<p>first&nbsp;<b>post</b></p>",
+        )]
+        .into_iter()
+        .map(|p| (p.link.clone(), p))
+        .collect();
+        let configs = [Config {
+            feed: "feed1".to_string(),
+            name: Some("Zeta".to_string()),
+            ..Config::default()
+        }];
+        sections(readlist, &store, &configs)
+    }
+
+    /// Accepts a single SMTP session, sending the received message over the
+    /// channel.
+    fn smtp_stub() -> (u16, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            write!(stream, "220 localhost\r\n").unwrap();
+            let mut data: Option<String> = None;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                if let Some(message) = data.as_mut() {
+                    if line == ".\r\n" {
+                        sender.send(std::mem::take(message)).unwrap();
+                        data = None;
+                        write!(stream, "250 queued\r\n").unwrap();
+                    } else {
+                        message.push_str(&line);
+                    }
+                    continue;
+                }
+                let reply = match line.get(..4).unwrap_or_default().to_uppercase().as_str() {
+                    "DATA" => {
+                        data = Some(String::new());
+                        "354 go ahead"
+                    }
+                    "QUIT" => "221 bye",
+                    _ => "250 ok",
+                };
+                write!(stream, "{}\r\n", reply).unwrap();
+            }
+        });
+        (port, receiver)
+    }
+
+    #[test]
+    fn test_sections() {
+        let sections = digest_sections();
+        assert_eq!(
+            vec!["Zeta", "feed2"],
+            sections.iter().map(|s| s.name.as_str()).collect::<Vec<_>>()
+        );
+        assert_eq!(Post::bare("feed2", "post2"), sections[1].posts[0]);
+        assert_eq!("Zeta\n====\n\n* a < b\n  post1\n  first post\n\n", {
+            let text = text(&sections);
+            text[..text.find("feed2").unwrap()].to_string()
+        });
+        assert!(html(&sections).contains("<li>a &lt; b<p>first post</p>"));
+    }
+
+    #[test]
+    fn test_send_smtp() {
+        let (port, messages) = smtp_stub();
+        let digest = Digest {
+            from: "rss-update <rss@example.com>".to_string(),
+            to: vec!["team@example.com".to_string()],
+            subject: default_subject(),
+            mailer: Mailer::Smtp {
+                host: "127.0.0.1".to_string(),
+                port: Some(port),
+                security: SmtpSecurity::None,
+                username: None,
+                password_env: None,
+            },
+        };
+        let now = crate::parse_user_date("2021-06-01").unwrap();
+        let message = message(&digest, &digest_sections(), now).unwrap();
+        send(&digest.mailer, &message).unwrap();
+
+        let received = messages.recv().unwrap();
+        assert!(received.contains("Subject: rss-update digest 2021-06-01"));
+        assert!(received.contains("To: team@example.com"));
+        assert!(received.contains("multipart/alternative"));
+        assert!(received.contains("text/plain"));
+        assert!(received.contains("text/html"));
+    }
+}
@@ -1,31 +1,478 @@
 use crate::config::Config;
+use crate::doctor::{Check, Status};
+use crate::download::{self, Job};
+use crate::error::Result;
+use crate::feeds::Change;
+use crate::health::{Report, State};
+use crate::posts::{self, Enclosure, Post, PostStore};
 use crate::readlist::ReadList;
-use std::error::Error;
-use std::io::Write;
-use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
+use crate::repair::{Outcome, Report as Repair};
+use crate::rules::Rule;
+use crate::search::Hit;
+use crate::starred::Starred;
+use crate::terminal::{self, Line, Style};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::process::{Command, Stdio};
+use termcolor::{Buffer, Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
 
-pub(crate) fn display_feeds(feeds: ReadList) -> Result<(), Box<dyn Error>> {
+/// Unread posts by feed with their short id, highlighted ones marked with
+/// `*` and starred ones with `★`. Enclosures of stored posts are listed under
+/// them.
+pub(crate) fn display_feeds(
+    feeds: ReadList,
+    highlighted: &HashSet<String>,
+    starred: &HashSet<String>,
+    names: &HashMap<String, String>,
+    store: &PostStore,
+) -> Result<()> {
+    display_posts(feeds, "total unread", highlighted, starred, names, store)
+}
+
+pub(crate) fn display_starred(feeds: Starred, names: &HashMap<String, String>) -> Result<()> {
+    display_posts(
+        feeds,
+        "starred",
+        &HashSet::new(),
+        &HashSet::new(),
+        names,
+        &PostStore::new(),
+    )
+}
+
+/// Mime type, size and duration of enclosure, as far as they are known.
+fn describe(enclosure: &Enclosure) -> String {
+    let mut details = vec![];
+    if let Some(mime) = &enclosure.mime {
+        details.push(mime.clone());
+    }
+    if let Some(length) = enclosure.length {
+        details.push(format!("{:.1} MB", length as f64 / 1_000_000.0));
+    }
+    if let Some(duration) = &enclosure.duration {
+        details.push(duration.clone());
+    }
+    let described = if details.is_empty() {
+        enclosure.url.clone()
+    } else {
+        format!("{} ({})", enclosure.url, details.join(", "))
+    };
+    terminal::sanitize(&described)
+}
+
+fn display_posts(
+    feeds: HashMap<String, Vec<String>>,
+    count: &str,
+    highlighted: &HashSet<String>,
+    starred: &HashSet<String>,
+    names: &HashMap<String, String>,
+    store: &PostStore,
+) -> Result<()> {
     let mut stdout = StandardStream::stdout(ColorChoice::Always);
     for (feed, to_read) in feeds {
         stdout.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
-        write!(&mut stdout, "feed: {}, ", feed)?;
+        write!(
+            &mut stdout,
+            "feed: {}, ",
+            terminal::sanitize(names.get(&feed).unwrap_or(&feed))
+        )?;
         stdout.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)).set_bold(true))?;
-        writeln!(&mut stdout, "total unread: {}", to_read.len())?;
+        writeln!(&mut stdout, "{}: {}", count, to_read.len())?;
         for tr in to_read {
-            stdout.set_color(ColorSpec::new().set_fg(Some(Color::White)))?;
-            writeln!(&mut stdout, "  {}", tr)?;
+            let link = format!("{} {}", posts::short_id(&tr), terminal::sanitize(&tr));
+            let marker = if starred.contains(&tr) { "★" } else { " " };
+            if highlighted.contains(&tr) {
+                stdout.set_color(ColorSpec::new().set_fg(Some(Color::Magenta)).set_bold(true))?;
+                writeln!(&mut stdout, "*{}{}", marker, link)?;
+            } else if starred.contains(&tr) {
+                stdout.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))?;
+                writeln!(&mut stdout, " {}{}", marker, link)?;
+            } else {
+                stdout.set_color(ColorSpec::new().set_fg(Some(Color::White)))?;
+                writeln!(&mut stdout, "  {}", link)?;
+            }
+            for enclosure in store.get(&tr).map_or(&[][..], |p| &p.enclosures) {
+                stdout.set_color(ColorSpec::new().set_fg(Some(Color::Cyan)))?;
+                writeln!(&mut stdout, "    ♫ {}", describe(enclosure))?;
+            }
         }
     }
     Ok(())
 }
 
-pub(crate) fn display_configs(configs: Vec<Config>) -> Result<(), Box<dyn Error>> {
+pub(crate) fn display_configs(configs: Vec<Config>) -> Result<()> {
     let mut stdout = StandardStream::stdout(ColorChoice::Always);
     for config in configs {
         stdout.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
-        writeln!(&mut stdout, "feed: {}", config.feed)?;
+        writeln!(
+            &mut stdout,
+            "feed: {}",
+            terminal::sanitize(config.display_name())
+        )?;
+        stdout.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)).set_bold(true))?;
+        if config.name.is_some() {
+            writeln!(&mut stdout, "    url: {}", config.feed)?;
+        }
+        if config.disabled {
+            writeln!(&mut stdout, "    disabled")?;
+        }
+        if config.full_content {
+            writeln!(&mut stdout, "    full content")?;
+        }
+        if let Some(auth) = &config.auth {
+            writeln!(&mut stdout, "    auth: {}", auth.describe())?;
+        }
+        if let Some(network) = &config.network {
+            writeln!(&mut stdout, "    network: {}", network.describe())?;
+        }
+        if config.dead {
+            stdout.set_color(ColorSpec::new().set_fg(Some(Color::Red)).set_bold(true))?;
+            writeln!(
+                &mut stdout,
+                "    dead: gone or not found {} times in a row, `edit --enable` to retry",
+                config.failures
+            )?;
+            stdout.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)).set_bold(true))?;
+        } else if config.failures > 0 {
+            writeln!(
+                &mut stdout,
+                "    gone or not found: {} times",
+                config.failures
+            )?;
+        }
         stdout.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)).set_bold(true))?;
         writeln!(&mut stdout, "    last_updated: {:?}", config.updated)?;
+        if !config.tags.is_empty() {
+            writeln!(&mut stdout, "    tags: {}", config.tags.join(", "))?;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn display_search_results(hits: Vec<Hit>) -> Result<()> {
+    let mut stdout = StandardStream::stdout(ColorChoice::Always);
+    for hit in hits {
+        let post = hit.post;
+        stdout.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
+        writeln!(
+            &mut stdout,
+            "{}",
+            terminal::sanitize(post.title.as_deref().unwrap_or(post.link.as_str()))
+        )?;
+        stdout.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)).set_bold(true))?;
+        write!(&mut stdout, "    feed: {}, ", post.feed)?;
+        if let Some(published) = post.published {
+            write!(&mut stdout, "published: {}, ", published.format("%Y-%m-%d"))?;
+        }
+        writeln!(
+            &mut stdout,
+            "{}",
+            if hit.unread { "unread" } else { "read" }
+        )?;
+        stdout.set_color(ColorSpec::new().set_fg(Some(Color::White)))?;
+        writeln!(
+            &mut stdout,
+            "    {} {}",
+            posts::short_id(&post.link),
+            terminal::sanitize(&post.link)
+        )?;
+    }
+    Ok(())
+}
+
+pub(crate) fn display_rules(rules: Vec<Rule>) -> Result<()> {
+    let mut stdout = StandardStream::stdout(ColorChoice::Always);
+    for (index, rule) in rules.into_iter().enumerate() {
+        stdout.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
+        writeln!(
+            &mut stdout,
+            "rule {}: {:?} when {:?} matches {}{}",
+            index,
+            rule.action,
+            rule.field,
+            rule.pattern,
+            if rule.regex { " (regex)" } else { "" }
+        )?;
+        stdout.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)).set_bold(true))?;
+        writeln!(
+            &mut stdout,
+            "    feed: {}",
+            rule.feed.as_deref().unwrap_or("all feeds")
+        )?;
+    }
+    Ok(())
+}
+
+pub(crate) fn display_health(reports: Vec<Report>) -> Result<()> {
+    let mut stdout = StandardStream::stdout(ColorChoice::Always);
+    for report in reports {
+        let (color, state) = match report.state {
+            State::Dead => (Color::Red, "dead"),
+            State::Erroring => (Color::Red, "erroring"),
+            State::Dormant => (Color::Yellow, "dormant"),
+            State::Unknown => (Color::Yellow, "unknown"),
+            State::Healthy => (Color::Green, "healthy"),
+        };
+        stdout.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
+        write!(&mut stdout, "feed: {}, ", terminal::sanitize(&report.name))?;
+        stdout.set_color(ColorSpec::new().set_fg(Some(color)).set_bold(true))?;
+        writeln!(&mut stdout, "{}", state)?;
+        stdout.set_color(ColorSpec::new().set_fg(Some(Color::White)))?;
+        let history = report.history;
+        if let Some(last_success) = history.last_success {
+            writeln!(&mut stdout, "    last success: {}", last_success)?;
+        }
+        if let (Some(error), Some(at)) = (&history.last_error, history.last_error_at) {
+            writeln!(
+                &mut stdout,
+                "    last error: {} at {}",
+                terminal::sanitize(error),
+                at
+            )?;
+        }
+        if history.consecutive_failures > 0 {
+            writeln!(
+                &mut stdout,
+                "    consecutive failures: {}",
+                history.consecutive_failures
+            )?;
+        }
+        if let Some(status) = history.status {
+            writeln!(
+                &mut stdout,
+                "    last response: status {}, {} ms, {} bytes",
+                status,
+                history.response_ms.unwrap_or_default(),
+                history.bytes.unwrap_or_default()
+            )?;
+        }
+        if let Some(last_post) = history.last_post {
+            writeln!(&mut stdout, "    last new post: {}", last_post)?;
+        }
+        if let Some(interval) = history.posting_interval() {
+            writeln!(
+                &mut stdout,
+                "    posts every: {:.1} days",
+                interval.num_hours() as f64 / 24.0
+            )?;
+        }
+        if let Some(suggestion) = report.suggestion {
+            stdout.set_color(ColorSpec::new().set_fg(Some(Color::Cyan)))?;
+            writeln!(&mut stdout, "    suggestion: {}", suggestion)?;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn display_changes(changes: Vec<Change>) -> Result<()> {
+    let mut stdout = StandardStream::stdout(ColorChoice::Always);
+    let show_date = |d: Option<chrono::DateTime<chrono::Utc>>| {
+        d.map_or_else(|| "never".to_string(), |d| d.to_rfc3339())
+    };
+    for change in changes {
+        stdout.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
+        write!(&mut stdout, "feed: {}, ", change.feed)?;
+        stdout.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)).set_bold(true))?;
+        writeln!(&mut stdout, "would add: {}", change.added.len())?;
+        writeln!(
+            &mut stdout,
+            "    cursor: {} -> {}",
+            show_date(change.cursor_from),
+            show_date(change.cursor_to)
+        )?;
+        if let Some(url) = &change.moved_to {
+            writeln!(
+                &mut stdout,
+                "    would move to: {}",
+                terminal::sanitize(url)
+            )?;
+        }
+        if let Some(error) = &change.error {
+            stdout.set_color(ColorSpec::new().set_fg(Some(Color::Red)).set_bold(true))?;
+            writeln!(&mut stdout, "    failed: {}", terminal::sanitize(error))?;
+        }
+        if change.dead {
+            stdout.set_color(ColorSpec::new().set_fg(Some(Color::Red)).set_bold(true))?;
+            writeln!(&mut stdout, "    would be marked dead")?;
+        }
+        for (label, posts) in [
+            ("+", &change.added),
+            ("read", &change.marked_read),
+            ("skip", &change.skipped),
+        ] {
+            stdout.set_color(ColorSpec::new().set_fg(Some(Color::White)))?;
+            for post in posts {
+                writeln!(&mut stdout, "  {:>4} {}", label, terminal::sanitize(post))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn display_checks(checks: Vec<Check>) -> Result<()> {
+    let mut stdout = StandardStream::stdout(ColorChoice::Always);
+    for check in checks {
+        let (color, status) = match check.status {
+            Status::Ok => (Color::Green, "ok"),
+            Status::Warning => (Color::Yellow, "warn"),
+            Status::Failed => (Color::Red, "fail"),
+        };
+        stdout.set_color(ColorSpec::new().set_fg(Some(color)).set_bold(true))?;
+        write!(&mut stdout, "{:>4} ", status)?;
+        stdout.set_color(ColorSpec::new().set_fg(Some(Color::White)))?;
+        writeln!(&mut stdout, "{}: {}", check.name, check.message)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn display_repairs(repairs: Vec<Repair>) -> Result<()> {
+    let mut stdout = StandardStream::stdout(ColorChoice::Always);
+    for repair in repairs {
+        stdout.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
+        write!(&mut stdout, "{}: ", repair.name)?;
+        match repair.outcome {
+            Ok(Outcome::Missing) => {
+                stdout.set_color(ColorSpec::new().set_fg(Some(Color::White)))?;
+                writeln!(&mut stdout, "not created yet")?;
+            }
+            Ok(Outcome::Valid) => {
+                stdout.set_color(ColorSpec::new().set_fg(Some(Color::White)))?;
+                writeln!(&mut stdout, "nothing to repair")?;
+            }
+            Ok(Outcome::Repaired { kept, dropped }) => {
+                stdout.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)).set_bold(true))?;
+                writeln!(&mut stdout, "kept {} entries, dropped {}", kept, dropped)?;
+                stdout.set_color(ColorSpec::new().set_fg(Some(Color::White)))?;
+                writeln!(
+                    &mut stdout,
+                    "    damaged file kept as {}",
+                    crate::store::backup_path(&repair.path, 1)
+                )?;
+            }
+            Err(e) => {
+                stdout.set_color(ColorSpec::new().set_fg(Some(Color::Red)).set_bold(true))?;
+                writeln!(&mut stdout, "could not repair: {}", e)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn display_downloads(outcomes: &[(Job, Result<download::Outcome>)]) -> Result<()> {
+    let mut stdout = StandardStream::stdout(ColorChoice::Always);
+    if outcomes.is_empty() {
+        writeln!(&mut stdout, "no enclosures to download")?;
+    }
+    for (job, outcome) in outcomes {
+        let path = job.path.display();
+        match outcome {
+            Ok(download::Outcome::Exists) => {
+                stdout.set_color(ColorSpec::new().set_fg(Some(Color::White)))?;
+                writeln!(&mut stdout, "exists: {}", path)?;
+            }
+            Ok(download::Outcome::Downloaded { bytes }) => {
+                stdout.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
+                writeln!(&mut stdout, "downloaded: {} ({} bytes)", path, bytes)?;
+            }
+            Ok(download::Outcome::Resumed { from, bytes }) => {
+                stdout.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
+                writeln!(
+                    &mut stdout,
+                    "resumed: {} ({} more bytes after {})",
+                    path, bytes, from
+                )?;
+            }
+            Err(e) => {
+                stdout.set_color(ColorSpec::new().set_fg(Some(Color::Red)).set_bold(true))?;
+                writeln!(&mut stdout, "failed: {}: {}", path, e)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn style_spec(style: Style) -> ColorSpec {
+    let mut spec = ColorSpec::new();
+    match style {
+        Style::Plain => spec.set_fg(Some(Color::White)),
+        Style::Heading => spec.set_fg(Some(Color::Green)).set_bold(true),
+        Style::Strong => spec.set_fg(Some(Color::White)).set_bold(true),
+        Style::Emphasis => spec.set_fg(Some(Color::White)).set_italic(true),
+        Style::Code => spec.set_fg(Some(Color::Cyan)),
+        Style::Link => spec.set_fg(Some(Color::Blue)).set_underline(true),
+        Style::Footnote => spec.set_fg(Some(Color::Yellow)),
+        Style::Quote => spec.set_fg(Some(Color::Magenta)),
+    };
+    spec
+}
+
+/// Shows post with its content, falling back to fetched article and then
+/// summary, paged through `$PAGER` (`less` if unset) on a terminal.
+pub(crate) fn display_post(post: &Post, feed_name: &str, width: usize) -> Result<()> {
+    let mut lines: Vec<Line> = vec![];
+    let mut heading = terminal::render_text(post.title.as_deref().unwrap_or(&post.link), width);
+    for span in heading.iter_mut().flatten() {
+        span.style = Style::Heading;
+    }
+    lines.append(&mut heading);
+    let mut meta = format!("feed: {}", feed_name);
+    if let Some(author) = &post.author {
+        meta.push_str(&format!(", author: {}", author));
+    }
+    if let Some(published) = post.published {
+        meta.push_str(&format!(", published: {}", published.format("%Y-%m-%d")));
+    }
+    lines.append(&mut terminal::render_text(&meta, width));
+    lines.append(&mut terminal::render_text(&post.link, usize::MAX));
+    lines.push(vec![]);
+    let mut body = match (&post.content, &post.article, &post.summary) {
+        (Some(content), _, _) => terminal::render(content, &post.link, width),
+        (None, Some(article), _) => terminal::render_text(article, width),
+        (None, None, Some(summary)) => terminal::render(summary, &post.link, width),
+        (None, None, None) => terminal::render_text("no content, open link to read post.", width),
+    };
+    lines.append(&mut body);
+
+    let mut buffer = Buffer::ansi();
+    for line in lines {
+        for span in line {
+            buffer.set_color(&style_spec(span.style))?;
+            write!(&mut buffer, "{}", span.text)?;
+        }
+        buffer.reset()?;
+        writeln!(&mut buffer)?;
+    }
+    page(buffer.as_slice())
+}
+
+/// Writes output through pager when stdout is a terminal, directly otherwise
+/// or if pager can not be started.
+fn page(output: &[u8]) -> Result<()> {
+    let pager = env::var("PAGER")
+        .ok()
+        .filter(|p| !p.trim().is_empty())
+        .unwrap_or_else(|| "less".to_string());
+    if io::stdout().is_terminal() {
+        // Like git, let less show colors and quit if output fits the screen.
+        let child = Command::new("sh")
+            .arg("-c")
+            .arg(&pager)
+            .env(
+                "LESS",
+                env::var("LESS").unwrap_or_else(|_| "FRX".to_string()),
+            )
+            .stdin(Stdio::piped())
+            .spawn();
+        if let Ok(mut child) = child {
+            if let Some(mut stdin) = child.stdin.take() {
+                // Quitting pager before reading everything is not an error.
+                let _ = stdin.write_all(output);
+            }
+            child.wait()?;
+            return Ok(());
+        }
     }
+    io::stdout().write_all(output)?;
     Ok(())
 }
@@ -0,0 +1,191 @@
+use crate::config::{self, ConfigList};
+use crate::health::HealthStore;
+use crate::posts::PostStore;
+use crate::readlist::{self, ReadList};
+use crate::settings::Settings;
+use crate::starred::Starred;
+use crate::store::{self, Migration};
+use serde::de::DeserializeOwned;
+use std::fs::{self, OpenOptions};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Status {
+    Ok,
+    Warning,
+    Failed,
+}
+
+/// Outcome of checking one part of the installation.
+#[derive(Debug)]
+pub(crate) struct Check {
+    pub(crate) name: String,
+    pub(crate) status: Status,
+    pub(crate) message: String,
+}
+
+impl Check {
+    fn new(name: &str, status: Status, message: impl Into<String>) -> Self {
+        Check {
+            name: name.to_string(),
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+/// Checks version, data directory and every data file.
+pub(crate) fn checks() -> Vec<Check> {
+    vec![
+        Check::new(
+            "version",
+            Status::Ok,
+            format!("rss-update {}", env!("CARGO_PKG_VERSION")),
+        ),
+        directory("data directory", &crate::base_dir()),
+        versioned::<ConfigList>("config", &config::config_path(), config::MIGRATIONS),
+        versioned::<ReadList>("readlist", &readlist::readlist_path(), readlist::MIGRATIONS),
+        file::<PostStore>("posts", &crate::posts::posts_path()),
+        file::<Settings>("settings", &crate::settings::settings_path()),
+        file::<HealthStore>("health", &crate::health::health_path()),
+        file::<Starred>("starred", &crate::starred::starred_path()),
+        secrets(&crate::auth::secrets_path()),
+    ]
+}
+
+/// Data files anyone can write to let others change what gets fetched.
+#[cfg(unix)]
+fn world_writable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o002 != 0
+}
+
+#[cfg(not(unix))]
+fn world_writable(_metadata: &fs::Metadata) -> bool {
+    false
+}
+
+fn directory(name: &str, path: &str) -> Check {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return Check::new(name, Status::Ok, "not created yet, created on first use"),
+    };
+    if !metadata.is_dir() {
+        Check::new(name, Status::Failed, format!("{} is not a directory", path))
+    } else if metadata.permissions().readonly() {
+        Check::new(name, Status::Failed, format!("{} is read only", path))
+    } else if world_writable(&metadata) {
+        Check::new(
+            name,
+            Status::Warning,
+            format!("{} is writable by everyone", path),
+        )
+    } else {
+        Check::new(name, Status::Ok, path)
+    }
+}
+
+/// Secrets file is refused when others can read it, so it fails the check.
+fn secrets(path: &str) -> Check {
+    check_file("secrets", path, || crate::auth::_get(path).map(|_| ()))
+}
+
+fn file<T: DeserializeOwned>(name: &str, path: &str) -> Check {
+    check_file(name, path, || store::read::<T>(path).map(|_| ()))
+}
+
+/// Checks versioned data file, reporting files in an older format which are
+/// upgraded on next use.
+fn versioned<T: DeserializeOwned>(name: &str, path: &str, migrations: &[Migration]) -> Check {
+    let mut version = 0;
+    let check = check_file(name, path, || {
+        version = store::inspect::<T>(path, migrations)?;
+        Ok(())
+    });
+    if check.status == Status::Ok && version < migrations.len() as u32 {
+        let message = format!(
+            "{} has format version {}, upgraded to {} on next use",
+            path,
+            version,
+            migrations.len()
+        );
+        return Check::new(name, Status::Warning, message);
+    }
+    check
+}
+
+fn check_file<F>(name: &str, path: &str, read: F) -> Check
+where
+    F: FnOnce() -> crate::error::Result<()>,
+{
+    if !Path::new(path).exists() {
+        return Check::new(name, Status::Ok, "not created yet, created on first use");
+    }
+    // Opening for append checks write permission without changing the file.
+    if let Err(e) = OpenOptions::new().read(true).append(true).open(path) {
+        return Check::new(name, Status::Failed, format!("{}: {}", path, e));
+    }
+    if let Err(e) = read() {
+        return Check::new(name, Status::Failed, e.to_string());
+    }
+    match fs::metadata(path) {
+        Ok(metadata) if world_writable(&metadata) => Check::new(
+            name,
+            Status::Warning,
+            format!("{} is writable by everyone", path),
+        ),
+        _ => Check::new(name, Status::Ok, path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_file() {
+        let mut data = NamedTempFile::new().unwrap();
+        let path = data.path().to_str().unwrap().to_string();
+        writeln!(data, "{{\"feed\": [\"post\"]}}").unwrap();
+        assert_eq!(Status::Ok, file::<ReadList>("readlist", &path).status);
+
+        let check = file::<ConfigList>("config", &path);
+        assert_eq!(Status::Failed, check.status);
+        assert!(check.message.contains("line 1, column 0"));
+
+        assert_eq!(
+            Status::Ok,
+            file::<ConfigList>("config", "some/really/fake/path").status
+        );
+    }
+
+    #[test]
+    fn test_versioned() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("config.json");
+        let path = path.to_str().unwrap().to_string();
+        fs::write(&path, "[]").unwrap();
+        let check = versioned::<ConfigList>("config", &path, config::MIGRATIONS);
+        assert_eq!(Status::Warning, check.status);
+        assert!(check
+            .message
+            .ends_with("format version 0, upgraded to 1 on next use"));
+
+        store::write_versioned(&path, config::MIGRATIONS, &ConfigList::new()).unwrap();
+        let check = versioned::<ConfigList>("config", &path, config::MIGRATIONS);
+        assert_eq!(Status::Ok, check.status);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_file_world_writable() {
+        use std::os::unix::fs::PermissionsExt;
+        let mut data = NamedTempFile::new().unwrap();
+        writeln!(data, "{{}}").unwrap();
+        fs::set_permissions(data.path(), fs::Permissions::from_mode(0o666)).unwrap();
+        let check = file::<Settings>("settings", data.path().to_str().unwrap());
+        assert_eq!(Status::Warning, check.status);
+    }
+}
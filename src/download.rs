@@ -0,0 +1,530 @@
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::network::Network;
+use crate::posts::{Enclosure, Post, PostStore};
+use crate::readlist::ReadList;
+use futures::{stream, StreamExt};
+use reqwest::{header, redirect, Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tokio::fs::{self, OpenOptions};
+use tokio::io::AsyncWriteExt;
+
+/// Suffix of files being downloaded, kept to resume interrupted downloads.
+const PARTIAL: &str = "part";
+
+/// Redirects followed by a download, as many as `reqwest` follows.
+const MAX_REDIRECTS: usize = 10;
+
+/// How `download` saves enclosures of unread posts.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Download {
+    /// Directory enclosures are saved under, `downloads` in data directory
+    /// if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir: Option<String>,
+    /// Path of file under `dir`, with `{{name}}`, `{{title}}`, `{{date}}`,
+    /// `{{filename}}` and `{{ext}}` replaced. Slashes make sub directories.
+    #[serde(default = "default_template")]
+    pub template: String,
+    /// Enclosures downloaded at once.
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+}
+
+fn default_template() -> String {
+    "{{name}}/{{date}} {{title}}.{{ext}}".to_string()
+}
+
+fn default_concurrency() -> usize {
+    2
+}
+
+impl Default for Download {
+    fn default() -> Self {
+        Download {
+            dir: None,
+            template: default_template(),
+            concurrency: default_concurrency(),
+        }
+    }
+}
+
+pub(crate) fn default_dir() -> String {
+    let dir = Path::new(&crate::base_dir()).join("downloads");
+    String::from(dir.to_str().unwrap())
+}
+
+/// Enclosure to download, along with where it goes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Job {
+    pub(crate) url: String,
+    pub(crate) path: PathBuf,
+    /// Network settings of feed of the post, merged into those of settings.
+    pub(crate) network: Network,
+}
+
+/// Clients of downloads by network settings and whether invalid
+/// certificates are accepted, built once first needed.
+#[derive(Debug, Default)]
+pub(crate) struct Clients(Mutex<HashMap<(Network, bool), Client>>);
+
+impl Clients {
+    /// Client for job, following redirects since enclosures are often served
+    /// from elsewhere than where feed links. Invalid certificates are only
+    /// accepted while on one of the insecure hosts.
+    fn client(&self, job: &Job) -> Result<Client> {
+        let insecure = job.network.is_insecure(&job.url);
+        let mut clients = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let key = (job.network.clone(), insecure);
+        if let Some(client) = clients.get(&key) {
+            return Ok(client.clone());
+        }
+        let network = job.network.clone();
+        let policy = redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                attempt.error("too many redirects")
+            } else if insecure && !network.is_insecure(attempt.url().as_str()) {
+                attempt.stop()
+            } else {
+                attempt.follow()
+            }
+        });
+        let client = job
+            .network
+            .builder(insecure)?
+            .redirect(policy)
+            .build()
+            .map_err(|e| Error::http("http client", e))?;
+        clients.insert(key, client.clone());
+        Ok(client)
+    }
+}
+
+/// Result of a single download.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Outcome {
+    /// File was already there.
+    Exists,
+    Downloaded {
+        bytes: u64,
+    },
+    /// Download continued from a partial file holding `from` bytes.
+    Resumed {
+        from: u64,
+        bytes: u64,
+    },
+}
+
+/// Makes value safe to use as a single path component.
+fn sanitize(value: &str) -> String {
+    let cleaned = value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect::<String>();
+    let cleaned = cleaned.trim().trim_matches('.').trim();
+    if cleaned.is_empty() {
+        "untitled".to_string()
+    } else {
+        cleaned.chars().take(120).collect()
+    }
+}
+
+/// Last segment of url path, split into stem and extension.
+fn file_name(url: &str) -> (String, Option<String>) {
+    let name = reqwest::Url::parse(url)
+        .ok()
+        .and_then(|u| u.path_segments()?.next_back().map(String::from))
+        .unwrap_or_default();
+    match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() && !ext.is_empty() && ext.len() <= 5 => {
+            (stem.to_string(), Some(ext.to_lowercase()))
+        }
+        _ => (name, None),
+    }
+}
+
+/// Extension for mime type of enclosures served without one in their url.
+fn extension(mime: &str) -> &str {
+    match mime {
+        "audio/mpeg" | "audio/mp3" => "mp3",
+        "audio/mp4" | "audio/x-m4a" | "audio/m4a" => "m4a",
+        "audio/ogg" => "ogg",
+        "audio/opus" => "opus",
+        "audio/aac" => "aac",
+        "audio/wav" | "audio/x-wav" => "wav",
+        "video/mp4" => "mp4",
+        "video/webm" => "webm",
+        "video/quicktime" => "mov",
+        "application/pdf" => "pdf",
+        "image/jpeg" => "jpg",
+        "image/png" => "png",
+        _ => "bin",
+    }
+}
+
+/// Path of enclosure relative to download directory, from template.
+pub(crate) fn render(template: &str, post: &Post, name: &str, enclosure: &Enclosure) -> PathBuf {
+    let (stem, ext) = file_name(&enclosure.url);
+    let ext =
+        ext.unwrap_or_else(|| extension(enclosure.mime.as_deref().unwrap_or_default()).to_string());
+    let fields = [
+        ("name", name.to_string()),
+        ("title", post.title.clone().unwrap_or_else(|| stem.clone())),
+        (
+            "date",
+            post.published
+                .map_or("undated".to_string(), |d| d.format("%Y-%m-%d").to_string()),
+        ),
+        ("filename", stem),
+        ("ext", ext),
+    ];
+    template
+        .split('/')
+        .map(|component| {
+            let rendered = fields
+                .iter()
+                .fold(component.to_string(), |c, (field, value)| {
+                    c.replace(&format!("{{{{{}}}}}", field), value)
+                });
+            sanitize(&rendered)
+        })
+        .collect()
+}
+
+/// Downloads for enclosures of unread posts, over network settings of their
+/// feed. Enclosures sharing a path get a numbered suffix, ones shared by
+/// posts are downloaded once.
+pub(crate) fn jobs(
+    readlist: &ReadList,
+    store: &PostStore,
+    configs: &[Config],
+    network: &Network,
+    dir: &Path,
+    template: &str,
+) -> Vec<Job> {
+    let configs = configs
+        .iter()
+        .map(|c| (c.feed.as_str(), c))
+        .collect::<HashMap<_, _>>();
+    let mut links = readlist.values().flatten().collect::<Vec<_>>();
+    links.sort();
+    let mut urls = HashSet::new();
+    let mut paths = HashSet::new();
+    let mut jobs = vec![];
+    for post in links.into_iter().filter_map(|l| store.get(l)) {
+        let config = configs.get(post.feed.as_str());
+        let name = config.and_then(|c| c.name.as_deref()).unwrap_or(&post.feed);
+        let network = network.merge(config.and_then(|c| c.network.as_ref()));
+        for enclosure in &post.enclosures {
+            if !urls.insert(enclosure.url.clone()) {
+                continue;
+            }
+            let relative = render(template, post, name, enclosure);
+            let mut path = dir.join(&relative);
+            let mut n = 1;
+            while !paths.insert(path.clone()) {
+                n += 1;
+                let stem = relative.file_stem().unwrap_or_default().to_string_lossy();
+                let numbered = match relative.extension() {
+                    Some(ext) => format!("{} ({}).{}", stem, n, ext.to_string_lossy()),
+                    None => format!("{} ({})", stem, n),
+                };
+                path = dir.join(relative.with_file_name(numbered));
+            }
+            jobs.push(Job {
+                url: enclosure.url.clone(),
+                path,
+                network: network.clone(),
+            });
+        }
+    }
+    jobs
+}
+
+fn partial_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(PARTIAL);
+    path.with_file_name(name)
+}
+
+/// Downloads enclosure to a partial file, renamed once complete. A partial
+/// file left by an interrupted download is continued with a range request,
+/// or started over if server does not support ranges.
+pub(crate) async fn download(client: &Client, job: &Job) -> Result<Outcome> {
+    let path = job.path.to_string_lossy().into_owned();
+    if job.path.is_file() {
+        return Ok(Outcome::Exists);
+    }
+    if let Some(parent) = job.path.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|e| Error::io(&path, e))?;
+    }
+    let partial = partial_path(&job.path);
+    let partial_str = partial.to_string_lossy().into_owned();
+    let from = fs::metadata(&partial).await.map_or(0, |m| m.len());
+    let mut request = client.get(&job.url);
+    if from > 0 {
+        request = request.header(header::RANGE, format!("bytes={}-", from));
+    }
+    let mut response = request.send().await.map_err(|e| Error::http(&job.url, e))?;
+    let status = response.status();
+    let resumed = match status {
+        StatusCode::PARTIAL_CONTENT if from > 0 => true,
+        // Partial file already holds everything there is.
+        StatusCode::RANGE_NOT_SATISFIABLE if from > 0 => {
+            fs::rename(&partial, &job.path)
+                .await
+                .map_err(|e| Error::io(&path, e))?;
+            return Ok(Outcome::Resumed { from, bytes: 0 });
+        }
+        s if s.is_success() => false,
+        s => return Err(Error::status(&job.url, s.as_u16())),
+    };
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(resumed)
+        .truncate(!resumed)
+        .open(&partial)
+        .await
+        .map_err(|e| Error::io(&partial_str, e))?;
+    let mut bytes = 0;
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| Error::http(&job.url, e))?
+    {
+        file.write_all(&chunk)
+            .await
+            .map_err(|e| Error::io(&partial_str, e))?;
+        bytes += chunk.len() as u64;
+    }
+    file.flush().await.map_err(|e| Error::io(&partial_str, e))?;
+    fs::rename(&partial, &job.path)
+        .await
+        .map_err(|e| Error::io(&path, e))?;
+    Ok(if resumed {
+        Outcome::Resumed { from, bytes }
+    } else {
+        Outcome::Downloaded { bytes }
+    })
+}
+
+/// Runs downloads, at most `concurrency` at once, in the order they finish.
+pub(crate) async fn download_all(
+    clients: &Clients,
+    jobs: Vec<Job>,
+    concurrency: usize,
+) -> Vec<(Job, Result<Outcome>)> {
+    stream::iter(jobs)
+        .map(|job| async move {
+            let outcome = match clients.client(&job) {
+                Ok(client) => download(&client, &job).await,
+                Err(e) => Err(e),
+            };
+            (job, outcome)
+        })
+        .buffer_unordered(concurrency.max(1))
+        .collect()
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use tempfile::TempDir;
+
+    fn post(link: &str, title: &str, enclosures: &[&str]) -> Post {
+        let mut post = Post::bare("https://pod.example.com/feed", link);
+        post.title = Some(title.to_string());
+        post.published = Some(crate::parse_user_date("2021-06-01").unwrap());
+        post.enclosures = enclosures
+            .iter()
+            .map(|url| Enclosure {
+                url: url.to_string(),
+                mime: Some("audio/mpeg".to_string()),
+                length: None,
+                duration: None,
+            })
+            .collect();
+        post
+    }
+
+    #[test]
+    fn test_render() {
+        let episode = post("post1", "Episode 1: a/b?", &["https://cdn.com/ep1.MP3?x=1"]);
+        assert_eq!(
+            PathBuf::from("Pod_ cast/2021-06-01 Episode 1_ a_b_.mp3"),
+            render(
+                &default_template(),
+                &episode,
+                "Pod: cast",
+                &episode.enclosures[0]
+            )
+        );
+        let bare = post("post2", "..", &["https://cdn.com/stream"]);
+        assert_eq!(
+            PathBuf::from("untitled/stream.mp3"),
+            render(
+                "{{title}}/{{filename}}.{{ext}}",
+                &bare,
+                "",
+                &bare.enclosures[0]
+            )
+        );
+    }
+
+    #[test]
+    fn test_jobs() {
+        let readlist = vec![(
+            "https://pod.example.com/feed".to_string(),
+            vec![
+                "post1".to_string(),
+                "post2".to_string(),
+                "post3".to_string(),
+            ],
+        )]
+        .into_iter()
+        .collect();
+        let store = [
+            post("post1", "Same", &["https://cdn.com/a.mp3"]),
+            post(
+                "post2",
+                "Same",
+                &["https://cdn.com/b.mp3", "https://cdn.com/a.mp3"],
+            ),
+        ]
+        .iter()
+        .map(|p| (p.link.clone(), p.clone()))
+        .collect();
+        let settings = Network {
+            proxy: Some("http://proxy:3128".to_string()),
+            ..Network::default()
+        };
+        let configs = vec![Config {
+            feed: "https://pod.example.com/feed".to_string(),
+            name: Some("pod".to_string()),
+            network: Some(Network {
+                insecure_hosts: vec!["cdn.com".to_string()],
+                ..Network::default()
+            }),
+            ..Config::default()
+        }];
+        let jobs = jobs(
+            &readlist,
+            &store,
+            &configs,
+            &settings,
+            Path::new("/d"),
+            "{{name}}/{{title}}.{{ext}}",
+        );
+        let network = Network {
+            proxy: Some("http://proxy:3128".to_string()),
+            insecure_hosts: vec!["cdn.com".to_string()],
+            ..Network::default()
+        };
+        assert_eq!(
+            vec![
+                Job {
+                    url: "https://cdn.com/a.mp3".to_string(),
+                    path: PathBuf::from("/d/pod/Same.mp3"),
+                    network: network.clone(),
+                },
+                Job {
+                    url: "https://cdn.com/b.mp3".to_string(),
+                    path: PathBuf::from("/d/pod/Same (2).mp3"),
+                    network,
+                },
+            ],
+            jobs
+        );
+    }
+
+    /// Serves body to `requests` requests, honoring ranges if asked to.
+    fn server(body: &'static [u8], ranges: bool, requests: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/episode.mp3", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let mut from = None;
+                for line in BufReader::new(&stream).lines() {
+                    let line = line.unwrap();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some(range) = line.to_lowercase().strip_prefix("range: bytes=") {
+                        from = range.trim_end_matches('-').parse::<usize>().ok();
+                    }
+                }
+                let (status, content) = match from.filter(|_| ranges) {
+                    Some(from) => ("206 Partial Content", &body[from..]),
+                    None => ("200 OK", body),
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                    status,
+                    content.len()
+                )
+                .unwrap();
+                stream.write_all(content).unwrap();
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_download() {
+        let dir = TempDir::new().unwrap();
+        let client = Client::new();
+        let job = |url: &str, name: &str| Job {
+            url: url.to_string(),
+            path: dir.path().join("pod").join(name),
+            network: Network::default(),
+        };
+
+        let resumable = job(&server(b"0123456789", true, 1), "a.mp3");
+        std::fs::create_dir_all(dir.path().join("pod")).unwrap();
+        std::fs::write(partial_path(&resumable.path), b"0123").unwrap();
+        let fresh = job(&server(b"abc", false, 1), "b.mp3");
+        let restarted = job(&server(b"xyz", false, 1), "c.mp3");
+        std::fs::write(partial_path(&restarted.path), b"old data").unwrap();
+
+        let outcomes = download_all(
+            &Clients::default(),
+            vec![resumable.clone(), fresh.clone(), restarted.clone()],
+            2,
+        )
+        .await
+        .into_iter()
+        .map(|(job, outcome)| (job.path, outcome.unwrap()))
+        .collect::<HashMap<_, _>>();
+        assert_eq!(
+            Outcome::Resumed { from: 4, bytes: 6 },
+            outcomes[&resumable.path]
+        );
+        assert_eq!(Outcome::Downloaded { bytes: 3 }, outcomes[&fresh.path]);
+        assert_eq!(Outcome::Downloaded { bytes: 3 }, outcomes[&restarted.path]);
+        assert_eq!(
+            b"0123456789".to_vec(),
+            std::fs::read(&resumable.path).unwrap()
+        );
+        assert_eq!(b"xyz".to_vec(), std::fs::read(&restarted.path).unwrap());
+        assert!(!partial_path(&resumable.path).exists());
+
+        assert_eq!(Outcome::Exists, download(&client, &fresh).await.unwrap());
+    }
+}
@@ -0,0 +1,297 @@
+use reqwest::StatusCode;
+use std::fmt;
+use std::io;
+
+/// Result of every fallible operation of rss-update.
+pub type Result<T> = std::result::Result<T, Error>;
+
+// Process exit codes per error category, following sysexits.h.
+const EXIT_INPUT: i32 = 64;
+const EXIT_PARSE: i32 = 65;
+const EXIT_HTTP: i32 = 69;
+const EXIT_IO: i32 = 74;
+const EXIT_FEED: i32 = 76;
+
+/// Errors surfaced to the user. Each variant carries enough context to tell
+/// what went wrong and how to fix it.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Data file could not be read or written. Path is absent for terminal io.
+    Io {
+        path: Option<String>,
+        source: io::Error,
+    },
+    /// Data file is not valid json, or not in the expected shape.
+    Parse {
+        path: String,
+        line: usize,
+        column: usize,
+        source: serde_json::Error,
+    },
+    /// Data file was written by a newer version, in a format this one does
+    /// not know.
+    Version {
+        path: String,
+        found: u32,
+        supported: u32,
+    },
+    /// Url, e.g. of a feed, a webhook or an enclosure, could not be
+    /// requested, or responded with an error status.
+    Http {
+        url: String,
+        status: Option<u16>,
+        reason: Option<String>,
+    },
+    /// Data could not be turned into json, e.g. to write it to a file.
+    Serialize {
+        what: String,
+        source: serde_json::Error,
+    },
+    /// Feed responded, but not with a valid rss or atom document.
+    Feed { url: String, reason: String },
+    /// Digest could not be delivered to the mail server or program.
+    Delivery { to: String, reason: String },
+    /// User provided argument is invalid.
+    Input(String),
+}
+
+impl Error {
+    pub fn io(path: &str, source: io::Error) -> Self {
+        Error::Io {
+            path: Some(path.to_string()),
+            source,
+        }
+    }
+
+    pub fn parse(path: &str, source: serde_json::Error) -> Self {
+        Error::Parse {
+            path: path.to_string(),
+            line: source.line(),
+            column: source.column(),
+            source,
+        }
+    }
+
+    /// Data for what, e.g. a file or a program, could not be serialized.
+    pub fn serialize(what: &str, source: serde_json::Error) -> Self {
+        Error::Serialize {
+            what: what.to_string(),
+            source,
+        }
+    }
+
+    /// Request of url could not be made or completed.
+    pub fn http(url: &str, reason: impl fmt::Display) -> Self {
+        Error::Http {
+            url: url.to_string(),
+            status: None,
+            reason: Some(reason.to_string()),
+        }
+    }
+
+    /// Url responded with an unexpected status.
+    pub fn status(url: &str, status: u16) -> Self {
+        Error::Http {
+            url: url.to_string(),
+            status: Some(status),
+            reason: None,
+        }
+    }
+
+    pub fn feed(url: &str, reason: impl fmt::Display) -> Self {
+        Error::Feed {
+            url: url.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+
+    pub fn delivery(to: &str, reason: impl fmt::Display) -> Self {
+        Error::Delivery {
+            to: to.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+
+    pub fn input(message: impl fmt::Display) -> Self {
+        Error::Input(message.to_string())
+    }
+
+    /// Process exit code for the error category.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Io { .. } => EXIT_IO,
+            Error::Parse { .. } | Error::Version { .. } | Error::Serialize { .. } => EXIT_PARSE,
+            Error::Http { .. } | Error::Delivery { .. } => EXIT_HTTP,
+            Error::Feed { .. } => EXIT_FEED,
+            Error::Input(_) => EXIT_INPUT,
+        }
+    }
+
+    /// Suggestion on how to fix the error, if there is an obvious one.
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            Error::Io {
+                path: Some(_),
+                source,
+            } if source.kind() == io::ErrorKind::NotFound => {
+                Some("run `rss-update doctor` to check data files")
+            }
+            Error::Io {
+                path: Some(_),
+                source,
+            } if source.kind() == io::ErrorKind::PermissionDenied => {
+                Some("check permissions of the file and its directory")
+            }
+            Error::Parse { .. } => {
+                Some("fix the file by hand at the given position, or run `rss-update repair`")
+            }
+            Error::Version { .. } => Some("upgrade rss-update to the version which wrote the file"),
+            Error::Http { status: None, .. } => Some("check network connection and the url"),
+            Error::Http {
+                status: Some(status),
+                ..
+            } if (400..500).contains(status) => {
+                Some("check the url, for a feed `edit --url` changes it")
+            }
+            Error::Http { .. } => Some("server is having trouble, try again later"),
+            Error::Feed { .. } => Some("make sure url points to the rss / atom feed, not the site"),
+            Error::Delivery { .. } => Some("check `mailer` of `digest` in settings.json"),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io {
+                path: Some(path),
+                source,
+            } => write!(f, "could not access {}: {}", path, source),
+            Error::Io { path: None, source } => write!(f, "could not write output: {}", source),
+            Error::Parse {
+                path,
+                line,
+                column,
+                source,
+            } if *line == 0 => write!(f, "invalid data in {}: {}", path, source),
+            Error::Parse {
+                path,
+                line,
+                column,
+                source,
+            } => {
+                // serde_json appends the position itself, which is already shown.
+                let message = source.to_string();
+                let suffix = format!(" at line {} column {}", line, column);
+                write!(
+                    f,
+                    "invalid data in {} at line {}, column {}: {}",
+                    path,
+                    line,
+                    column,
+                    message.strip_suffix(&suffix).unwrap_or(&message)
+                )
+            }
+            Error::Version {
+                path,
+                found,
+                supported,
+            } => write!(
+                f,
+                "{} has format version {}, but only up to {} is supported",
+                path, found, supported
+            ),
+            Error::Http {
+                url,
+                status: Some(status),
+                ..
+            } => match StatusCode::from_u16(*status) {
+                Ok(status) => write!(f, "request to {} responded with status {}", url, status),
+                Err(_) => write!(f, "request to {} responded with status {}", url, status),
+            },
+            Error::Http {
+                url,
+                status: None,
+                reason,
+            } => match reason {
+                Some(reason) => write!(f, "request to {} failed: {}", url, reason),
+                None => write!(f, "request to {} failed", url),
+            },
+            Error::Serialize { what, source } => {
+                write!(f, "could not serialize data for {}: {}", what, source)
+            }
+            Error::Feed { url, reason } => write!(f, "invalid feed: {}: {}", url, reason),
+            Error::Delivery { to, reason } => write!(f, "could not deliver to {}: {}", to, reason),
+            Error::Input(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::Parse { source, .. } => Some(source),
+            Error::Serialize { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Terminal io, which has no path to report.
+impl From<io::Error> for Error {
+    fn from(source: io::Error) -> Self {
+        Error::Io { path: None, source }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_file_hints_doctor() {
+        let error = Error::io(
+            "/home/user/.rss-update-cli/config.json",
+            io::Error::from(io::ErrorKind::NotFound),
+        );
+        let message = error.to_string();
+        assert!(message.starts_with("could not access /home/user/.rss-update-cli/config.json"));
+        assert!(error.hint().unwrap().contains("rss-update doctor"));
+        assert_eq!(EXIT_IO, error.exit_code());
+    }
+
+    #[test]
+    fn test_parse_error_position() {
+        let source = serde_json::from_str::<Vec<String>>("[\n\"a\",\n]").unwrap_err();
+        let error = Error::parse("config.json", source);
+        assert_eq!(
+            "invalid data in config.json at line 3, column 1: trailing comma",
+            error.to_string()
+        );
+        assert_eq!(EXIT_PARSE, error.exit_code());
+    }
+
+    #[test]
+    fn test_exit_codes_are_distinct() {
+        let errors = [
+            Error::from(io::Error::from(io::ErrorKind::Other)),
+            Error::parse("f", serde_json::from_str::<u8>("").unwrap_err()),
+            Error::status("url", 404),
+            Error::feed("url", "not xml"),
+            Error::input("bad date"),
+        ];
+        let mut codes = errors.iter().map(Error::exit_code).collect::<Vec<_>>();
+        codes.sort_unstable();
+        codes.dedup();
+        assert_eq!(errors.len(), codes.len());
+        assert_eq!("bad date", errors[4].to_string());
+        assert!(errors[2].hint().unwrap().contains("edit --url"));
+        assert_eq!(
+            "request to url responded with status 404 Not Found",
+            errors[2].to_string()
+        );
+    }
+}
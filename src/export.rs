@@ -0,0 +1,261 @@
+use crate::config::Config;
+use crate::html::escape_html as escape;
+use crate::posts::{Post, PostStore};
+use crate::readlist::ReadList;
+use atom_syndication as atom;
+use chrono::{DateTime, Utc};
+use rss::extension::dublincore::DublinCoreExtension;
+
+const TITLE: &str = "rss-update";
+const DESCRIPTION: &str = "Posts of feeds tracked by rss-update.";
+
+/// Format of exported document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Format {
+    Atom,
+    Rss,
+}
+
+/// Stored posts of unread links, newest first. Links fetched before post
+/// metadata was stored are exported bare.
+pub(crate) fn unread(readlist: &ReadList, store: &PostStore) -> Vec<Post> {
+    let posts = readlist
+        .iter()
+        .flat_map(|(feed, links)| {
+            links
+                .iter()
+                .map(move |l| store.get(l).cloned().unwrap_or_else(|| Post::bare(feed, l)))
+        })
+        .collect();
+    newest_first(posts)
+}
+
+/// Stored posts published since date, read or not, newest first.
+pub(crate) fn since(store: &PostStore, since: DateTime<Utc>) -> Vec<Post> {
+    let posts = store
+        .values()
+        .filter(|p| p.published.is_some_and(|d| d >= since))
+        .cloned()
+        .collect();
+    newest_first(posts)
+}
+
+fn newest_first(mut posts: Vec<Post>) -> Vec<Post> {
+    posts.sort_by(|a, b| b.published.cmp(&a.published).then(a.link.cmp(&b.link)));
+    posts
+}
+
+fn feed_name<'a>(post: &'a Post, configs: &'a [Config]) -> &'a str {
+    configs
+        .iter()
+        .find(|c| c.feed == post.feed)
+        .map_or(post.feed.as_str(), Config::display_name)
+}
+
+// `atom_syndication` writes text of elements as is, so it is escaped here.
+fn atom_entry(post: &Post, configs: &[Config], now: DateTime<Utc>) -> atom::Entry {
+    let mut link = atom::Link::default();
+    link.set_href(post.link.clone());
+    let mut source_link = atom::Link::default();
+    source_link.set_href(post.feed.clone());
+    source_link.set_rel("self");
+    // Entries credit the feed they were found in, as atom:source.
+    let mut source = atom::Source::default();
+    source.set_id(escape(&post.feed));
+    source.set_title(escape(feed_name(post, configs)));
+    source.set_links(vec![source_link]);
+    source.set_updated(now.to_rfc3339());
+
+    let mut entry = atom::Entry::default();
+    entry.set_id(escape(&post.link));
+    entry.set_title(escape(post.title.as_deref().unwrap_or(&post.link)));
+    entry.set_updated(post.published.unwrap_or(now).to_rfc3339());
+    entry.set_published(post.published.map(|d| d.to_rfc3339()));
+    entry.set_links(vec![link]);
+    entry.set_source(source);
+    if let Some(author) = &post.author {
+        let mut person = atom::Person::default();
+        person.set_name(escape(author));
+        entry.set_authors(vec![person]);
+    }
+    entry.set_categories(
+        post.categories
+            .iter()
+            .map(|c| {
+                let mut category = atom::Category::default();
+                category.set_term(c.clone());
+                category
+            })
+            .collect::<Vec<_>>(),
+    );
+    if let Some(summary) = &post.summary {
+        let mut content = atom::Content::default();
+        content.set_value(escape(summary));
+        content.set_content_type("html".to_string());
+        entry.set_content(content);
+    }
+    entry
+}
+
+/// Atom 1.0 document of posts.
+pub(crate) fn atom(posts: &[Post], configs: &[Config], now: DateTime<Utc>) -> String {
+    let mut feed = atom::Feed::default();
+    feed.set_id("urn:rss-update:export");
+    feed.set_title(TITLE);
+    feed.set_subtitle(DESCRIPTION.to_string());
+    feed.set_updated(now.to_rfc3339());
+    feed.set_entries(
+        posts
+            .iter()
+            .map(|p| atom_entry(p, configs, now))
+            .collect::<Vec<_>>(),
+    );
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n{}",
+        feed.to_string()
+    )
+}
+
+fn rss_item(post: &Post, configs: &[Config]) -> rss::Item {
+    let mut guid = rss::Guid::default();
+    guid.set_value(post.link.clone());
+    guid.set_permalink(true);
+    // Items credit the feed they were found in, as rss source.
+    let mut source = rss::Source::default();
+    source.set_url(post.feed.clone());
+    source.set_title(feed_name(post, configs).to_string());
+
+    let mut item = rss::Item::default();
+    item.set_title(post.title.clone());
+    item.set_link(post.link.clone());
+    item.set_guid(guid);
+    item.set_description(post.summary.clone());
+    item.set_pub_date(post.published.map(|d| d.to_rfc2822()));
+    item.set_source(source);
+    item.set_categories(
+        post.categories
+            .iter()
+            .map(|c| {
+                let mut category = rss::Category::default();
+                category.set_name(c.clone());
+                category
+            })
+            .collect::<Vec<_>>(),
+    );
+    // Rss author is meant to be an email address, names go in dc:creator.
+    if let Some(author) = &post.author {
+        let mut dublin_core = DublinCoreExtension::default();
+        dublin_core.set_creators(vec![author.clone()]);
+        item.set_dublin_core_ext(dublin_core);
+    }
+    item
+}
+
+/// Rss 2.0 document of posts.
+pub(crate) fn rss(posts: &[Post], configs: &[Config], now: DateTime<Utc>) -> String {
+    let mut channel = rss::Channel::default();
+    channel.set_title(TITLE);
+    channel.set_link(env!("CARGO_PKG_HOMEPAGE"));
+    channel.set_description(DESCRIPTION);
+    channel.set_last_build_date(now.to_rfc2822());
+    channel.set_items(
+        posts
+            .iter()
+            .map(|p| rss_item(p, configs))
+            .collect::<Vec<_>>(),
+    );
+    channel.to_string()
+}
+
+pub(crate) fn document(
+    format: Format,
+    posts: &[Post],
+    configs: &[Config],
+    now: DateTime<Utc>,
+) -> String {
+    match format {
+        Format::Atom => atom(posts, configs, now),
+        Format::Rss => rss(posts, configs, now),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> PostStore {
+        let mut old = Post::bare("feed1", "post1");
+        old.published = Some(crate::parse_user_date("2021-01-01").unwrap());
+        let new = Post {
+            title: Some("New & shiny".to_string()),
+            summary: Some("<p>hello</p>".to_string()),
+            author: Some("ann".to_string()),
+            published: Some(crate::parse_user_date("2021-06-01").unwrap()),
+            categories: vec!["a&b".to_string()],
+            ..Post::bare("feed2", "post2?a=1&b=2")
+        };
+        vec![old, new]
+            .into_iter()
+            .map(|p| (p.link.clone(), p))
+            .collect()
+    }
+
+    fn configs() -> Vec<Config> {
+        vec![Config {
+            feed: "feed2".to_string(),
+            name: Some("Second".to_string()),
+            ..Config::default()
+        }]
+    }
+
+    #[test]
+    fn test_unread_and_since() {
+        let readlist = vec![(
+            "feed1".to_string(),
+            vec!["post1".to_string(), "post3".to_string()],
+        )]
+        .into_iter()
+        .collect();
+        let links = |posts: Vec<Post>| posts.into_iter().map(|p| p.link).collect::<Vec<_>>();
+        assert_eq!(vec!["post1", "post3"], links(unread(&readlist, &store())));
+        let since = since(&store(), crate::parse_user_date("2021-03-01").unwrap());
+        assert_eq!(vec!["post2?a=1&b=2"], links(since));
+    }
+
+    #[test]
+    fn test_atom() {
+        let now = Utc::now();
+        let posts = since(&store(), crate::parse_user_date("2021-01-01").unwrap());
+        let document = atom(&posts, &configs(), now);
+        let feed = document.parse::<atom::Feed>().unwrap();
+        assert_eq!(2, feed.entries().len());
+        let entry = &feed.entries()[0];
+        assert_eq!("New & shiny", entry.title());
+        assert_eq!("post2?a=1&b=2", entry.links()[0].href());
+        assert_eq!("post2?a=1&b=2", entry.id());
+        assert_eq!("a&b", entry.categories()[0].term());
+        assert_eq!(Some("<p>hello</p>"), entry.content().unwrap().value());
+        assert_eq!("ann", entry.authors()[0].name());
+        assert_eq!("Second", entry.source().unwrap().title());
+        assert_eq!("feed2", entry.source().unwrap().id());
+        assert_eq!("feed1", feed.entries()[1].source().unwrap().title());
+    }
+
+    #[test]
+    fn test_rss() {
+        let now = Utc::now();
+        let posts = since(&store(), crate::parse_user_date("2021-01-01").unwrap());
+        let document = rss(&posts, &configs(), now);
+        let channel = document.parse::<rss::Channel>().unwrap();
+        let item = &channel.items()[0];
+        assert_eq!(Some("New & shiny"), item.title());
+        assert_eq!(Some("<p>hello</p>"), item.description());
+        assert_eq!("feed2", item.source().unwrap().url());
+        assert_eq!(Some("Second"), item.source().unwrap().title());
+        assert_eq!(
+            vec!["ann".to_string()],
+            item.dublin_core_ext().unwrap().creators()
+        );
+        assert_eq!(Some("Tue, 1 Jun 2021 00:00:00 +0000"), item.pub_date());
+    }
+}
@@ -0,0 +1,212 @@
+use crate::auth::{self, Secrets};
+use crate::config::{self, ConfigList};
+use crate::error::Result;
+use crate::health::{self, Attempt};
+use crate::posts::{self, Post};
+use crate::readlist::{self, ReadList};
+use crate::search;
+use crate::settings::{self, Settings};
+use crate::starred;
+use crate::store;
+use std::path::PathBuf;
+
+/// Where tracked feeds, their unread posts and fetch history are kept.
+/// Implement it to keep them somewhere other than json files, e.g. in a
+/// database of your own service.
+pub trait FeedStore {
+    /// Locks store against writes of other users, like another process
+    /// sharing its files, till the returned guard is dropped. Stores not
+    /// shared with anyone can keep the default, which locks nothing.
+    fn lock(&self) -> Result<Box<dyn Send>> {
+        Ok(Box::new(()))
+    }
+
+    /// Tracked feeds.
+    fn configs(&self) -> Result<ConfigList>;
+
+    /// Replaces tracked feeds.
+    fn replace_configs(&self, configs: ConfigList) -> Result<()>;
+
+    fn settings(&self) -> Result<Settings>;
+
+    /// Secrets referred to by credentials of feeds, by key. None by
+    /// default, for stores of feeds without credentials kept in a file.
+    fn secrets(&self) -> Result<Secrets> {
+        Ok(Secrets::new())
+    }
+
+    /// Unread posts of every feed, feeds without any are left out.
+    fn unread(&self) -> Result<ReadList>;
+
+    /// Adds posts to the unread ones of their feeds.
+    fn add_unread(&self, feeds: ReadList) -> Result<()>;
+
+    /// Keeps metadata of posts, replacing posts already kept.
+    fn add_posts(&self, posts: Vec<Post>) -> Result<()>;
+
+    /// Records fetch attempts in history of their feeds.
+    fn record_attempts(&self, attempts: Vec<Attempt>) -> Result<()>;
+
+    /// Moves unread posts, stored posts, history and rules of a feed to its
+    /// new url. Tracked feeds are left as is.
+    fn rename_feed(&self, feed: &str, url: &str) -> Result<()>;
+}
+
+/// `FeedStore` over json files in a data directory, the one used by the cli.
+#[derive(Debug, Clone)]
+pub struct FileStore {
+    dir: PathBuf,
+}
+
+impl FileStore {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        FileStore { dir: dir.into() }
+    }
+
+    pub(crate) fn path(&self, file: &str) -> String {
+        self.dir.join(file).to_string_lossy().into_owned()
+    }
+
+    pub(crate) fn readlist(&self) -> Result<ReadList> {
+        let path = self.path(readlist::FILE);
+        store::init_versioned(&path, readlist::MIGRATIONS, &ReadList::new())?;
+        readlist::_get(&path)
+    }
+
+    pub(crate) fn replace_readlist(&self, readlist: &ReadList) -> Result<()> {
+        store::write_versioned(&self.path(readlist::FILE), readlist::MIGRATIONS, readlist)
+    }
+}
+
+/// Store in `~/.rss-update-cli`.
+impl Default for FileStore {
+    fn default() -> Self {
+        FileStore::new(crate::base_dir())
+    }
+}
+
+impl FeedStore for FileStore {
+    fn lock(&self) -> Result<Box<dyn Send>> {
+        Ok(Box::new(store::lock(&self.dir)?))
+    }
+
+    fn configs(&self) -> Result<ConfigList> {
+        let path = self.path(config::FILE);
+        store::init_versioned(&path, config::MIGRATIONS, &ConfigList::new())?;
+        config::_get(&path)
+    }
+
+    fn replace_configs(&self, configs: ConfigList) -> Result<()> {
+        config::_replace(&self.path(config::FILE), configs)?;
+        Ok(())
+    }
+
+    fn settings(&self) -> Result<Settings> {
+        settings::_get(&self.path(settings::FILE))
+    }
+
+    fn secrets(&self) -> Result<Secrets> {
+        auth::_get(&self.path(auth::FILE))
+    }
+
+    fn unread(&self) -> Result<ReadList> {
+        Ok(readlist::_unread(self.readlist()?))
+    }
+
+    fn add_unread(&self, feeds: ReadList) -> Result<()> {
+        self.replace_readlist(&readlist::_update(feeds, self.readlist()?))
+    }
+
+    fn add_posts(&self, new_posts: Vec<Post>) -> Result<()> {
+        let path = self.path(posts::FILE);
+        search::_add(&self.path(search::FILE), &new_posts)?;
+        posts::_replace(&path, posts::_update(new_posts, posts::_get(&path)?))?;
+        Ok(())
+    }
+
+    fn record_attempts(&self, attempts: Vec<Attempt>) -> Result<()> {
+        let path = self.path(health::FILE);
+        store::write(&path, &health::_update(attempts, health::_get(&path)?))
+    }
+
+    fn rename_feed(&self, feed: &str, url: &str) -> Result<()> {
+        self.replace_readlist(&readlist::_rename_feed(self.readlist()?, feed, url))?;
+
+        let path = self.path(posts::FILE);
+        let mut stored = posts::_get(&path)?;
+        for post in stored.values_mut().filter(|p| p.feed == feed) {
+            post.feed = url.to_string();
+        }
+        posts::_replace(&path, stored)?;
+
+        let path = self.path(starred::FILE);
+        starred::_replace(
+            &path,
+            starred::_rename_feed(starred::_get(&path)?, feed, url),
+        )?;
+
+        let path = self.path(health::FILE);
+        let mut history = health::_get(&path)?;
+        if let Some(entry) = history.remove(feed) {
+            history.insert(url.to_string(), entry);
+        }
+        store::write(&path, &history)?;
+
+        let path = self.path(settings::FILE);
+        let mut settings = settings::_get(&path)?;
+        for rule in &mut settings.rules {
+            if rule.feed.as_deref() == Some(feed) {
+                rule.feed = Some(url.to_string());
+            }
+        }
+        settings::_replace(&path, settings)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::{Action, Field, Rule};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_rename_feed() {
+        let dir = TempDir::new().unwrap();
+        let store = FileStore::new(dir.path());
+        store
+            .add_unread(
+                vec![("feed1".to_string(), vec!["post1".to_string()])]
+                    .into_iter()
+                    .collect(),
+            )
+            .unwrap();
+        store.add_posts(vec![Post::bare("feed1", "post1")]).unwrap();
+        settings::_replace(
+            &store.path(settings::FILE),
+            Settings {
+                rules: vec![Rule {
+                    feed: Some("feed1".to_string()),
+                    field: Field::Title,
+                    pattern: "a".to_string(),
+                    regex: false,
+                    action: Action::Skip,
+                }],
+                ..Settings::default()
+            },
+        )
+        .unwrap();
+
+        store.rename_feed("feed1", "feed2").unwrap();
+        assert_eq!(
+            vec!["feed2"],
+            store.unread().unwrap().keys().collect::<Vec<_>>()
+        );
+        let stored = posts::_get(&store.path(posts::FILE)).unwrap();
+        assert_eq!("feed2", stored["post1"].feed);
+        assert_eq!(
+            Some("feed2".to_string()),
+            store.settings().unwrap().rules[0].feed
+        );
+    }
+}
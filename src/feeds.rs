@@ -1,37 +1,150 @@
-use crate::config::ConfigList;
+use crate::article;
+use crate::auth::Secrets;
+use crate::config::{self, Config, ConfigList, Selection};
+use crate::error::{Error, Result};
+use crate::feed_store::FeedStore;
+use crate::fetcher::{self, Fetcher, Request};
+use crate::health::Attempt;
+use crate::network::Network;
+use crate::posts::{Enclosure, Post};
 use crate::readlist::ReadList;
+use crate::rules;
 use chrono::{DateTime, Utc};
-use futures::future;
-use std::error::Error;
+use futures::{future, stream, StreamExt};
+use reqwest::StatusCode;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::time::Instant;
 
-pub(crate) type Result<T> = std::result::Result<T, Box<dyn Error>>;
-pub(crate) type Posts = Vec<String>;
+pub(crate) type Posts = Vec<Post>;
+
+/// Redirects are followed by hand to know which of them were permanent.
+const MAX_REDIRECTS: usize = 10;
+
+/// Linked pages requested at once when fetching full content of posts.
+const MAX_ARTICLE_REQUESTS: usize = 4;
+
+/// Outcome of requesting a feed.
+#[derive(Debug)]
+pub(crate) struct Response {
+    /// Url the feed was finally served from.
+    pub(crate) url: String,
+    /// Url reached by following only permanent redirects, if feed has moved.
+    pub(crate) moved_to: Option<String>,
+    pub(crate) status: StatusCode,
+    pub(crate) bytes: u64,
+    pub(crate) posts: Posts,
+}
+
+fn status_code(url: &str, status: u16) -> Result<StatusCode> {
+    StatusCode::from_u16(status).map_err(|e| Error::feed(url, e))
+}
+
+/// Feed is gone for good or does not exist (anymore) at the url.
+fn is_gone(status: StatusCode) -> bool {
+    status == StatusCode::GONE || status == StatusCode::NOT_FOUND
+}
+
+fn is_permanent_redirect(status: StatusCode) -> bool {
+    status == StatusCode::MOVED_PERMANENTLY || status == StatusCode::PERMANENT_REDIRECT
+}
+
+fn post_from_entry(url: &str, entry: &atom_syndication::Entry) -> Post {
+    Post {
+        link: String::from(entry.links()[0].href()),
+        feed: url.to_string(),
+        title: Some(entry.title().to_string()),
+        summary: entry.summary().map(String::from),
+        author: entry.authors().first().map(|a| a.name().to_string()),
+        published: DateTime::parse_from_rfc3339(entry.updated())
+            .ok()
+            .map(|d| d.with_timezone(&Utc)),
+        categories: entry
+            .categories()
+            .iter()
+            .map(|c| c.term().to_string())
+            .collect(),
+        enclosures: entry
+            .links()
+            .iter()
+            .filter(|l| l.rel() == "enclosure")
+            .map(|l| Enclosure {
+                url: l.href().to_string(),
+                mime: l.mime_type().map(String::from),
+                length: l.length().and_then(|n| n.parse().ok()),
+                duration: None,
+            })
+            .collect(),
+        content: entry.content().and_then(|c| c.value()).map(String::from),
+        article: None,
+    }
+}
+
+fn post_from_item(url: &str, item: &rss::Item) -> Post {
+    Post {
+        link: String::from(item.link().unwrap()),
+        feed: url.to_string(),
+        title: item.title().map(String::from),
+        summary: item.description().map(String::from),
+        author: item.author().map(String::from).or_else(|| {
+            item.dublin_core_ext()
+                .and_then(|dc| dc.creators().first().cloned())
+        }),
+        published: item
+            .pub_date()
+            .and_then(|d| DateTime::parse_from_rfc2822(d).ok())
+            .map(|d| d.with_timezone(&Utc)),
+        categories: item
+            .categories()
+            .iter()
+            .map(|c| c.name().to_string())
+            .collect(),
+        enclosures: item
+            .enclosure()
+            .map(|e| Enclosure {
+                url: e.url().to_string(),
+                mime: Some(e.mime_type().to_string()).filter(|m| !m.is_empty()),
+                length: e.length().parse().ok().filter(|n| *n > 0),
+                duration: item
+                    .itunes_ext()
+                    .and_then(|i| i.duration())
+                    .map(String::from),
+            })
+            .into_iter()
+            .collect(),
+        content: item.content().map(String::from),
+        article: None,
+    }
+}
 
 fn new_posts_from_feed(
+    url: &str,
     feed: &atom_syndication::Feed,
     updated: Option<DateTime<Utc>>,
     peek_time: DateTime<Utc>,
 ) -> Posts {
     let updated = updated.unwrap_or_else(Utc::now);
-    let links = feed
+    let posts = feed
         .entries()
         .iter()
         .filter(|entry| {
             let date_time = DateTime::parse_from_rfc3339(entry.updated()).ok().unwrap();
             date_time > updated && date_time <= peek_time
         })
-        .map(|entry| String::from(entry.links()[0].href()))
+        .map(|entry| post_from_entry(url, entry))
         .collect::<Vec<_>>();
-    links
+    posts
 }
 
 fn new_posts_from_channel(
+    url: &str,
     channel: &rss::Channel,
     updated: Option<DateTime<Utc>>,
     peek_time: DateTime<Utc>,
 ) -> Posts {
     let updated = updated.unwrap_or_else(Utc::now);
-    let links = channel
+    let posts = channel
         .items()
         .iter()
         .filter(|item| {
@@ -40,44 +153,939 @@ fn new_posts_from_channel(
                 .unwrap();
             parsed_date > updated && parsed_date <= peek_time
         })
-        .map(|item| String::from(item.link().unwrap()))
+        .map(|item| post_from_item(url, item))
         .collect::<Vec<_>>();
-    links
+    posts
+}
+
+/// Last response to a request, after following its redirects.
+struct Followed {
+    /// Url the response finally came from.
+    url: reqwest::Url,
+    /// Url reached by following only permanent redirects, if any.
+    moved_to: Option<String>,
+    response: fetcher::Response,
+}
+
+/// Sends request following up to `MAX_REDIRECTS` redirects. Headers, which
+/// may carry credentials, are only sent while on the origin of its url.
+async fn follow<F: Fetcher>(fetcher: &F, request: &Request) -> Result<Followed> {
+    let url = request.url.as_str();
+    let mut current = reqwest::Url::parse(url).map_err(|e| Error::feed(url, e))?;
+    let origin = current.origin();
+    let request = |current: &reqwest::Url| {
+        let headers = if current.origin() == origin {
+            request.headers.clone()
+        } else {
+            vec![]
+        };
+        Request {
+            url: current.to_string(),
+            headers,
+            network: request.network.clone(),
+        }
+    };
+    let mut moved_to = None;
+    let mut permanent = true;
+    let mut response = fetcher.get(request(&current)).await?;
+    for _ in 0..MAX_REDIRECTS {
+        let status = status_code(url, response.status)?;
+        if !status.is_redirection() {
+            break;
+        }
+        let location = response
+            .location
+            .as_deref()
+            .ok_or_else(|| Error::feed(url, "redirected without location"))?;
+        current = current.join(location).map_err(|e| Error::feed(url, e))?;
+        permanent = permanent && is_permanent_redirect(status);
+        if permanent {
+            moved_to = Some(current.to_string());
+        }
+        response = fetcher.get(request(&current)).await?;
+    }
+    Ok(Followed {
+        url: current,
+        moved_to,
+        response,
+    })
 }
 
-async fn new_posts(
-    url: String,
+/// Requests feed following redirects, and parses posts published after
+/// `updated` and till `peek_time`. Feeds which are gone are not treated as
+/// error, but reported with their status.
+async fn new_posts<F: Fetcher>(
+    fetcher: &F,
+    request: Request,
     updated: Option<DateTime<Utc>>,
     peek_time: DateTime<Utc>,
-) -> Result<Posts> {
-    let data = reqwest::get(url.as_str()).await?.text().await?;
-    let new_posts: Vec<String> = match data.parse::<syndication::Feed>()? {
-        syndication::Feed::Atom(feed) => new_posts_from_feed(&feed, updated, peek_time),
-        syndication::Feed::RSS(channel) => new_posts_from_channel(&channel, updated, peek_time),
+) -> Result<Response> {
+    let Followed {
+        url: current,
+        moved_to,
+        response,
+    } = follow(fetcher, &request).await?;
+    let url = request.url;
+    let status = status_code(&url, response.status)?;
+    let mut fetched = Response {
+        url: current.to_string(),
+        moved_to,
+        status,
+        bytes: 0,
+        posts: Posts::new(),
+    };
+    if is_gone(status) {
+        return Ok(fetched);
+    }
+    if !status.is_success() {
+        return Err(Error::status(&url, status.as_u16()));
+    }
+    let data = response.body;
+    fetched.bytes = data.len() as u64;
+    let feed = data
+        .parse::<syndication::Feed>()
+        .map_err(|e| Error::feed(&url, e))?;
+    fetched.posts = match feed {
+        syndication::Feed::Atom(feed) => new_posts_from_feed(&url, &feed, updated, peek_time),
+        syndication::Feed::RSS(channel) => {
+            new_posts_from_channel(&url, &channel, updated, peek_time)
+        }
     };
-    Ok(new_posts)
+    Ok(fetched)
 }
 
-#[derive(Debug)]
+/// Requests page a post links to and extracts its main article.
+async fn article<F: Fetcher>(fetcher: &F, request: &Request) -> Result<Option<String>> {
+    let link = request.url.as_str();
+    let followed = follow(fetcher, request).await?;
+    if !status_code(link, followed.response.status)?.is_success() {
+        return Err(Error::status(link, followed.response.status));
+    }
+    Ok(article::extract(
+        &followed.response.body,
+        followed.url.as_str(),
+    ))
+}
+
+/// Request for url of feed, or a page it links to, carrying network
+/// settings of feed. Credentials are only carried to the origin of feed.
+fn request(url: &str, config: &Config, network: &Network, secrets: &Secrets) -> Result<Request> {
+    let headers = match &config.auth {
+        Some(auth) if same_origin(url, &config.feed) => auth.headers(secrets)?,
+        _ => vec![],
+    };
+    Ok(Request::new(url)
+        .with_headers(&headers)
+        .with_network(network.merge(config.network.as_ref())))
+}
+
+fn same_origin(a: &str, b: &str) -> bool {
+    match (reqwest::Url::parse(a), reqwest::Url::parse(b)) {
+        (Ok(a), Ok(b)) => a.origin() == b.origin(),
+        _ => false,
+    }
+}
+
+/// Secrets file is only read when a feed refers to it, so that a damaged
+/// one does not stop feeds without credentials from being fetched.
+fn secrets_of<S: FeedStore>(store: &S, configs: &[Config]) -> Result<Secrets> {
+    if configs.iter().any(|c| c.auth.is_some()) {
+        store.secrets()
+    } else {
+        Ok(Secrets::new())
+    }
+}
+
+/// Adds main article of linked page to new posts of feeds fetching full
+/// content. Posts whose page fails to load are kept without article.
+async fn add_articles<F: Fetcher>(
+    fetcher: &F,
+    configs: &[Config],
+    network: &Network,
+    secrets: &Secrets,
+    posts: &mut Posts,
+) {
+    let feeds = configs
+        .iter()
+        .filter(|c| c.full_content)
+        .map(|c| (c.feed.as_str(), c))
+        .collect::<HashMap<_, _>>();
+    let requests = posts
+        .iter()
+        .enumerate()
+        .filter_map(|(i, post)| {
+            let config = feeds.get(post.feed.as_str())?;
+            Some((i, request(&post.link, config, network, secrets).ok()?))
+        })
+        .collect::<Vec<_>>();
+    let articles = stream::iter(requests)
+        .map(|(i, request)| async move { (i, article(fetcher, &request).await) })
+        .buffer_unordered(MAX_ARTICLE_REQUESTS)
+        .collect::<Vec<_>>()
+        .await;
+    for (i, article) in articles {
+        posts[i].article = article.ok().flatten();
+    }
+}
+
+/// Counts consecutive gone responses of a feed, marking it dead once there
+/// are `dead_after` of them. Any other response revives a dead feed, e.g.
+/// when it is fetched by name after coming back.
+fn record_status(config: &mut Config, status: StatusCode, dead_after: u32) {
+    if is_gone(status) {
+        config.failures += 1;
+    } else {
+        config.failures = 0;
+    }
+    config.dead = config.failures >= dead_after;
+}
+
+/// Posts fetched for feeds, along with what happened to each feed.
+#[derive(Debug, Clone)]
 pub(crate) struct Context {
     pub(crate) feeds: ReadList,
     pub(crate) configs: ConfigList,
+    pub(crate) posts: Posts,
+    /// Feeds which moved permanently, along with their new url.
+    pub(crate) redirects: Vec<(String, String)>,
+    /// Feeds which could not be fetched, along with the reason.
+    pub(crate) failed: Vec<(String, String)>,
+    /// Record of every request made, to keep track of feed health.
+    pub(crate) attempts: Vec<Attempt>,
+}
+
+/// Requests feed, timing how long it took.
+async fn timed_new_posts<F: Fetcher>(
+    fetcher: &F,
+    config: &Config,
+    network: &Network,
+    secrets: &Secrets,
+    peek_time: DateTime<Utc>,
+) -> (Result<Response>, u64) {
+    let start = Instant::now();
+    let response = match request(&config.feed, config, network, secrets) {
+        Ok(request) => new_posts(fetcher, request, config.updated, peek_time).await,
+        Err(e) => Err(e),
+    };
+    let elapsed_ms = u64::try_from(start.elapsed().as_millis()).unwrap_or(u64::MAX);
+    (response, elapsed_ms)
 }
 
-pub(crate) async fn feeds_and_config(
+fn attempt(feed: &str, at: DateTime<Utc>, response: &Result<Response>, elapsed_ms: u64) -> Attempt {
+    let mut attempt = Attempt {
+        feed: feed.to_string(),
+        at,
+        status: None,
+        elapsed_ms,
+        bytes: 0,
+        error: None,
+        new_posts: 0,
+        oldest_post: None,
+        newest_post: None,
+    };
+    match response {
+        Ok(response) => {
+            attempt.status = Some(response.status.as_u16());
+            attempt.bytes = response.bytes;
+            if is_gone(response.status) {
+                attempt.error = Some(format!("responded with status {}", response.status));
+            }
+            attempt.new_posts = response.posts.len() as u32;
+            attempt.oldest_post = response.posts.iter().filter_map(|p| p.published).min();
+            attempt.newest_post = response.posts.iter().filter_map(|p| p.published).max();
+        }
+        Err(e) => attempt.error = Some(e.to_string()),
+    }
+    attempt
+}
+
+/// Fetches new posts of all configs. A feed failing does not stop others from
+/// being fetched, its cursor is just left where it was.
+async fn feeds_and_config<F: Fetcher>(
+    fetcher: &F,
     mut configs: ConfigList,
+    network: &Network,
+    secrets: &Secrets,
     peek_time: DateTime<Utc>,
+    dead_after: u32,
 ) -> Result<Context> {
     let feeds_futures = configs
         .iter()
-        .map(|c| new_posts(c.feed.clone(), c.updated, peek_time))
+        .map(|c| timed_new_posts(fetcher, c, network, secrets, peek_time))
         .collect::<Vec<_>>();
-    let new_posts = future::try_join_all(feeds_futures).await?;
+    let responses = future::join_all(feeds_futures).await;
 
-    let mut feeds = ReadList::new();
-    for (config, to_read) in configs.iter_mut().zip(new_posts.into_iter()) {
-        feeds.insert(config.feed.clone(), to_read);
+    let mut context = Context {
+        feeds: ReadList::new(),
+        configs: ConfigList::new(),
+        posts: Posts::new(),
+        redirects: vec![],
+        failed: vec![],
+        attempts: vec![],
+    };
+    for (config, (response, elapsed_ms)) in configs.iter_mut().zip(responses) {
+        context
+            .attempts
+            .push(attempt(&config.feed, peek_time, &response, elapsed_ms));
+        let mut response = match response {
+            Ok(response) => response,
+            Err(e) => {
+                context.failed.push((config.feed.clone(), e.to_string()));
+                continue;
+            }
+        };
+        record_status(config, response.status, dead_after);
+        if let Some(moved_to) = response.moved_to {
+            context.redirects.push((config.feed.clone(), moved_to));
+        }
+        if is_gone(response.status) {
+            let reason = format!(
+                "responded with status {} from {}",
+                response.status, response.url
+            );
+            context.failed.push((config.feed.clone(), reason));
+            continue;
+        }
+        context.feeds.insert(
+            config.feed.clone(),
+            response.posts.iter().map(|p| p.link.clone()).collect(),
+        );
+        context.posts.append(&mut response.posts);
         config.updated = Some(peek_time);
     }
-    Ok(Context { feeds, configs })
+    context.configs = configs;
+    Ok(context)
+}
+
+/// What a fetch would change for a single feed.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct Change {
+    pub feed: String,
+    pub cursor_from: Option<DateTime<Utc>>,
+    pub cursor_to: Option<DateTime<Utc>>,
+    pub added: Vec<String>,
+    pub marked_read: Vec<String>,
+    pub skipped: Vec<String>,
+    pub moved_to: Option<String>,
+    pub error: Option<String>,
+    pub dead: bool,
+}
+
+/// Compares configs before fetching with the fetched context, and the context
+/// left after applying rules, to describe what persisting it would change.
+pub(crate) fn changes(before: &[Config], fetched: &Context, filtered: &Context) -> Vec<Change> {
+    let links = |posts: &Posts, feed: &str| -> Vec<String> {
+        posts
+            .iter()
+            .filter(|p| p.feed == feed)
+            .map(|p| p.link.clone())
+            .collect()
+    };
+    before
+        .iter()
+        .zip(&filtered.configs)
+        .map(|(old, new)| {
+            let added = filtered.feeds.get(&old.feed).cloned().unwrap_or_default();
+            let kept = links(&filtered.posts, &old.feed);
+            let (skipped, kept_or_read): (Vec<_>, Vec<_>) = links(&fetched.posts, &old.feed)
+                .into_iter()
+                .partition(|l| !kept.contains(l));
+            Change {
+                feed: old.feed.clone(),
+                cursor_from: old.updated,
+                cursor_to: new.updated,
+                marked_read: kept_or_read
+                    .into_iter()
+                    .filter(|l| !added.contains(l))
+                    .collect(),
+                added,
+                skipped,
+                moved_to: filtered
+                    .redirects
+                    .iter()
+                    .find(|(feed, _)| *feed == old.feed)
+                    .map(|(_, url)| url.clone()),
+                error: filtered
+                    .failed
+                    .iter()
+                    .find(|(feed, _)| *feed == old.feed)
+                    .map(|(_, reason)| reason.clone()),
+                dead: new.dead && !old.dead,
+            }
+        })
+        .collect()
+}
+
+/// Fetches selected feeds among configs and applies rules to what was found.
+/// Returns selected configs along with context before and after rules.
+async fn fetch_selected<S: FeedStore, F: Fetcher>(
+    store: &S,
+    fetcher: &F,
+    configs: &[Config],
+    selection: &Selection,
+    now: DateTime<Utc>,
+) -> Result<(ConfigList, Context, Context)> {
+    let settings = store.settings()?;
+    let selected = config::select(configs, selection)?;
+    let secrets = secrets_of(store, &selected)?;
+    let fetched = feeds_and_config(
+        fetcher,
+        selected.clone(),
+        &settings.network,
+        &secrets,
+        now,
+        settings.dead_after,
+    )
+    .await?;
+    let filtered = rules::apply(&settings.rules, fetched.clone())?;
+    Ok((selected, fetched, filtered))
+}
+
+/// Fetches new posts of selected feeds and describes what storing them would
+/// change, without storing anything.
+pub async fn fetch_and_diff<S: FeedStore, F: Fetcher>(
+    store: &S,
+    fetcher: &F,
+    selection: &Selection,
+    now: DateTime<Utc>,
+) -> Result<Vec<Change>> {
+    let configs = store.configs()?;
+    let (selected, fetched, filtered) =
+        fetch_selected(store, fetcher, &configs, selection, now).await?;
+    Ok(changes(&selected, &fetched, &filtered))
+}
+
+/// Outcome of `fetch`.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct Fetched {
+    /// What changed for every selected feed.
+    pub changes: Vec<Change>,
+    /// Feeds whose permanent move was not followed, along with the reason.
+    pub unfollowed: Vec<(String, Error)>,
+}
+
+/// Fetches new posts of selected feeds and stores them as unread. Feeds
+/// which moved permanently are tracked at their new url from now on.
+pub async fn fetch<S: FeedStore, F: Fetcher>(
+    store: &S,
+    fetcher: &F,
+    selection: &Selection,
+    now: DateTime<Utc>,
+) -> Result<Fetched> {
+    let (selected, fetched, mut filtered) =
+        fetch_selected(store, fetcher, &store.configs()?, selection, now).await?;
+    let changes = changes(&selected, &fetched, &filtered);
+    let network = store.settings()?.network;
+    let secrets = secrets_of(store, &filtered.configs)?;
+    add_articles(
+        fetcher,
+        &filtered.configs,
+        &network,
+        &secrets,
+        &mut filtered.posts,
+    )
+    .await;
+    // Feeds are read again under lock, keeping ones added or removed by
+    // others while fetching.
+    let _lock = store.lock()?;
+    let configs = store.configs()?;
+    store.add_unread(filtered.feeds)?;
+    store.add_posts(filtered.posts)?;
+    store.record_attempts(filtered.attempts)?;
+    let mut configs = config::merge(configs, filtered.configs);
+    let mut unfollowed = vec![];
+    for (feed, url) in filtered.redirects {
+        match config::rename(configs.clone(), &feed, &url) {
+            Ok(renamed) => {
+                configs = renamed;
+                store.rename_feed(&feed, &url)?;
+            }
+            Err(e) => unfollowed.push((feed, e)),
+        }
+    }
+    store.replace_configs(configs)?;
+    Ok(Fetched {
+        changes,
+        unfollowed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feed_store::FileStore;
+    use crate::fetcher::Response as Fetched;
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+
+    /// Serves responses from memory, by url.
+    struct Stub(HashMap<String, Fetched>);
+
+    impl Fetcher for Stub {
+        async fn get(&self, request: Request) -> Result<Fetched> {
+            self.0
+                .get(&request.url)
+                .cloned()
+                .ok_or_else(|| Error::status(&request.url, 404))
+        }
+    }
+
+    /// Serves responses of a `Stub`, recording requests made.
+    struct Recorder(Stub, std::sync::Mutex<Vec<Request>>);
+
+    impl Fetcher for Recorder {
+        async fn get(&self, request: Request) -> Result<Fetched> {
+            self.1.lock().unwrap().push(request.clone());
+            self.0.get(request).await
+        }
+    }
+
+    fn channel(link: &str) -> String {
+        format!(
+            "<rss version=\"2.0\"><channel><title>t</title><link>l</link><description>d</description>\
+             <item><title>post</title><link>{}</link><pubDate>Tue, 01 Jun 2021 00:00:00 GMT</pubDate></item>\
+             </channel></rss>",
+            link
+        )
+    }
+
+    #[tokio::test]
+    async fn test_fetch() {
+        let dir = TempDir::new().unwrap();
+        let store = FileStore::new(dir.path());
+        let from = crate::parse_user_date("2021-01-01").unwrap();
+        let now = crate::parse_user_date("2021-07-01").unwrap();
+        let configs = ["https://a.com/feed", "https://b.com/feed"]
+            .iter()
+            .map(|feed| Config {
+                feed: feed.to_string(),
+                updated: Some(from),
+                ..Config::default()
+            })
+            .collect();
+        store.replace_configs(configs).unwrap();
+        let stub = Stub(
+            vec![
+                ("https://a.com/feed", Fetched::new(200, &channel("post1"))),
+                (
+                    "https://b.com/feed",
+                    Fetched::redirect(301, "https://c.com/feed"),
+                ),
+                ("https://c.com/feed", Fetched::new(200, &channel("post2"))),
+            ]
+            .into_iter()
+            .map(|(url, response)| (url.to_string(), response))
+            .collect(),
+        );
+
+        let changes = fetch_and_diff(&store, &stub, &Selection::default(), now)
+            .await
+            .unwrap();
+        assert_eq!(vec!["post1".to_string()], changes[0].added);
+        assert_eq!(Some("https://c.com/feed".to_string()), changes[1].moved_to);
+        assert!(store.unread().unwrap().is_empty());
+
+        let fetched = fetch(&store, &stub, &Selection::default(), now)
+            .await
+            .unwrap();
+        assert_eq!(changes, fetched.changes);
+        assert!(fetched.unfollowed.is_empty());
+        let unread = store.unread().unwrap();
+        assert_eq!(vec!["post2".to_string()], unread["https://c.com/feed"]);
+        let feeds = store
+            .configs()
+            .unwrap()
+            .into_iter()
+            .map(|c| c.feed)
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["https://a.com/feed", "https://c.com/feed"], feeds);
+    }
+
+    /// Serves responses of a `Stub`, editing feeds of store as if done by
+    /// another command while fetching.
+    struct Editor(Stub, FileStore);
+
+    impl Fetcher for Editor {
+        async fn get(&self, request: Request) -> Result<Fetched> {
+            let mut configs = self.1.configs()?;
+            configs[0].name = Some("A".to_string());
+            self.1.replace_configs(configs)?;
+            self.0.get(request).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_keeps_edits_made_while_fetching() {
+        let dir = TempDir::new().unwrap();
+        let store = FileStore::new(dir.path());
+        let from = crate::parse_user_date("2021-01-01").unwrap();
+        let now = crate::parse_user_date("2021-07-01").unwrap();
+        store
+            .replace_configs(vec![Config {
+                feed: "https://a.com/feed".to_string(),
+                updated: Some(from),
+                ..Config::default()
+            }])
+            .unwrap();
+        let stub = Stub(
+            vec![(
+                "https://a.com/feed".to_string(),
+                Fetched::new(200, &channel("post1")),
+            )]
+            .into_iter()
+            .collect(),
+        );
+
+        fetch(
+            &store,
+            &Editor(stub, store.clone()),
+            &Selection::default(),
+            now,
+        )
+        .await
+        .unwrap();
+        let configs = store.configs().unwrap();
+        assert_eq!(Some("A".to_string()), configs[0].name);
+        assert_eq!(Some(now), configs[0].updated);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_full_content() {
+        let dir = TempDir::new().unwrap();
+        let store = FileStore::new(dir.path());
+        let from = crate::parse_user_date("2021-01-01").unwrap();
+        let now = crate::parse_user_date("2021-07-01").unwrap();
+        let configs = [("https://a.com/feed", true), ("https://b.com/feed", false)]
+            .iter()
+            .map(|(feed, full_content)| Config {
+                feed: feed.to_string(),
+                updated: Some(from),
+                full_content: *full_content,
+                ..Config::default()
+            })
+            .collect();
+        store.replace_configs(configs).unwrap();
+        let page = "<html><body><nav><a href=\"/\">home</a></nav>\
+                    <div><p>The whole post, with all of its text.</p></div></body></html>";
+        let stub = Stub(
+            vec![
+                (
+                    "https://a.com/feed",
+                    Fetched::new(200, &channel("https://a.com/post")),
+                ),
+                ("https://a.com/post", Fetched::redirect(302, "/posts/1")),
+                ("https://a.com/posts/1", Fetched::new(200, page)),
+                (
+                    "https://b.com/feed",
+                    Fetched::new(200, &channel("https://b.com/post")),
+                ),
+                ("https://b.com/post", Fetched::new(200, page)),
+            ]
+            .into_iter()
+            .map(|(url, response)| (url.to_string(), response))
+            .collect(),
+        );
+
+        fetch(&store, &stub, &Selection::default(), now)
+            .await
+            .unwrap();
+        let posts = crate::posts::_get(&store.path(crate::posts::FILE)).unwrap();
+        assert_eq!(
+            Some("The whole post, with all of its text.".to_string()),
+            posts["https://a.com/post"].article
+        );
+        assert_eq!(None, posts["https://b.com/post"].article);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_auth() {
+        let dir = TempDir::new().unwrap();
+        let store = FileStore::new(dir.path());
+        let from = crate::parse_user_date("2021-01-01").unwrap();
+        let now = crate::parse_user_date("2021-07-01").unwrap();
+        let config = |feed: &str, token: crate::auth::Secret| Config {
+            feed: feed.to_string(),
+            updated: Some(from),
+            full_content: true,
+            auth: Some(crate::auth::Auth {
+                bearer: Some(token),
+                ..crate::auth::Auth::default()
+            }),
+            ..Config::default()
+        };
+        store
+            .replace_configs(vec![
+                config(
+                    "https://a.com/feed",
+                    crate::auth::Secret::File("a".to_string()),
+                ),
+                config(
+                    "https://b.com/feed",
+                    crate::auth::Secret::Env("RSS_UPDATE_TEST_UNSET_TOKEN".to_string()),
+                ),
+                config(
+                    "https://c.com/feed",
+                    crate::auth::Secret::File("a".to_string()),
+                ),
+            ])
+            .unwrap();
+        let secrets = vec![("a".to_string(), "t0ken".to_string())]
+            .into_iter()
+            .collect();
+        crate::auth::_replace(&store.path(crate::auth::FILE), &secrets).unwrap();
+        let stub = Stub(
+            vec![
+                ("https://a.com/feed", Fetched::redirect(302, "/feed.xml")),
+                (
+                    "https://a.com/feed.xml",
+                    Fetched::redirect(302, "https://cdn.com/feed.xml"),
+                ),
+                (
+                    "https://cdn.com/feed.xml",
+                    Fetched::new(200, &channel("https://tracker.com/post")),
+                ),
+                (
+                    "https://c.com/feed",
+                    Fetched::new(200, &channel("https://c.com/post")),
+                ),
+            ]
+            .into_iter()
+            .map(|(url, response)| (url.to_string(), response))
+            .collect(),
+        );
+        let recorder = Recorder(stub, Default::default());
+
+        let changes = fetch(&store, &recorder, &Selection::default(), now)
+            .await
+            .unwrap()
+            .changes;
+        assert_eq!(
+            vec!["https://tracker.com/post".to_string()],
+            changes[0].added
+        );
+        assert!(changes[1].error.as_ref().unwrap().contains("not set"));
+        let bearer = vec![("Authorization".to_string(), "Bearer t0ken".to_string())];
+        let headers = recorder
+            .1
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|r| (r.url.clone(), r.headers.clone()))
+            .collect::<HashMap<_, _>>();
+        // Article of a post on another host gets no credentials of its feed.
+        assert_eq!(
+            vec![
+                ("https://a.com/feed".to_string(), bearer.clone()),
+                ("https://a.com/feed.xml".to_string(), bearer.clone()),
+                ("https://cdn.com/feed.xml".to_string(), vec![]),
+                ("https://tracker.com/post".to_string(), vec![]),
+                ("https://c.com/feed".to_string(), bearer.clone()),
+                ("https://c.com/post".to_string(), bearer),
+            ]
+            .into_iter()
+            .collect::<HashMap<_, _>>(),
+            headers
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_network() {
+        let dir = TempDir::new().unwrap();
+        let store = FileStore::new(dir.path());
+        let now = crate::parse_user_date("2021-07-01").unwrap();
+        let settings = crate::settings::Settings {
+            network: Network {
+                proxy: Some("http://proxy:3128".to_string()),
+                ..Network::default()
+            },
+            ..crate::settings::Settings::default()
+        };
+        crate::settings::_replace(&store.path(crate::settings::FILE), settings).unwrap();
+        store
+            .replace_configs(vec![
+                Config {
+                    feed: "https://a.com/feed".to_string(),
+                    ..Config::default()
+                },
+                Config {
+                    feed: "https://intranet.corp/feed".to_string(),
+                    network: Some(Network {
+                        no_proxy: Some("intranet.corp".to_string()),
+                        insecure_hosts: vec!["intranet.corp".to_string()],
+                        ..Network::default()
+                    }),
+                    ..Config::default()
+                },
+            ])
+            .unwrap();
+        let recorder = Recorder(Stub(HashMap::new()), Default::default());
+
+        fetch_and_diff(&store, &recorder, &Selection::default(), now)
+            .await
+            .unwrap();
+        let networks = recorder
+            .1
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|r| (r.url.clone(), r.network.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (
+                    "https://a.com/feed".to_string(),
+                    Network {
+                        proxy: Some("http://proxy:3128".to_string()),
+                        ..Network::default()
+                    }
+                ),
+                (
+                    "https://intranet.corp/feed".to_string(),
+                    Network {
+                        proxy: Some("http://proxy:3128".to_string()),
+                        no_proxy: Some("intranet.corp".to_string()),
+                        insecure_hosts: vec!["intranet.corp".to_string()],
+                        ..Network::default()
+                    }
+                ),
+            ],
+            networks
+        );
+    }
+
+    #[test]
+    fn test_enclosures() {
+        let channel =
+            "<rss version=\"2.0\" xmlns:itunes=\"http://www.itunes.com/dtds/podcast-1.0.dtd\">\
+             <channel><title>t</title><link>l</link><description>d</description>\
+             <item><title>ep</title><link>https://pod.com/1</link>\
+             <enclosure url=\"https://cdn.com/1.mp3\" type=\"audio/mpeg\" length=\"1200\"/>\
+             <itunes:duration>42:10</itunes:duration></item></channel></rss>"
+                .parse::<rss::Channel>()
+                .unwrap();
+        let post = post_from_item("feed1", &channel.items()[0]);
+        assert_eq!(
+            vec![Enclosure {
+                url: "https://cdn.com/1.mp3".to_string(),
+                mime: Some("audio/mpeg".to_string()),
+                length: Some(1200),
+                duration: Some("42:10".to_string()),
+            }],
+            post.enclosures
+        );
+
+        let feed = "<feed xmlns=\"http://www.w3.org/2005/Atom\"><title>t</title><id>i</id>\
+             <updated>2021-06-01T00:00:00Z</updated><entry><title>ep</title><id>e</id>\
+             <updated>2021-06-01T00:00:00Z</updated><link href=\"https://pod.com/1\"/>\
+             <link rel=\"enclosure\" href=\"https://cdn.com/1.ogg\" type=\"audio/ogg\"/>\
+             </entry></feed>"
+            .parse::<atom_syndication::Feed>()
+            .unwrap();
+        let post = post_from_entry("feed1", &feed.entries()[0]);
+        assert_eq!("https://pod.com/1", post.link);
+        assert_eq!(
+            vec![Enclosure {
+                url: "https://cdn.com/1.ogg".to_string(),
+                mime: Some("audio/ogg".to_string()),
+                length: None,
+                duration: None,
+            }],
+            post.enclosures
+        );
+    }
+
+    #[test]
+    fn test_changes() {
+        let now = Utc::now();
+        let before = vec![
+            Config {
+                feed: "feed1".to_string(),
+                ..Config::default()
+            },
+            Config {
+                feed: "feed2".to_string(),
+                failures: 2,
+                ..Config::default()
+            },
+        ];
+        let mut after = before.clone();
+        after[0].updated = Some(now);
+        after[1].failures = 3;
+        after[1].dead = true;
+        let fetched = Context {
+            feeds: vec![(
+                "feed1".to_string(),
+                vec![
+                    "post1".to_string(),
+                    "post2".to_string(),
+                    "post3".to_string(),
+                ],
+            )]
+            .into_iter()
+            .collect(),
+            configs: after,
+            posts: vec![
+                Post::bare("feed1", "post1"),
+                Post::bare("feed1", "post2"),
+                Post::bare("feed1", "post3"),
+            ],
+            redirects: vec![("feed1".to_string(), "feed3".to_string())],
+            failed: vec![("feed2".to_string(), "gone".to_string())],
+            attempts: vec![],
+        };
+        let mut filtered = fetched.clone();
+        filtered.posts.remove(0);
+        filtered
+            .feeds
+            .insert("feed1".to_string(), vec!["post3".to_string()]);
+
+        let changes = changes(&before, &fetched, &filtered);
+        assert_eq!(
+            Change {
+                feed: "feed1".to_string(),
+                cursor_from: None,
+                cursor_to: Some(now),
+                added: vec!["post3".to_string()],
+                marked_read: vec!["post2".to_string()],
+                skipped: vec!["post1".to_string()],
+                moved_to: Some("feed3".to_string()),
+                error: None,
+                dead: false,
+            },
+            changes[0]
+        );
+        assert_eq!(Some("gone".to_string()), changes[1].error);
+        assert!(changes[1].dead);
+        assert!(changes[1].added.is_empty());
+    }
+
+    #[test]
+    fn test_record_status() {
+        let mut config = Config::default();
+        record_status(&mut config, StatusCode::NOT_FOUND, 2);
+        assert_eq!((1, false), (config.failures, config.dead));
+        record_status(&mut config, StatusCode::OK, 2);
+        assert_eq!((0, false), (config.failures, config.dead));
+        record_status(&mut config, StatusCode::GONE, 2);
+        record_status(&mut config, StatusCode::NOT_FOUND, 2);
+        assert_eq!((2, true), (config.failures, config.dead));
+        record_status(&mut config, StatusCode::GONE, 2);
+        assert_eq!((3, true), (config.failures, config.dead));
+        record_status(&mut config, StatusCode::OK, 2);
+        assert_eq!((0, false), (config.failures, config.dead));
+
+        let mut config = Config::default();
+        record_status(&mut config, StatusCode::NOT_FOUND, 1);
+        assert_eq!((1, true), (config.failures, config.dead));
+    }
+
+    #[test]
+    fn test_status_kinds() {
+        assert!(is_gone(StatusCode::GONE));
+        assert!(!is_gone(StatusCode::INTERNAL_SERVER_ERROR));
+        assert!(is_permanent_redirect(StatusCode::PERMANENT_REDIRECT));
+        assert!(!is_permanent_redirect(StatusCode::FOUND));
+    }
 }
@@ -0,0 +1,147 @@
+use crate::error::{Error, Result};
+use crate::network::Network;
+use reqwest::{header, Client};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+/// Request for a feed, or a page a feed links to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Request {
+    pub url: String,
+    /// Extra headers, like credentials of a private feed.
+    pub headers: Vec<(String, String)>,
+    /// Proxies and certificates to request url with.
+    pub network: Network,
+}
+
+impl Request {
+    pub fn new(url: &str) -> Self {
+        Request {
+            url: url.to_string(),
+            headers: vec![],
+            network: Network::default(),
+        }
+    }
+
+    pub fn with_headers(mut self, headers: &[(String, String)]) -> Self {
+        self.headers.extend_from_slice(headers);
+        self
+    }
+
+    pub fn with_network(mut self, network: Network) -> Self {
+        self.network = network;
+        self
+    }
+}
+
+/// Response to a `Request`. Redirects are returned as is, with their
+/// location, to let the caller tell permanent moves from temporary ones.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Response {
+    pub status: u16,
+    pub location: Option<String>,
+    pub body: String,
+}
+
+impl Response {
+    pub fn new(status: u16, body: &str) -> Self {
+        Response {
+            status,
+            location: None,
+            body: body.to_string(),
+        }
+    }
+
+    /// Redirect response pointing to location.
+    pub fn redirect(status: u16, location: &str) -> Self {
+        Response {
+            status,
+            location: Some(location.to_string()),
+            body: String::new(),
+        }
+    }
+}
+
+/// Makes http requests for feeds. Implement it to fetch through a client of
+/// your own, or to serve feeds from memory in tests.
+pub trait Fetcher {
+    /// Requests url without following redirects.
+    fn get(&self, request: Request) -> impl Future<Output = Result<Response>> + Send;
+}
+
+/// `Fetcher` over shared `reqwest` clients.
+#[derive(Debug, Clone)]
+pub struct HttpFetcher {
+    client: Client,
+    /// Clients of requests with network settings of their own, built once
+    /// first needed.
+    clients: Arc<Mutex<HashMap<(Network, bool), Client>>>,
+}
+
+impl HttpFetcher {
+    pub fn new() -> Result<Self> {
+        Ok(HttpFetcher {
+            client: build(&Network::default(), false)?,
+            clients: Arc::default(),
+        })
+    }
+
+    /// Client for request, which only accepts invalid certificates when
+    /// requesting one of the insecure hosts.
+    fn client(&self, request: &Request) -> Result<Client> {
+        let insecure = request.network.is_insecure(&request.url);
+        let network = Network {
+            insecure_hosts: vec![],
+            ..request.network.clone()
+        };
+        if network.is_default() && !insecure {
+            return Ok(self.client.clone());
+        }
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        let key = (network, insecure);
+        if let Some(client) = clients.get(&key) {
+            return Ok(client.clone());
+        }
+        let client = build(&key.0, insecure)?;
+        clients.insert(key, client.clone());
+        Ok(client)
+    }
+}
+
+fn build(network: &Network, insecure: bool) -> Result<Client> {
+    network
+        .builder(insecure)?
+        .build()
+        .map_err(|e| Error::http("http client", e))
+}
+
+impl Fetcher for HttpFetcher {
+    async fn get(&self, request: Request) -> Result<Response> {
+        let client = self.client(&request)?;
+        let url = request.url;
+        let mut builder = client.get(&url);
+        for (name, value) in &request.headers {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+        let response = builder.send().await.map_err(|e| Error::http(&url, e))?;
+        let status = response.status().as_u16();
+        let location = match response.headers().get(header::LOCATION) {
+            Some(location) => Some(
+                location
+                    .to_str()
+                    .map_err(|e| Error::feed(&url, e))?
+                    .to_string(),
+            ),
+            None => None,
+        };
+        let body = response.text().await.map_err(|e| Error::http(&url, e))?;
+        Ok(Response {
+            status,
+            location,
+            body,
+        })
+    }
+}
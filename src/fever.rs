@@ -0,0 +1,623 @@
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::feed_store::{FeedStore, FileStore};
+use crate::posts::{self, Post, PostStore};
+use crate::readlist::ReadList;
+use crate::starred::{self, Starred};
+use crate::store;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::env;
+use std::path::Path;
+
+/// Version of the Fever api implemented.
+const API_VERSION: u32 = 3;
+
+/// Most items answered per request, as in Fever.
+const ITEMS_PER_REQUEST: usize = 50;
+
+/// Name of the file in data directory.
+pub(crate) const FILE: &str = "fever.json";
+
+/// Parameters of a request, from both its query and form body.
+pub(crate) type Params = HashMap<String, String>;
+
+/// Credentials Fever clients log in with. Password is read from the
+/// environment variable named by `password_env`, to keep it out of settings.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Fever {
+    pub username: String,
+    pub password_env: String,
+}
+
+impl Fever {
+    /// Key clients send in place of credentials, md5 of `username:password`.
+    fn api_key(&self) -> Result<String> {
+        let password = env::var(&self.password_env).map_err(|_| {
+            Error::input(format!(
+                "environment variable {} is not set",
+                self.password_env
+            ))
+        })?;
+        let credentials = format!("{}:{}", self.username, password);
+        Ok(format!("{:x}", md5::compute(credentials)))
+    }
+}
+
+/// Numeric ids Fever clients know feeds, groups (tags) and items by. Ids are
+/// handed out in order and never reused, so that clients can sync items
+/// with `since_id`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Ids {
+    #[serde(default)]
+    feeds: HashMap<String, u64>,
+    #[serde(default)]
+    groups: HashMap<String, u64>,
+    #[serde(default)]
+    items: HashMap<String, u64>,
+}
+
+pub(crate) fn _get(path: &str) -> Result<Ids> {
+    if !Path::new(path).is_file() {
+        return Ok(Ids::default());
+    }
+    store::read(path)
+}
+
+/// State requests are answered from. Only readlist, starred posts and ids
+/// are changed.
+pub(crate) struct Data {
+    pub(crate) configs: Vec<Config>,
+    pub(crate) readlist: ReadList,
+    pub(crate) starred: Starred,
+    pub(crate) posts: PostStore,
+    pub(crate) ids: Ids,
+}
+
+/// Hands out ids to keys which have none yet, after the highest one given.
+fn assign(ids: &mut HashMap<String, u64>, keys: impl IntoIterator<Item = String>) {
+    let mut next = ids.values().max().map_or(1, |id| id + 1);
+    for key in keys {
+        ids.entry(key).or_insert_with(|| {
+            next += 1;
+            next - 1
+        });
+    }
+}
+
+fn assign_ids(data: &mut Data) {
+    assign(
+        &mut data.ids.feeds,
+        data.configs.iter().map(|c| c.feed.clone()),
+    );
+    assign(&mut data.ids.groups, tags(&data.configs));
+    // Oldest posts first, so that new posts get higher ids than old ones.
+    let mut stored = data.posts.values().collect::<Vec<_>>();
+    stored.sort_by(|a, b| a.published.cmp(&b.published).then(a.link.cmp(&b.link)));
+    assign(
+        &mut data.ids.items,
+        stored.into_iter().map(|p| p.link.clone()),
+    );
+    let mut listed = data
+        .readlist
+        .values()
+        .chain(data.starred.values())
+        .flatten()
+        .cloned()
+        .collect::<Vec<_>>();
+    listed.sort();
+    assign(&mut data.ids.items, listed);
+}
+
+fn tags(configs: &[Config]) -> BTreeSet<String> {
+    configs.iter().flat_map(|c| c.tags.clone()).collect()
+}
+
+/// Known posts along with their ids, ordered by id. Readlist and starred
+/// entries fetched before post metadata was stored are bare.
+fn items(data: &Data) -> Vec<(u64, Post)> {
+    let mut posts = data.posts.clone();
+    for (feed, links) in data.readlist.iter().chain(&data.starred) {
+        for link in links {
+            posts
+                .entry(link.clone())
+                .or_insert_with(|| Post::bare(feed, link));
+        }
+    }
+    let mut items = posts
+        .into_iter()
+        .filter_map(|(link, post)| data.ids.items.get(&link).map(|id| (*id, post)))
+        .collect::<Vec<_>>();
+    items.sort_by_key(|(id, _)| *id);
+    items
+}
+
+fn unread(data: &Data) -> HashSet<&String> {
+    data.readlist.values().flatten().collect()
+}
+
+fn id_list<'a>(ids: impl Iterator<Item = &'a u64>) -> String {
+    let mut ids = ids.collect::<Vec<_>>();
+    ids.sort();
+    ids.iter()
+        .map(|id| id.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn feeds_groups(data: &Data) -> Value {
+    tags(&data.configs)
+        .iter()
+        .map(|tag| {
+            let feeds = data
+                .configs
+                .iter()
+                .filter(|c| c.tags.contains(tag))
+                .filter_map(|c| data.ids.feeds.get(&c.feed));
+            json!({"group_id": data.ids.groups[tag], "feed_ids": id_list(feeds)})
+        })
+        .collect()
+}
+
+fn groups(data: &Data) -> Value {
+    tags(&data.configs)
+        .iter()
+        .map(|tag| json!({"id": data.ids.groups[tag], "title": tag}))
+        .collect()
+}
+
+fn feeds(data: &Data) -> Value {
+    data.configs
+        .iter()
+        .map(|c| {
+            json!({
+                "id": data.ids.feeds[&c.feed],
+                "favicon_id": 0,
+                "title": c.display_name(),
+                "url": c.feed,
+                "site_url": c.feed,
+                "is_spark": 0,
+                "last_updated_on_time": c.updated.map_or(0, |d| d.timestamp()),
+            })
+        })
+        .collect()
+}
+
+fn ids_param(params: &Params, key: &str) -> Option<Vec<u64>> {
+    params.get(key).map(|ids| {
+        ids.split(',')
+            .filter_map(|id| id.trim().parse().ok())
+            .collect()
+    })
+}
+
+fn id_param(params: &Params, key: &str) -> Option<u64> {
+    params.get(key).and_then(|id| id.parse().ok())
+}
+
+/// Items selected by `with_ids`, before `max_id` (newest first) or after
+/// `since_id`, at most `ITEMS_PER_REQUEST` of them.
+fn select_items(params: &Params, items: Vec<(u64, Post)>) -> Vec<(u64, Post)> {
+    if let Some(ids) = ids_param(params, "with_ids") {
+        return items
+            .into_iter()
+            .filter(|(id, _)| ids.contains(id))
+            .take(ITEMS_PER_REQUEST)
+            .collect();
+    }
+    if let Some(max) = id_param(params, "max_id") {
+        return items
+            .into_iter()
+            .rev()
+            .filter(|(id, _)| *id < max)
+            .take(ITEMS_PER_REQUEST)
+            .collect();
+    }
+    let since = id_param(params, "since_id").unwrap_or(0);
+    items
+        .into_iter()
+        .filter(|(id, _)| *id > since)
+        .take(ITEMS_PER_REQUEST)
+        .collect()
+}
+
+fn items_response(params: &Params, data: &Data) -> (Value, usize) {
+    let unread = unread(data);
+    let saved = starred::links(&data.starred);
+    let items = items(data);
+    let total = items.len();
+    let selected = select_items(params, items)
+        .into_iter()
+        .map(|(id, post)| {
+            json!({
+                "id": id,
+                "feed_id": data.ids.feeds.get(&post.feed).copied().unwrap_or(0),
+                "title": post.title.as_deref().unwrap_or(&post.link),
+                "author": post.author.as_deref().unwrap_or_default(),
+                "html": post.content.as_deref().or(post.summary.as_deref()).unwrap_or_default(),
+                "url": post.link,
+                "is_saved": if saved.contains(&post.link) { 1 } else { 0 },
+                "is_read": if unread.contains(&post.link) { 0 } else { 1 },
+                "created_on_time": post.published.map_or(0, |d| d.timestamp()),
+            })
+        })
+        .collect();
+    (selected, total)
+}
+
+/// Marks posts matching predicate read, leaving the rest as is.
+fn mark_read_where(data: &mut Data, read: impl Fn(&Post) -> bool) {
+    let posts = &data.posts;
+    for (feed, links) in &mut data.readlist {
+        links.retain(|link| {
+            let post = posts
+                .get(link)
+                .cloned()
+                .unwrap_or_else(|| Post::bare(feed, link));
+            !read(&post)
+        });
+    }
+}
+
+/// Applies `mark` request. Fever marks feeds and groups read only up to the
+/// `before` time the client last refreshed at, posts without a date count as
+/// older than that. Group 0 is every feed. Saved items are starred posts.
+fn mark(params: &Params, data: &mut Data) {
+    let id = match id_param(params, "id") {
+        Some(id) => id,
+        None => return,
+    };
+    let before = params
+        .get("before")
+        .and_then(|b| b.parse::<i64>().ok())
+        .unwrap_or(i64::MAX);
+    let is_before = |post: &Post| post.published.is_none_or(|d| d.timestamp() <= before);
+    let link = data
+        .ids
+        .items
+        .iter()
+        .find(|(_, item)| **item == id)
+        .map(|(link, _)| link.clone());
+    let feed = data
+        .ids
+        .feeds
+        .iter()
+        .find(|(_, feed)| **feed == id)
+        .map(|(feed, _)| feed.clone());
+    let group = data
+        .ids
+        .groups
+        .iter()
+        .find(|(_, group)| **group == id)
+        .map(|(tag, _)| tag.clone());
+    let kind = params.get("mark").map(String::as_str);
+    let mark_as = params.get("as").map(String::as_str);
+    match (kind, mark_as) {
+        (Some("item"), Some("read")) => {
+            if let Some(link) = link {
+                mark_read_where(data, |post| post.link == link);
+            }
+        }
+        (Some("item"), Some("unread")) => {
+            if let Some(post) = link.and_then(|link| data.posts.get(&link).cloned()) {
+                let links = data.readlist.entry(post.feed).or_default();
+                if !links.contains(&post.link) {
+                    links.push(post.link);
+                }
+            }
+        }
+        (Some("item"), Some("saved")) => {
+            let item = items(data).into_iter().find(|(item, _)| *item == id);
+            if let Some((_, post)) = item {
+                data.starred = starred::_star(data.starred.clone(), &post.feed, &post.link);
+            }
+        }
+        (Some("item"), Some("unsaved")) => {
+            if let Some(link) = link {
+                data.starred = starred::_unstar(data.starred.clone(), &link);
+            }
+        }
+        (Some("feed"), Some("read")) => {
+            if let Some(feed) = feed {
+                mark_read_where(data, |post| post.feed == feed && is_before(post));
+            }
+        }
+        (Some("group"), Some("read")) => {
+            let feeds = if id == 0 {
+                data.configs.iter().map(|c| c.feed.clone()).collect()
+            } else if let Some(tag) = group {
+                crate::config::feeds_with_tags(&data.configs, &[tag])
+            } else {
+                HashSet::new()
+            };
+            mark_read_where(data, |post| feeds.contains(&post.feed) && is_before(post));
+        }
+        _ => {}
+    }
+}
+
+/// Answers Fever api request. Clients are authorized by api key alone, and
+/// ask for any of groups, feeds, items and id lists in a single request.
+pub(crate) fn api(params: &Params, api_key: &str, data: &mut Data, now: DateTime<Utc>) -> Value {
+    let mut response = Map::new();
+    response.insert("api_version".to_string(), json!(API_VERSION));
+    let authorized = params.get("api_key").is_some_and(|key| {
+        crate::auth::constant_time_eq(&key.to_ascii_lowercase(), &api_key.to_ascii_lowercase())
+    });
+    response.insert("auth".to_string(), json!(authorized as u8));
+    if !authorized {
+        return Value::Object(response);
+    }
+    response.insert("last_refreshed_on_time".to_string(), json!(now.timestamp()));
+    assign_ids(data);
+    if params.contains_key("mark") {
+        mark(params, data);
+    }
+    if params.contains_key("groups") {
+        response.insert("groups".to_string(), groups(data));
+    }
+    if params.contains_key("feeds") {
+        response.insert("feeds".to_string(), feeds(data));
+    }
+    if params.contains_key("groups") || params.contains_key("feeds") {
+        response.insert("feeds_groups".to_string(), feeds_groups(data));
+    }
+    if params.contains_key("favicons") {
+        response.insert("favicons".to_string(), json!([]));
+    }
+    if params.contains_key("links") {
+        response.insert("links".to_string(), json!([]));
+    }
+    if params.contains_key("items") {
+        let (items, total) = items_response(params, data);
+        response.insert("items".to_string(), items);
+        response.insert("total_items".to_string(), json!(total));
+    }
+    if params.contains_key("unread_item_ids") {
+        let unread = unread(data);
+        let ids = data
+            .ids
+            .items
+            .iter()
+            .filter(|(link, _)| unread.contains(link))
+            .map(|(_, id)| id);
+        response.insert("unread_item_ids".to_string(), json!(id_list(ids)));
+    }
+    if params.contains_key("saved_item_ids") {
+        let saved = starred::links(&data.starred);
+        let ids = data
+            .ids
+            .items
+            .iter()
+            .filter(|(link, _)| saved.contains(*link))
+            .map(|(_, id)| id);
+        response.insert("saved_item_ids".to_string(), json!(id_list(ids)));
+    }
+    Value::Object(response)
+}
+
+/// Answers request from data files in dir, storing read and saved state
+/// and ids it changed.
+pub(crate) fn request(dir: &Path, params: &Params, now: DateTime<Utc>) -> Result<Value> {
+    let store = FileStore::new(dir);
+    let fever = store
+        .settings()?
+        .fever
+        .ok_or_else(|| Error::input("fever api is not set up, add `fever` to settings.json"))?;
+    let path = store.path(FILE);
+    let mut data = Data {
+        configs: store.configs()?,
+        readlist: store.readlist()?,
+        starred: starred::_get(&store.path(starred::FILE))?,
+        posts: posts::_get(&store.path(posts::FILE))?,
+        ids: _get(&path)?,
+    };
+    let (readlist, starred, ids) = (
+        data.readlist.clone(),
+        data.starred.clone(),
+        data.ids.clone(),
+    );
+    let response = api(params, &fever.api_key()?, &mut data, now);
+    if data.ids != ids {
+        store::write(&path, &data.ids)?;
+    }
+    if data.readlist != readlist {
+        store.replace_readlist(&data.readlist)?;
+    }
+    if data.starred != starred {
+        starred::_replace(&store.path(starred::FILE), data.starred)?;
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::{self, Settings};
+    use tempfile::TempDir;
+
+    const KEY: &str = "0123abcd";
+
+    fn post(feed: &str, link: &str, published: &str) -> Post {
+        Post {
+            title: Some(link.to_uppercase()),
+            published: Some(crate::parse_user_date(published).unwrap()),
+            ..Post::bare(feed, link)
+        }
+    }
+
+    fn data() -> Data {
+        let config = |feed: &str, tags: &[&str]| Config {
+            feed: feed.to_string(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            ..Config::default()
+        };
+        Data {
+            configs: vec![config("feed1", &["rust"]), config("feed2", &[])],
+            readlist: vec![
+                (
+                    "feed1".to_string(),
+                    vec!["post1".to_string(), "post3".to_string()],
+                ),
+                ("feed2".to_string(), vec!["post2".to_string()]),
+            ]
+            .into_iter()
+            .collect(),
+            starred: Starred::new(),
+            posts: vec![
+                post("feed1", "post1", "2021-06-02"),
+                post("feed2", "post2", "2021-06-01"),
+            ]
+            .into_iter()
+            .map(|p| (p.link.clone(), p))
+            .collect(),
+            ids: Ids::default(),
+        }
+    }
+
+    fn params(query: &[(&str, &str)]) -> Params {
+        let mut params = query
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<Params>();
+        params.insert("api".to_string(), String::new());
+        params.insert("api_key".to_string(), KEY.to_string());
+        params
+    }
+
+    #[test]
+    fn test_auth() {
+        let mut params = params(&[("items", "")]);
+        params.insert("api_key".to_string(), "wrong".to_string());
+        let response = api(&params, KEY, &mut data(), Utc::now());
+        assert_eq!(json!({"api_version": 3, "auth": 0}), response);
+    }
+
+    #[test]
+    fn test_feeds_and_items() {
+        let mut data = data();
+        let now = Utc::now();
+        let response = api(
+            &params(&[("feeds", ""), ("groups", "")]),
+            KEY,
+            &mut data,
+            now,
+        );
+        assert_eq!(json!([{"id": 1, "title": "rust"}]), response["groups"]);
+        assert_eq!(
+            json!([{"group_id": 1, "feed_ids": "1"}]),
+            response["feeds_groups"]
+        );
+        assert_eq!("feed2", response["feeds"][1]["url"]);
+
+        // Stored posts get ids oldest first, bare readlist entries after them.
+        let response = api(&params(&[("items", "")]), KEY, &mut data, now);
+        let items = response["items"].as_array().unwrap();
+        let links = items.iter().map(|i| &i["url"]).collect::<Vec<_>>();
+        assert_eq!(vec!["post2", "post1", "post3"], links);
+        assert_eq!(json!(2), items[0]["feed_id"]);
+        assert_eq!(json!(3), response["total_items"]);
+
+        let response = api(
+            &params(&[("items", ""), ("since_id", "2")]),
+            KEY,
+            &mut data,
+            now,
+        );
+        assert_eq!(json!(3), response["items"][0]["id"]);
+        let response = api(
+            &params(&[("items", ""), ("max_id", "3")]),
+            KEY,
+            &mut data,
+            now,
+        );
+        assert_eq!(json!("post1"), response["items"][0]["url"]);
+        let response = api(
+            &params(&[("items", ""), ("with_ids", "1,3")]),
+            KEY,
+            &mut data,
+            now,
+        );
+        assert_eq!(2, response["items"].as_array().unwrap().len());
+    }
+
+    #[test]
+    fn test_mark() {
+        let mut data = data();
+        let now = Utc::now();
+        let unread = |data: &mut Data, mark: &[(&str, &str)]| {
+            let mut query = mark.to_vec();
+            query.push(("unread_item_ids", ""));
+            api(&params(&query), KEY, data, now)["unread_item_ids"].clone()
+        };
+        assert_eq!(json!("1,2,3"), unread(&mut data, &[]));
+        assert_eq!(
+            json!("1,3"),
+            unread(&mut data, &[("mark", "item"), ("as", "read"), ("id", "2")])
+        );
+        assert_eq!(
+            json!("1,2,3"),
+            unread(
+                &mut data,
+                &[("mark", "item"), ("as", "unread"), ("id", "2")]
+            )
+        );
+        // Only posts published before the given time, or without a date.
+        let before = crate::parse_user_date("2021-06-01").unwrap().timestamp();
+        let before = before.to_string();
+        let group = [("mark", "group"), ("as", "read"), ("id", "0")];
+        let mut query = group.to_vec();
+        query.push(("before", &before));
+        assert_eq!(json!("2"), unread(&mut data, &query));
+
+        // Read posts can be saved too.
+        let saved = [("mark", "item"), ("as", "saved"), ("id", "1")];
+        let mut query = saved.to_vec();
+        query.push(("saved_item_ids", ""));
+        let response = api(&params(&query), KEY, &mut data, now);
+        assert_eq!(json!("1"), response["saved_item_ids"]);
+        assert_eq!(vec!["post2"], data.starred["feed2"]);
+        let items = api(&params(&[("items", "")]), KEY, &mut data, now)["items"].clone();
+        assert_eq!(json!(1), items[0]["is_saved"]);
+        assert_eq!(json!(1), items[0]["is_read"]);
+    }
+
+    #[test]
+    fn test_request() {
+        let dir = TempDir::new().unwrap();
+        let store = FileStore::new(dir.path());
+        let var = "RSS_UPDATE_TEST_FEVER_PASSWORD";
+        env::set_var(var, "secret");
+        let fever = Fever {
+            username: "ann".to_string(),
+            password_env: var.to_string(),
+        };
+        settings::_replace(
+            &store.path(settings::FILE),
+            Settings {
+                fever: Some(fever.clone()),
+                ..Settings::default()
+            },
+        )
+        .unwrap();
+        store
+            .add_unread(
+                vec![("feed1".to_string(), vec!["post1".to_string()])]
+                    .into_iter()
+                    .collect(),
+            )
+            .unwrap();
+        let key = format!("{:x}", md5::compute("ann:secret"));
+        assert_eq!(key, fever.api_key().unwrap());
+
+        let mut params = params(&[("mark", "item"), ("as", "read"), ("id", "1")]);
+        params.insert("api_key".to_string(), key);
+        let response = request(dir.path(), &params, Utc::now()).unwrap();
+        assert_eq!(json!(1), response["auth"]);
+        assert!(store.unread().unwrap().is_empty());
+        let ids = _get(&store.path(FILE)).unwrap();
+        assert_eq!(Some(&1), ids.items.get("post1"));
+    }
+}
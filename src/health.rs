@@ -0,0 +1,298 @@
+use crate::config::ConfigList;
+use crate::error::Result;
+use crate::store;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Fetch history of feeds, keyed by feed url.
+pub(crate) type HealthStore = HashMap<String, History>;
+
+/// Name of the file in data directory.
+pub(crate) const FILE: &str = "health.json";
+
+/// Returns path where fetch history should reside.
+pub(crate) fn health_path() -> String {
+    let health_path = Path::new(&crate::base_dir()).join(FILE);
+    String::from(health_path.to_str().unwrap())
+}
+
+/// Single request of a feed made by the fetch pipeline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Attempt {
+    pub feed: String,
+    pub at: DateTime<Utc>,
+    pub status: Option<u16>,
+    pub elapsed_ms: u64,
+    pub bytes: u64,
+    pub error: Option<String>,
+    pub new_posts: u32,
+    pub oldest_post: Option<DateTime<Utc>>,
+    pub newest_post: Option<DateTime<Utc>>,
+}
+
+/// Accumulated fetch history of a feed.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct History {
+    pub(crate) first_fetch: Option<DateTime<Utc>>,
+    pub(crate) last_fetch: Option<DateTime<Utc>>,
+    pub(crate) last_success: Option<DateTime<Utc>>,
+    pub(crate) last_error: Option<String>,
+    pub(crate) last_error_at: Option<DateTime<Utc>>,
+    pub(crate) consecutive_failures: u32,
+    pub(crate) status: Option<u16>,
+    pub(crate) response_ms: Option<u64>,
+    pub(crate) bytes: Option<u64>,
+    pub(crate) posts_seen: u32,
+    pub(crate) first_post: Option<DateTime<Utc>>,
+    pub(crate) last_post: Option<DateTime<Utc>>,
+}
+
+impl History {
+    fn record(&mut self, attempt: Attempt) {
+        self.first_fetch.get_or_insert(attempt.at);
+        self.last_fetch = Some(attempt.at);
+        self.status = attempt.status;
+        self.response_ms = Some(attempt.elapsed_ms);
+        self.bytes = Some(attempt.bytes);
+        match attempt.error {
+            Some(error) => {
+                self.consecutive_failures += 1;
+                self.last_error = Some(error);
+                self.last_error_at = Some(attempt.at);
+            }
+            None => {
+                self.consecutive_failures = 0;
+                self.last_success = Some(attempt.at);
+            }
+        }
+        self.posts_seen += attempt.new_posts;
+        self.first_post = self.first_post.into_iter().chain(attempt.oldest_post).min();
+        self.last_post = self.last_post.into_iter().chain(attempt.newest_post).max();
+    }
+
+    /// Average time between posts seen so far.
+    pub(crate) fn posting_interval(&self) -> Option<Duration> {
+        match (self.first_post, self.last_post) {
+            (Some(first), Some(last)) if self.posts_seen > 1 => {
+                Some((last - first) / (self.posts_seen - 1) as i32)
+            }
+            _ => None,
+        }
+    }
+}
+
+pub(crate) fn setup() -> Result<()> {
+    if store::init(&health_path(), &HealthStore::new())? {
+        println!("creating health path.");
+    } else {
+        println!("health file already exists.");
+    }
+    Ok(())
+}
+
+/// Reads fetch history. Missing file means nothing was recorded yet.
+pub(crate) fn get() -> Result<HealthStore> {
+    _get(&health_path())
+}
+
+pub(crate) fn _get(path: &str) -> Result<HealthStore> {
+    if !Path::new(path).is_file() {
+        return Ok(HealthStore::new());
+    }
+    store::read(path)
+}
+
+pub(crate) fn _update(attempts: Vec<Attempt>, mut store: HealthStore) -> HealthStore {
+    for attempt in attempts {
+        store
+            .entry(attempt.feed.clone())
+            .or_default()
+            .record(attempt);
+    }
+    store
+}
+
+/// Overall state of a feed, worst first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum State {
+    Dead,
+    Erroring,
+    Dormant,
+    Unknown,
+    Healthy,
+}
+
+#[derive(Debug)]
+pub(crate) struct Report {
+    pub(crate) feed: String,
+    pub(crate) name: String,
+    pub(crate) state: State,
+    pub(crate) suggestion: Option<&'static str>,
+    pub(crate) history: History,
+}
+
+fn state(
+    dead: bool,
+    history: Option<&History>,
+    now: DateTime<Utc>,
+    dormant_after: Duration,
+) -> State {
+    let history = match history {
+        _ if dead => return State::Dead,
+        Some(history) => history,
+        None => return State::Unknown,
+    };
+    if history.consecutive_failures > 0 {
+        return State::Erroring;
+    }
+    let quiet_since = history.last_post.or(history.first_fetch);
+    match quiet_since {
+        Some(last_post) if now - last_post > dormant_after => State::Dormant,
+        _ => State::Healthy,
+    }
+}
+
+fn suggestion(state: State) -> Option<&'static str> {
+    match state {
+        State::Dead => Some("feed is gone, fix its url with `edit --url` or `remove` it"),
+        State::Erroring => Some("check the url in a browser, fetch will keep retrying"),
+        State::Dormant => Some("feed went quiet, consider `edit --disable` or `remove`"),
+        State::Unknown => Some("not fetched since health tracking started, run `fetch`"),
+        State::Healthy => None,
+    }
+}
+
+/// Builds health report of tracked feeds, worst first. Disabled feeds are
+/// left out since they are not fetched.
+pub(crate) fn report(
+    configs: ConfigList,
+    store: &HealthStore,
+    now: DateTime<Utc>,
+    dormant_after: Duration,
+) -> Vec<Report> {
+    let mut reports = configs
+        .into_iter()
+        .filter(|c| !c.disabled)
+        .map(|c| {
+            let history = store.get(&c.feed);
+            let state = state(c.dead, history, now, dormant_after);
+            Report {
+                name: c.display_name().to_string(),
+                feed: c.feed,
+                state,
+                suggestion: suggestion(state),
+                history: history.cloned().unwrap_or_default(),
+            }
+        })
+        .collect::<Vec<_>>();
+    reports.sort_by(|a, b| a.state.cmp(&b.state).then(a.feed.cmp(&b.feed)));
+    reports
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn date(date: &str) -> DateTime<Utc> {
+        crate::parse_user_date(date).unwrap()
+    }
+
+    fn attempt(feed: &str, at: &str, error: Option<&str>, posts: Vec<&str>) -> Attempt {
+        let posts = posts.into_iter().map(date).collect::<Vec<_>>();
+        Attempt {
+            feed: feed.to_string(),
+            at: date(at),
+            status: Some(if error.is_some() { 500 } else { 200 }),
+            elapsed_ms: 120,
+            bytes: 2048,
+            error: error.map(String::from),
+            new_posts: posts.len() as u32,
+            oldest_post: posts.iter().min().copied(),
+            newest_post: posts.iter().max().copied(),
+        }
+    }
+
+    #[test]
+    fn test_health_path() {
+        assert!(health_path().starts_with('/'));
+    }
+
+    #[test]
+    fn test_update() {
+        let attempts = vec![
+            attempt(
+                "feed1",
+                "2021-01-01",
+                None,
+                vec!["2020-12-01", "2020-12-11"],
+            ),
+            attempt("feed1", "2021-01-02", Some("timeout"), vec![]),
+            attempt("feed1", "2021-01-03", Some("timeout"), vec![]),
+            attempt("feed2", "2021-01-03", None, vec![]),
+        ];
+        let store = _update(attempts, HealthStore::new());
+        let history = &store["feed1"];
+        assert_eq!(2, history.consecutive_failures);
+        assert_eq!(Some(date("2021-01-01")), history.last_success);
+        assert_eq!(Some("timeout".to_string()), history.last_error);
+        assert_eq!(Some(Duration::days(10)), history.posting_interval());
+
+        let store = _update(
+            vec![attempt("feed1", "2021-01-04", None, vec!["2020-12-21"])],
+            store,
+        );
+        let history = &store["feed1"];
+        assert_eq!(0, history.consecutive_failures);
+        assert_eq!(3, history.posts_seen);
+        assert_eq!(Some(date("2020-12-21")), history.last_post);
+        assert_eq!(None, store["feed2"].posting_interval());
+    }
+
+    #[test]
+    fn test_report() {
+        let config = |feed: &str, dead: bool| Config {
+            feed: feed.to_string(),
+            dead,
+            ..Config::default()
+        };
+        let configs = vec![
+            config("healthy", false),
+            config("dormant", false),
+            config("erroring", false),
+            config("dead", true),
+            config("unknown", false),
+            config("silent", false),
+        ];
+        let store = _update(
+            vec![
+                attempt("healthy", "2021-06-01", None, vec!["2021-05-20"]),
+                attempt("dormant", "2021-06-01", None, vec!["2020-01-01"]),
+                attempt("silent", "2021-01-01", None, vec![]),
+                attempt("silent", "2021-06-01", None, vec![]),
+                attempt("erroring", "2021-06-01", Some("dns error"), vec![]),
+            ],
+            HealthStore::new(),
+        );
+        let reports = report(configs, &store, date("2021-06-02"), Duration::days(90));
+        let states = reports
+            .iter()
+            .map(|r| (r.feed.as_str(), r.state))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("dead", State::Dead),
+                ("erroring", State::Erroring),
+                ("dormant", State::Dormant),
+                ("silent", State::Dormant),
+                ("unknown", State::Unknown),
+                ("healthy", State::Healthy),
+            ],
+            states
+        );
+        assert!(reports[5].suggestion.is_none());
+    }
+}
@@ -0,0 +1,104 @@
+use crate::posts::Post;
+
+/// Characters of a summary kept when listing posts.
+const SUMMARY_CHARS: usize = 300;
+
+/// Text of html fragment, with tags dropped and common entities decoded.
+pub(crate) fn strip_tags(html: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                text.push(' ');
+            }
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Escapes text to be used in html text or quoted attribute values.
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Anchor of text linking to href. Links feeds make up are only followed
+/// if they are http or https, others like `javascript:` are shown as text.
+pub(crate) fn link(href: &str, text: &str) -> String {
+    let web = reqwest::Url::parse(href).is_ok_and(|u| matches!(u.scheme(), "http" | "https"));
+    if web {
+        format!(
+            "<a href=\"{}\">{}</a>",
+            escape_html(href),
+            escape_html(text)
+        )
+    } else {
+        escape_html(text)
+    }
+}
+
+/// Plain text of summary, cut to `SUMMARY_CHARS`.
+pub(crate) fn summary(post: &Post) -> Option<String> {
+    let text = strip_tags(post.summary.as_deref()?);
+    if text.is_empty() {
+        return None;
+    }
+    match text.char_indices().nth(SUMMARY_CHARS) {
+        Some((end, _)) => Some(format!("{}...", text[..end].trim_end())),
+        None => Some(text),
+    }
+}
+
+pub(crate) fn title(post: &Post) -> &str {
+    post.title.as_deref().unwrap_or(&post.link)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_tags() {
+        assert_eq!(
+            "a < b & c",
+            strip_tags("<p>a &lt; <b>b</b>&nbsp;&amp;\n c</p>")
+        );
+    }
+
+    #[test]
+    fn test_link() {
+        assert_eq!(
+            "<a href=\"https://a.com/?a=1&amp;b=&quot;\">a &lt; b</a>",
+            link("https://a.com/?a=1&b=\"", "a < b")
+        );
+        assert_eq!("post", link("javascript:alert(1)", "post"));
+        assert_eq!("post", link("JavaScript:alert(1)", "post"));
+        assert_eq!("post", link("/relative", "post"));
+    }
+
+    #[test]
+    fn test_summary_is_cut() {
+        let long = "word ".repeat(100);
+        let cut = summary(&Post {
+            summary: Some(long),
+            ..Post::bare("feed", "post")
+        })
+        .unwrap();
+        assert!(cut.ends_with("word..."));
+        assert!(cut.len() <= SUMMARY_CHARS + 3);
+    }
+}
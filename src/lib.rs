@@ -0,0 +1,92 @@
+//! Track rss/atom feeds and keep a list of their unread posts.
+//!
+//! This crate backs the `rss-update` cli, and can be embedded in other tools
+//! to reuse its fetch pipeline: feeds are requested through a [`Fetcher`],
+//! and tracked feeds, unread posts and fetch history are kept in a
+//! [`FeedStore`]. [`HttpFetcher`] and [`FileStore`] are the ones used by the
+//! cli, either can be replaced by an implementation of your own.
+//!
+//! ```no_run
+//! use rss_update::{FileStore, HttpFetcher, Selection};
+//!
+//! # async fn run() -> rss_update::Result<()> {
+//! let store = FileStore::new("/var/lib/feeds");
+//! let fetcher = HttpFetcher::new()?;
+//! let changes = rss_update::fetch_and_diff(&store, &fetcher, &Selection::default(), chrono::Utc::now()).await?;
+//! for change in changes {
+//!     println!("{}: {} new posts", change.feed, change.added.len());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Items exported here follow semver. While on 0.x, breaking changes bump
+//! the minor version. The `cli` module is not part of the public api.
+
+pub(crate) mod article;
+pub(crate) mod auth;
+#[doc(hidden)]
+pub mod cli;
+pub(crate) mod config;
+pub(crate) mod digest;
+pub(crate) mod display;
+pub(crate) mod doctor;
+pub(crate) mod download;
+pub(crate) mod error;
+pub(crate) mod export;
+pub(crate) mod feed_store;
+pub(crate) mod feeds;
+pub(crate) mod fetcher;
+pub(crate) mod fever;
+pub(crate) mod health;
+pub(crate) mod html;
+pub(crate) mod network;
+pub(crate) mod notify;
+pub(crate) mod onboarding;
+pub(crate) mod opml;
+pub(crate) mod posts;
+pub(crate) mod readlist;
+pub(crate) mod render;
+pub(crate) mod repair;
+pub(crate) mod rules;
+pub(crate) mod search;
+pub(crate) mod serve;
+pub(crate) mod settings;
+pub(crate) mod starred;
+pub(crate) mod store;
+pub(crate) mod terminal;
+
+pub use auth::{Auth, Basic, Secret, Secrets};
+pub use config::{Config as FeedConfig, ConfigList, Selection};
+pub use digest::{Digest, Mailer, SmtpSecurity};
+pub use download::Download;
+pub use error::{Error, Result};
+pub use feed_store::{FeedStore, FileStore};
+pub use feeds::{fetch, fetch_and_diff, Change, Fetched};
+pub use fetcher::{Fetcher, HttpFetcher, Request, Response};
+pub use fever::Fever;
+pub use health::Attempt;
+pub use network::Network;
+pub use notify::{Notifier, Sink};
+pub use posts::{Enclosure, Post};
+pub use readlist::ReadList;
+pub use rules::{Action, Field, Rule};
+pub use settings::Settings;
+
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use std::path::Path;
+
+/// Data directory of the cli.
+pub(crate) fn base_dir() -> String {
+    let base_path = Path::new(&dirs::home_dir().unwrap()).join(".rss-update-cli");
+    String::from(base_path.to_str().unwrap())
+}
+
+pub(crate) const USER_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Parses user provided date in `USER_DATE_FORMAT` as start of that day.
+pub(crate) fn parse_user_date(date: &str) -> Result<DateTime<Utc>> {
+    let date = NaiveDate::parse_from_str(date, USER_DATE_FORMAT)
+        .map_err(|_| Error::input(format!("invalid date: {}, expected YYYY-MM-DD", date)))?;
+    Ok(Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap()))
+}
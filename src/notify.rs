@@ -0,0 +1,402 @@
+use crate::config::{self, Config};
+use crate::error::{Error, Result};
+use crate::feeds::Change;
+use crate::posts::{self, Post};
+use crate::settings;
+use crate::terminal;
+use reqwest::{header, Client};
+use serde::{Deserialize, Serialize};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// Titles listed in a desktop notification before the rest are counted.
+const DESKTOP_TITLES: usize = 5;
+
+/// Time a webhook or command gets, so that a hung one does not keep
+/// fetching from finishing.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where new posts are sent after fetching.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Sink {
+    /// Desktop notification through `notify-send`.
+    Desktop,
+    /// Json POST per post, body rendered from template. Placeholders like
+    /// `{{title}}` are replaced with json escaped fields of the post.
+    Webhook {
+        url: String,
+        #[serde(default = "default_template")]
+        template: String,
+    },
+    /// Runs program, writing new posts to its stdin as a json array.
+    Command {
+        program: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+}
+
+/// Template understood by Slack and Mattermost. Discord expects `content`
+/// in place of `text`.
+fn default_template() -> String {
+    r#"{"text": "{{name}}: {{title}} {{link}}"}"#.to_string()
+}
+
+/// Notifier stored in settings. Notifiers without feed and tag are sent new
+/// posts of every feed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Notifier {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub feed: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    #[serde(flatten)]
+    pub sink: Sink,
+}
+
+impl Notifier {
+    fn matches(&self, config: Option<&Config>) -> bool {
+        let feed = config.map(|c| c.feed.as_str());
+        let tags = config.map(|c| c.tags.as_slice()).unwrap_or_default();
+        self.feed.as_deref().is_none_or(|f| Some(f) == feed)
+            && self.tag.as_ref().is_none_or(|t| tags.contains(t))
+    }
+}
+
+/// Json string content of value, without surrounding quotes.
+fn escape(value: &str) -> String {
+    let quoted = serde_json::Value::String(value.to_string()).to_string();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+/// Renders webhook template for post. Name is that of the feed, or its url.
+pub(crate) fn render(template: &str, post: &Post, name: &str) -> String {
+    let fields = [
+        ("title", post.title.as_deref().unwrap_or(&post.link)),
+        ("link", post.link.as_str()),
+        ("feed", post.feed.as_str()),
+        ("name", name),
+        ("author", post.author.as_deref().unwrap_or_default()),
+        ("summary", post.summary.as_deref().unwrap_or_default()),
+    ];
+    fields
+        .iter()
+        .fold(template.to_string(), |body, (field, value)| {
+            body.replace(&format!("{{{{{}}}}}", field), &escape(value))
+        })
+}
+
+async fn desktop(posts: &[&Post]) -> Result<()> {
+    let summary = format!("rss-update: {} new posts", posts.len());
+    let mut body = posts
+        .iter()
+        .take(DESKTOP_TITLES)
+        .map(|p| p.title.as_deref().unwrap_or(&p.link))
+        .collect::<Vec<_>>()
+        .join("\n");
+    if posts.len() > DESKTOP_TITLES {
+        body.push_str(&format!("\nand {} more", posts.len() - DESKTOP_TITLES));
+    }
+    run(
+        Command::new("notify-send").arg(summary).arg(body),
+        "notify-send",
+        None,
+        TIMEOUT,
+    )
+    .await
+}
+
+async fn webhook(
+    client: &Client,
+    url: &str,
+    template: &str,
+    posts: &[&Post],
+    configs: &[Config],
+) -> Result<()> {
+    for post in posts {
+        let config = configs.iter().find(|c| c.feed == post.feed);
+        let name = config.map_or(post.feed.as_str(), Config::display_name);
+        let response = client
+            .post(url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(render(template, post, name))
+            .send()
+            .await
+            .map_err(|e| Error::http(url, e))?;
+        if !response.status().is_success() {
+            return Err(Error::status(url, response.status().as_u16()));
+        }
+    }
+    Ok(())
+}
+
+/// Runs command to completion, writing input to its stdin if given. Command
+/// still running after timeout is killed.
+async fn run(
+    command: &mut Command,
+    program: &str,
+    input: Option<&[u8]>,
+    timeout: Duration,
+) -> Result<()> {
+    let mut child = command
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| Error::io(program, e))?;
+    let stdin = child.stdin.take();
+    let completed = async {
+        if let (Some(input), Some(mut stdin)) = (input, stdin) {
+            stdin
+                .write_all(input)
+                .await
+                .map_err(|e| Error::io(program, e))?;
+        }
+        child.wait().await.map_err(|e| Error::io(program, e))
+    };
+    let status = tokio::time::timeout(timeout, completed)
+        .await
+        .map_err(|_| {
+            Error::input(format!(
+                "{} did not finish within {} seconds",
+                program,
+                timeout.as_secs_f64()
+            ))
+        })??;
+    if !status.success() {
+        return Err(Error::input(format!("{} exited with {}", program, status)));
+    }
+    Ok(())
+}
+
+async fn send(sink: &Sink, client: &Client, posts: &[&Post], configs: &[Config]) -> Result<()> {
+    match sink {
+        Sink::Desktop => desktop(posts).await,
+        Sink::Webhook { url, template } => webhook(client, url, template, posts, configs).await,
+        Sink::Command { program, args } => {
+            let input = serde_json::to_vec(posts).map_err(|e| Error::serialize(program, e))?;
+            run(
+                Command::new(program).args(args),
+                program,
+                Some(&input),
+                TIMEOUT,
+            )
+            .await
+        }
+    }
+}
+
+/// Sends new posts to every notifier matching their feeds. A notifier failing
+/// does not stop others, its error is returned along with the rest.
+pub(crate) async fn notify(
+    notifiers: &[Notifier],
+    posts: &[Post],
+    configs: &[Config],
+) -> Vec<Error> {
+    let client = match Client::builder().timeout(TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => return vec![Error::http("http client", e)],
+    };
+    let mut errors = vec![];
+    for notifier in notifiers {
+        let matched = posts
+            .iter()
+            .filter(|p| notifier.matches(configs.iter().find(|c| c.feed == p.feed)))
+            .collect::<Vec<_>>();
+        if matched.is_empty() {
+            continue;
+        }
+        if let Err(e) = send(&notifier.sink, &client, &matched, configs).await {
+            errors.push(e);
+        }
+    }
+    errors
+}
+
+/// Sends posts added by fetch to notifiers configured in settings, telling
+/// about ones failing on stderr.
+pub(crate) async fn notify_added(changes: &[Change]) -> Result<()> {
+    let notifiers = settings::get()?.notifiers;
+    if notifiers.is_empty() {
+        return Ok(());
+    }
+    let stored = posts::get()?;
+    let added = changes
+        .iter()
+        .flat_map(|c| c.added.iter().map(move |link| (c, link)))
+        .map(|(c, link)| {
+            stored
+                .get(link)
+                .cloned()
+                .unwrap_or_else(|| Post::bare(&c.feed, link))
+        })
+        .collect::<Vec<_>>();
+    for e in notify(&notifiers, &added, &config::get()?).await {
+        eprintln!("failed to notify: {}", terminal::sanitize(&e.to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    fn post(feed: &str, link: &str, title: &str) -> Post {
+        Post {
+            title: Some(title.to_string()),
+            ..Post::bare(feed, link)
+        }
+    }
+
+    fn config(feed: &str, tags: &[&str]) -> Config {
+        Config {
+            feed: feed.to_string(),
+            name: Some(format!("{} name", feed)),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            ..Config::default()
+        }
+    }
+
+    /// Serves http requests with status, sending their bodies over the channel.
+    fn stub(status: &'static str) -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                        length = value.trim().parse().unwrap();
+                    }
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                write!(stream, "HTTP/1.1 {}\r\ncontent-length: 0\r\n\r\n", status).unwrap();
+                let _ = sender.send(String::from_utf8(body).unwrap());
+            }
+        });
+        (url, receiver)
+    }
+
+    #[test]
+    fn test_render_escapes_fields() {
+        let post = post("feed1", "post1", "say \"hi\"\n");
+        assert_eq!(
+            r#"{"content": "say \"hi\"\n post1 by  from feed"}"#,
+            render(
+                r#"{"content": "{{title}} {{link}} by {{author}} from {{name}}"}"#,
+                &post,
+                "feed"
+            )
+        );
+    }
+
+    #[test]
+    fn test_matches() {
+        let configs = [config("feed1", &["rust"]), config("feed2", &[])];
+        let notifier = |feed: Option<&str>, tag: Option<&str>| Notifier {
+            feed: feed.map(String::from),
+            tag: tag.map(String::from),
+            sink: Sink::Desktop,
+        };
+        assert!(notifier(None, None).matches(Some(&configs[1])));
+        assert!(notifier(None, Some("rust")).matches(Some(&configs[0])));
+        assert!(!notifier(None, Some("rust")).matches(Some(&configs[1])));
+        assert!(notifier(Some("feed2"), None).matches(Some(&configs[1])));
+        assert!(!notifier(Some("feed2"), None).matches(None));
+    }
+
+    #[tokio::test]
+    async fn test_notify_webhook() {
+        let (url, bodies) = stub("200 OK");
+        let (failing, _) = stub("500 Internal Server Error");
+        let configs = [config("feed1", &["rust"]), config("feed2", &[])];
+        let posts = [post("feed1", "post1", "one"), post("feed2", "post2", "two")];
+        let notifiers = [
+            Notifier {
+                feed: None,
+                tag: Some("rust".to_string()),
+                sink: Sink::Webhook {
+                    url,
+                    template: default_template(),
+                },
+            },
+            Notifier {
+                feed: Some("feed2".to_string()),
+                tag: None,
+                sink: Sink::Webhook {
+                    url: failing,
+                    template: default_template(),
+                },
+            },
+        ];
+
+        let errors = notify(&notifiers, &posts, &configs).await;
+        match errors.as_slice() {
+            [Error::Http {
+                status: Some(500), ..
+            }] => {}
+            other => panic!("expected status error, got {:?}", other),
+        }
+        assert_eq!(
+            r#"{"text": "feed1 name: one post1"}"#,
+            bodies.recv().unwrap()
+        );
+        assert!(bodies.try_recv().is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_notify_command() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let out = dir.path().join("posts.json");
+        let notifiers = [Notifier {
+            feed: None,
+            tag: None,
+            sink: Sink::Command {
+                program: "sh".to_string(),
+                args: vec!["-c".to_string(), format!("cat > {}", out.display())],
+            },
+        }];
+        let posts = [post("feed1", "post1", "one")];
+        assert!(notify(&notifiers, &posts, &[]).await.is_empty());
+        let written: Vec<Post> =
+            serde_json::from_str(&std::fs::read_to_string(out).unwrap()).unwrap();
+        assert_eq!(posts.to_vec(), written);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_run_timeout() {
+        let started = std::time::Instant::now();
+        let result = run(
+            Command::new("sleep").arg("5"),
+            "sleep",
+            None,
+            Duration::from_millis(100),
+        )
+        .await;
+        assert!(result.unwrap_err().to_string().contains("did not finish"));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
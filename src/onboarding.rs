@@ -0,0 +1,152 @@
+use crate::config::{Config, ConfigList};
+use crate::error::Result;
+use crate::settings::Settings;
+use chrono::{DateTime, Utc};
+use std::io::{BufRead, Write};
+use std::str::FromStr;
+
+/// Answers given during interactive setup.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Onboarding {
+    pub(crate) opml: Option<String>,
+    pub(crate) feeds: ConfigList,
+    pub(crate) settings: Settings,
+}
+
+/// Asks question and returns trimmed answer. End of input reads as empty
+/// answer so that every question falls back to its default.
+fn prompt<R: BufRead, W: Write>(input: &mut R, output: &mut W, question: &str) -> Result<String> {
+    write!(output, "{}: ", question)?;
+    output.flush()?;
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    Ok(answer.trim().to_string())
+}
+
+/// Asks for a number until a valid one or nothing (meaning default) is given.
+fn prompt_number<R, W, T>(
+    input: &mut R,
+    output: &mut W,
+    question: &str,
+    default: T,
+    min: T,
+) -> Result<T>
+where
+    R: BufRead,
+    W: Write,
+    T: FromStr + std::fmt::Display + Copy + PartialOrd,
+{
+    loop {
+        let answer = prompt(input, output, &format!("{} [{}]", question, default))?;
+        if answer.is_empty() {
+            return Ok(default);
+        }
+        match answer.parse() {
+            Ok(number) if number >= min => return Ok(number),
+            Ok(_) => writeln!(output, "{} is less than {}.", answer, min)?,
+            Err(_) => writeln!(output, "{} is not a valid number.", answer)?,
+        }
+    }
+}
+
+/// Walks user through importing feeds from another reader, adding a few
+/// feeds by hand and picking settings.
+pub(crate) fn ask<R: BufRead, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    settings: Settings,
+    now: DateTime<Utc>,
+) -> Result<Onboarding> {
+    let opml = prompt(input, output, "OPML file to import (empty to skip)")?;
+    let mut feeds = ConfigList::new();
+    loop {
+        let feed = prompt(input, output, "Feed url to track (empty to finish)")?;
+        if feed.is_empty() {
+            break;
+        }
+        let tags = prompt(input, output, "Tags for feed (comma separated)")?;
+        feeds.push(Config {
+            feed,
+            updated: Some(now),
+            tags: tags
+                .split(',')
+                .map(str::trim)
+                .filter(|t| !t.is_empty())
+                .map(String::from)
+                .collect(),
+            ..Config::default()
+        });
+    }
+    let dead_after = prompt_number(
+        input,
+        output,
+        "Mark feed dead after how many gone responses in a row",
+        settings.dead_after,
+        1,
+    )?;
+    let dormant_after_days = prompt_number(
+        input,
+        output,
+        "Report feed dormant after how many days without posts",
+        settings.dormant_after_days,
+        1,
+    )?;
+    Ok(Onboarding {
+        opml: Some(opml).filter(|o| !o.is_empty()),
+        feeds,
+        settings: Settings {
+            dead_after,
+            dormant_after_days,
+            ..settings
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_ask() {
+        let now = crate::parse_user_date("2021-06-01").unwrap();
+        let mut input = Cursor::new("subs.opml\nhttps://a.com/feed\nrust, news\n\n0\n5\nten\n30\n");
+        let mut output = Vec::new();
+        let onboarding = ask(&mut input, &mut output, Settings::default(), now).unwrap();
+        assert_eq!(Some("subs.opml".to_string()), onboarding.opml);
+        assert_eq!(
+            vec![Config {
+                feed: "https://a.com/feed".to_string(),
+                updated: Some(now),
+                tags: vec!["rust".to_string(), "news".to_string()],
+                ..Config::default()
+            }],
+            onboarding.feeds
+        );
+        assert_eq!(5, onboarding.settings.dead_after);
+        assert_eq!(30, onboarding.settings.dormant_after_days);
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("0 is less than 1."));
+        assert!(output.contains("ten is not a valid number."));
+    }
+
+    #[test]
+    fn test_ask_end_of_input_keeps_defaults() {
+        let now = Utc::now();
+        let onboarding = ask(
+            &mut Cursor::new(""),
+            &mut Vec::new(),
+            Settings::default(),
+            now,
+        )
+        .unwrap();
+        assert_eq!(
+            Onboarding {
+                opml: None,
+                feeds: vec![],
+                settings: Settings::default(),
+            },
+            onboarding
+        );
+    }
+}
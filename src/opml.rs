@@ -0,0 +1,164 @@
+use crate::error::{Error, Result};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::HashMap;
+use std::fs;
+
+/// Feed listed in an OPML subscription list.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Outline {
+    pub(crate) feed: String,
+    pub(crate) name: Option<String>,
+    /// Folders the feed is nested in along with its categories.
+    pub(crate) tags: Vec<String>,
+}
+
+/// Reads feeds from OPML file exported by another reader.
+pub(crate) fn read(path: &str) -> Result<Vec<Outline>> {
+    let data = fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+    parse(&data).map_err(|e| Error::input(format!("invalid opml file {}: {}", path, e)))
+}
+
+/// Attributes of outline element, keyed by lowercased name since readers
+/// disagree on `xmlUrl` vs `xmlurl`.
+fn attributes(
+    reader: &Reader<&[u8]>,
+    element: &BytesStart<'_>,
+) -> quick_xml::Result<HashMap<String, String>> {
+    let mut attributes = HashMap::new();
+    for attribute in element.attributes() {
+        let attribute = attribute?;
+        let key = String::from_utf8_lossy(attribute.key).to_lowercase();
+        attributes.insert(key, attribute.unescape_and_decode_value(reader)?);
+    }
+    Ok(attributes)
+}
+
+/// Turns folder names and `category` attribute (comma separated, each
+/// possibly a `/Tech/Rust` path) into tags.
+fn tags(folders: &[Option<String>], category: Option<&String>) -> Vec<String> {
+    let categories = category.into_iter().flat_map(|c| c.split([',', '/']));
+    let mut tags = Vec::new();
+    for tag in folders
+        .iter()
+        .flatten()
+        .map(String::as_str)
+        .chain(categories)
+    {
+        let tag = tag.trim().to_lowercase();
+        if !tag.is_empty() && !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    tags
+}
+
+/// Parses outline element, returning the feed it lists or, for folders, the
+/// folder name.
+fn outline(
+    attributes: HashMap<String, String>,
+    folders: &[Option<String>],
+) -> std::result::Result<Outline, Option<String>> {
+    let name = attributes
+        .get("title")
+        .or_else(|| attributes.get("text"))
+        .map(|n| n.trim().to_string())
+        .filter(|n| !n.is_empty());
+    match attributes.get("xmlurl") {
+        Some(feed) => Ok(Outline {
+            feed: feed.trim().to_string(),
+            name,
+            tags: tags(folders, attributes.get("category")),
+        }),
+        None => Err(name),
+    }
+}
+
+pub(crate) fn parse(data: &str) -> std::result::Result<Vec<Outline>, String> {
+    let mut reader = Reader::from_str(data);
+    reader.trim_text(true);
+    let mut buf = Vec::new();
+    let mut feeds = Vec::new();
+    // Name of every open outline element, none for feeds and unnamed folders.
+    let mut folders = Vec::new();
+    loop {
+        let event = reader
+            .read_event(&mut buf)
+            .map_err(|e| format!("{} at byte {}", e, reader.buffer_position()))?;
+        match event {
+            Event::Start(ref e) | Event::Empty(ref e) if e.name() == b"outline" => {
+                let empty = matches!(event, Event::Empty(_));
+                let attributes = attributes(&reader, e).map_err(|e| e.to_string())?;
+                let folder = match outline(attributes, &folders) {
+                    Ok(feed) => {
+                        feeds.push(feed);
+                        None
+                    }
+                    Err(folder) => folder,
+                };
+                if !empty {
+                    folders.push(folder);
+                }
+            }
+            Event::End(ref e) if e.name() == b"outline" => {
+                folders.pop();
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(feeds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let data = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <opml version="2.0">
+              <head><title>subscriptions</title></head>
+              <body>
+                <outline text="Rust">
+                  <outline text="This Week" title="This Week in Rust" type="rss"
+                    xmlUrl="https://this-week-in-rust.org/rss.xml"/>
+                  <outline text="Async">
+                    <outline text="Tokio" xmlUrl="https://tokio.rs/feed.xml" category="/Tech/Rust,news"/>
+                  </outline>
+                </outline>
+                <outline text="" xmlurl="https://example.com/feed?a=1&amp;b=2"></outline>
+              </body>
+            </opml>"#;
+        let feeds = parse(data).unwrap();
+        assert_eq!(
+            vec![
+                Outline {
+                    feed: "https://this-week-in-rust.org/rss.xml".to_string(),
+                    name: Some("This Week in Rust".to_string()),
+                    tags: vec!["rust".to_string()],
+                },
+                Outline {
+                    feed: "https://tokio.rs/feed.xml".to_string(),
+                    name: Some("Tokio".to_string()),
+                    tags: vec!["rust", "async", "tech", "news"]
+                        .into_iter()
+                        .map(String::from)
+                        .collect(),
+                },
+                Outline {
+                    feed: "https://example.com/feed?a=1&b=2".to_string(),
+                    name: None,
+                    tags: vec![],
+                },
+            ],
+            feeds
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(parse("<opml><body><outline text=\"a></body></opml>").is_err());
+    }
+}
@@ -0,0 +1,210 @@
+use crate::error::{Error, Result};
+use crate::store;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// All posts ever seen while fetching, keyed by post url.
+pub(crate) type PostStore = HashMap<String, Post>;
+
+/// Name of the file in data directory.
+pub(crate) const FILE: &str = "posts.json";
+
+/// Returns path where post metadata should reside.
+pub(crate) fn posts_path() -> String {
+    let posts_path = Path::new(&crate::base_dir()).join(FILE);
+    String::from(posts_path.to_str().unwrap())
+}
+
+/// Metadata of a post found in a feed. Unlike the readlist, posts are never
+/// removed from the store when they are marked as read.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Post {
+    pub link: String,
+    pub feed: String,
+    pub title: Option<String>,
+    pub summary: Option<String>,
+    pub author: Option<String>,
+    pub published: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub categories: Vec<String>,
+    /// Media attached to the post, like podcast episodes.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub enclosures: Vec<Enclosure>,
+    /// Full html content, when feed carries more than a summary.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    /// Main article of linked page as markdown, for feeds fetching full content.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub article: Option<String>,
+}
+
+/// Media file attached to a post, from rss `<enclosure>` or atom
+/// `rel="enclosure"` links.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Enclosure {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime: Option<String>,
+    /// Size in bytes, as announced by the feed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub length: Option<u64>,
+    /// Play time from itunes extension, like `1:02:03` or seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<String>,
+}
+
+impl Post {
+    /// Post for which nothing but the link is known, e.g. readlist entries
+    /// fetched before post metadata was stored.
+    pub(crate) fn bare(feed: &str, link: &str) -> Self {
+        Post {
+            link: link.to_string(),
+            feed: feed.to_string(),
+            title: None,
+            summary: None,
+            author: None,
+            published: None,
+            categories: vec![],
+            enclosures: vec![],
+            content: None,
+            article: None,
+        }
+    }
+}
+
+/// Length of ids listings show for posts.
+const ID_LEN: usize = 7;
+
+/// Short id of post, derived from its link so that it stays the same.
+pub(crate) fn short_id(link: &str) -> String {
+    format!("{:x}", md5::compute(link))[..ID_LEN].to_string()
+}
+
+/// Finds stored post by its link or its short id.
+pub(crate) fn find<'a>(store: &'a PostStore, key: &str) -> Result<&'a Post> {
+    if let Some(post) = store.get(key) {
+        return Ok(post);
+    }
+    let mut matched = store.values().filter(|p| short_id(&p.link) == key);
+    match (matched.next(), matched.next()) {
+        (Some(post), None) => Ok(post),
+        (Some(_), Some(_)) => Err(Error::input(format!(
+            "ambiguous post id: {}, use url of post",
+            key
+        ))),
+        _ => Err(Error::input(format!("unknown post: {}", key))),
+    }
+}
+
+pub(crate) fn setup() -> Result<()> {
+    if store::init(&posts_path(), &PostStore::new())? {
+        println!("creating posts path.");
+    } else {
+        println!("posts file already exists.");
+    }
+    Ok(())
+}
+
+/// Reads stored posts. Missing file is treated as an empty store since it
+/// did not exist in older setups.
+pub(crate) fn get() -> Result<PostStore> {
+    _get(&posts_path())
+}
+
+pub(crate) fn _get(path: &str) -> Result<PostStore> {
+    if !Path::new(path).is_file() {
+        return Ok(PostStore::new());
+    }
+    store::read(path)
+}
+
+pub(crate) fn _update(posts: Vec<Post>, mut store: PostStore) -> PostStore {
+    for post in posts {
+        store.insert(post.link.clone(), post);
+    }
+    store
+}
+
+pub(crate) fn _replace(path: &str, store: PostStore) -> Result<PostStore> {
+    store::write(path, &store)?;
+    Ok(store)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    fn post(link: &str, title: &str) -> Post {
+        Post {
+            link: link.to_string(),
+            feed: "feed1".to_string(),
+            title: Some(title.to_string()),
+            summary: None,
+            author: None,
+            published: None,
+            categories: vec![],
+            enclosures: vec![],
+            content: None,
+            article: None,
+        }
+    }
+
+    #[test]
+    fn test_find() {
+        let store = _update(
+            vec![
+                post("https://a.com/1", "one"),
+                post("https://a.com/2", "two"),
+            ],
+            PostStore::new(),
+        );
+        let id = short_id("https://a.com/2");
+        assert_eq!(ID_LEN, id.len());
+        assert_eq!(id, short_id("https://a.com/2"));
+        assert_eq!("two", find(&store, &id).unwrap().title.as_deref().unwrap());
+        assert_eq!(
+            "one",
+            find(&store, "https://a.com/1")
+                .unwrap()
+                .title
+                .as_deref()
+                .unwrap()
+        );
+        assert!(find(&store, "https://a.com/3").is_err());
+    }
+
+    #[test]
+    fn test_posts_path() {
+        assert!(posts_path().starts_with('/'));
+    }
+
+    #[test]
+    fn test_get_file_does_not_exist() {
+        assert!(_get("some/really/fake/path").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_update_overrides_existing_post() {
+        let store = _update(
+            vec![post("post1", "old"), post("post2", "two")],
+            PostStore::new(),
+        );
+        let store = _update(vec![post("post1", "new")], store);
+        assert_eq!(2, store.len());
+        assert_eq!(Some("new".to_string()), store["post1"].title);
+    }
+
+    #[test]
+    fn test_replace_and_get() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap();
+        let store = _update(vec![post("post1", "one")], PostStore::new());
+        _replace(path, store.clone()).unwrap();
+        assert_eq!(store, _get(path).unwrap());
+    }
+}
@@ -1,37 +1,46 @@
-use std::collections::HashMap;
-use std::error::Error;
-use std::fs;
+use crate::error::Result;
+use crate::store;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
 
-pub(crate) type ReadList = HashMap<String, Vec<String>>;
-type Result<T> = std::result::Result<T, Box<dyn Error>>;
+pub type ReadList = HashMap<String, Vec<String>>;
 
-fn readlist_path() -> String {
-    let readlist_path = Path::new(&crate::base_dir()).join("read_list.json");
+/// Upgrades of readlist format, oldest first.
+pub(crate) const MIGRATIONS: &[store::Migration] = &[store::envelope];
+
+/// Name of the file in data directory.
+pub(crate) const FILE: &str = "read_list.json";
+
+pub(crate) fn readlist_path() -> String {
+    let readlist_path = Path::new(&crate::base_dir()).join(FILE);
     String::from(readlist_path.to_str().unwrap())
 }
 
 pub(crate) fn setup() -> Result<()> {
-    let readlist_path = readlist_path();
-    if Path::new(&readlist_path).is_file() {
-        println!("readlist file already exists.");
-    } else {
+    if store::init_versioned(&readlist_path(), MIGRATIONS, &ReadList::new())? {
         println!("creating readlist path.");
-        fs::write(readlist_path, "{}")?;
+    } else {
+        println!("readlist file already exists.");
     }
     Ok(())
 }
 
+/// Reads readlist, creating it empty on first use.
 fn get() -> Result<ReadList> {
-    let read_list = fs::read_to_string(readlist_path())?;
-    Ok(serde_json::from_str(read_list.as_str())?)
+    let path = readlist_path();
+    store::init_versioned(&path, MIGRATIONS, &ReadList::new())?;
+    _get(&path)
+}
+
+pub(crate) fn _get(path: &str) -> Result<ReadList> {
+    store::read_versioned(path, MIGRATIONS)
 }
 
 pub(crate) fn unread() -> Result<ReadList> {
     Ok(_unread(get()?))
 }
 
-fn _unread(readlist: ReadList) -> ReadList {
+pub(crate) fn _unread(readlist: ReadList) -> ReadList {
     readlist
         .into_iter()
         .filter(|(_, readlist)| !readlist.is_empty())
@@ -54,20 +63,52 @@ pub(crate) fn mark_read(post: &str) -> Result<ReadList> {
     replace(readlist)
 }
 
-pub(crate) fn update(feeds: ReadList) -> Result<ReadList> {
-    let read_list = get()?;
-    let read_list = _update(feeds, read_list);
-    let data = serde_json::to_string_pretty(&read_list)?;
-    fs::write(readlist_path(), data)?;
-    Ok(read_list)
+/// Marks given posts as read, leaving posts added since they were listed.
+pub(crate) fn mark_posts_read(posts: &HashSet<String>) -> Result<ReadList> {
+    let mut readlist = get()?;
+    for to_read in readlist.values_mut() {
+        to_read.retain(|p| !posts.contains(p));
+    }
+    replace(readlist)
 }
 
-fn _update(feeds: ReadList, mut readlist: ReadList) -> ReadList {
-    for (feed, mut to_read) in feeds {
+/// Marks every post of given feeds as read, or of all feeds if none are given.
+pub(crate) fn mark_feeds_read(feeds: Option<&HashSet<String>>) -> Result<ReadList> {
+    let readlist = _mark_feeds_read(get()?, feeds);
+    replace(readlist)
+}
+
+fn _mark_feeds_read(mut readlist: ReadList, feeds: Option<&HashSet<String>>) -> ReadList {
+    for (feed, to_read) in &mut readlist {
+        if feeds.is_none_or(|f| f.contains(feed)) {
+            to_read.clear();
+        }
+    }
+    readlist
+}
+
+pub(crate) fn _rename_feed(mut readlist: ReadList, feed: &str, url: &str) -> ReadList {
+    if let Some(to_read) = readlist.remove(feed) {
+        _update(
+            vec![(url.to_string(), to_read)].into_iter().collect(),
+            readlist,
+        )
+    } else {
         readlist
-            .entry(feed)
-            .or_insert_with(Vec::new)
-            .append(&mut to_read);
+    }
+}
+
+/// Keeps only entries of given feeds.
+pub(crate) fn filter(readlist: ReadList, feeds: &HashSet<String>) -> ReadList {
+    readlist
+        .into_iter()
+        .filter(|(feed, _)| feeds.contains(feed))
+        .collect()
+}
+
+pub(crate) fn _update(feeds: ReadList, mut readlist: ReadList) -> ReadList {
+    for (feed, mut to_read) in feeds {
+        readlist.entry(feed).or_default().append(&mut to_read);
     }
     for to_read in readlist.values_mut() {
         to_read.sort();
@@ -77,8 +118,7 @@ fn _update(feeds: ReadList, mut readlist: ReadList) -> ReadList {
 }
 
 pub(crate) fn replace(readlist: ReadList) -> Result<ReadList> {
-    let data = serde_json::to_string_pretty(&readlist)?;
-    fs::write(readlist_path(), data)?;
+    store::write_versioned(&readlist_path(), MIGRATIONS, &readlist)?;
     Ok(readlist)
 }
 
@@ -105,6 +145,20 @@ mod tests {
         assert!(super::readlist_path().starts_with('/'));
     }
 
+    // Readlist written by 0.1.x, before it was versioned.
+    #[test]
+    fn test_get_unversioned() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("read_list.json");
+        let path = path.to_str().unwrap();
+        std::fs::write(path, r#"{"feed1": ["post1", "post2"], "feed2": []}"#).unwrap();
+        let expected = readlist_from(vec![("feed1", vec!["post1", "post2"]), ("feed2", vec![])]);
+        assert_eq!(expected, _get(path).unwrap());
+        assert!(std::path::Path::new(&format!("{}.v0.bak", path)).is_file());
+        assert_eq!(1, store::inspect::<ReadList>(path, MIGRATIONS).unwrap());
+        assert_eq!(expected, _get(path).unwrap());
+    }
+
     #[test]
     fn test_update() {
         let readlist = readlist_from(vec![
@@ -169,4 +223,45 @@ mod tests {
         let output = _mark_read(readlist.clone(), "post4");
         assert_eq!(readlist, output);
     }
+
+    #[test]
+    fn test_mark_feeds_read() {
+        let readlist = readlist_from(vec![
+            ("feed1", vec!["post1", "post2"]),
+            ("feed2", vec!["post3"]),
+        ]);
+        let feeds = vec!["feed1".to_string()].into_iter().collect();
+        let output = _mark_feeds_read(readlist, Some(&feeds));
+        let expected = readlist_from(vec![("feed1", vec![]), ("feed2", vec!["post3"])]);
+        assert_eq!(expected, output);
+
+        let output = _mark_feeds_read(output, None);
+        let expected = readlist_from(vec![("feed1", vec![]), ("feed2", vec![])]);
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_filter() {
+        let readlist = readlist_from(vec![
+            ("feed1", vec!["post1", "post2"]),
+            ("feed2", vec!["post3"]),
+        ]);
+        let feeds = vec!["feed2".to_string(), "feed3".to_string()]
+            .into_iter()
+            .collect();
+        let expected = readlist_from(vec![("feed2", vec!["post3"])]);
+        assert_eq!(expected, filter(readlist, &feeds));
+    }
+
+    #[test]
+    fn test_rename_feed() {
+        let readlist = readlist_from(vec![
+            ("feed1", vec!["post1", "post2"]),
+            ("feed2", vec!["post2", "post3"]),
+        ]);
+        let output = _rename_feed(readlist.clone(), "feed1", "feed2");
+        let expected = readlist_from(vec![("feed2", vec!["post1", "post2", "post3"])]);
+        assert_eq!(expected, output);
+        assert_eq!(readlist, _rename_feed(readlist.clone(), "feed3", "feed4"));
+    }
 }
@@ -0,0 +1,194 @@
+use crate::config::Config;
+use crate::html::{escape_html, link, summary, title};
+use crate::posts::{Post, PostStore};
+use chrono::{DateTime, NaiveDate, Utc};
+use std::collections::{BTreeMap, HashSet};
+
+const STYLE: &str =
+    "body{font-family:sans-serif;max-width:50em;margin:auto;padding:1em;color:#222}\
+h2{border-bottom:1px solid #ccc}h3{margin-bottom:.2em;color:#555}\
+ul{list-style:none;padding:0}li{margin:.6em 0}\
+li.read a{color:#888}li.unread a{font-weight:bold}\
+.meta{font-size:.8em;color:#888}p{margin:.2em 0}";
+
+/// Posts of a feed published on a day, newest first, along with whether
+/// each is still unread.
+#[derive(Debug, PartialEq)]
+pub(crate) struct FeedPosts {
+    pub(crate) name: String,
+    pub(crate) feed: String,
+    pub(crate) posts: Vec<(Post, bool)>,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct Day {
+    pub(crate) date: NaiveDate,
+    pub(crate) feeds: Vec<FeedPosts>,
+}
+
+/// Groups posts published since date by day and feed, newest day first and
+/// feeds ordered by name. Posts without a date cannot be placed and are left
+/// out.
+pub(crate) fn days(
+    store: &PostStore,
+    unread: &HashSet<String>,
+    configs: &[Config],
+    since: DateTime<Utc>,
+) -> Vec<Day> {
+    // Posts by day, then by feed name and url.
+    let mut grouped = BTreeMap::<NaiveDate, BTreeMap<(String, String), Vec<_>>>::new();
+    for post in store.values() {
+        let published = match post.published {
+            Some(published) if published >= since => published,
+            _ => continue,
+        };
+        let name = configs
+            .iter()
+            .find(|c| c.feed == post.feed)
+            .map_or(post.feed.as_str(), Config::display_name)
+            .to_string();
+        grouped
+            .entry(published.date_naive())
+            .or_default()
+            .entry((name, post.feed.clone()))
+            .or_default()
+            .push((post.clone(), unread.contains(&post.link)));
+    }
+    grouped
+        .into_iter()
+        .rev()
+        .map(|(date, feeds)| Day {
+            date,
+            feeds: feeds
+                .into_iter()
+                .map(|((name, feed), mut posts)| {
+                    posts.sort_by_key(|(p, _)| std::cmp::Reverse(p.published));
+                    FeedPosts { name, feed, posts }
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+fn post(html: &mut String, post: &Post, unread: bool) {
+    html.push_str(&format!(
+        "<li class=\"{}\">{}",
+        if unread { "unread" } else { "read" },
+        link(&post.link, title(post))
+    ));
+    let mut meta = vec![];
+    if let Some(author) = &post.author {
+        meta.push(escape_html(author));
+    }
+    if let Some(published) = post.published {
+        meta.push(published.format("%H:%M UTC").to_string());
+    }
+    meta.push((if unread { "unread" } else { "read" }).to_string());
+    html.push_str(&format!(
+        " <span class=\"meta\">{}</span>",
+        meta.join(" · ")
+    ));
+    if let Some(summary) = summary(post) {
+        html.push_str(&format!("<p>{}</p>", escape_html(&summary)));
+    }
+    html.push_str("</li>\n");
+}
+
+/// Self contained html page of days, styles included.
+pub(crate) fn page(days: &[Day], now: DateTime<Utc>) -> String {
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>rss-update</title>\
+         <style>{}</style></head><body>\n<h1>rss-update</h1>\n\
+         <p class=\"meta\">generated {}</p>\n",
+        STYLE,
+        now.format("%Y-%m-%d %H:%M UTC")
+    );
+    if days.is_empty() {
+        html.push_str("<p>no recent posts.</p>\n");
+    }
+    for day in days {
+        html.push_str(&format!("<h2>{}</h2>\n", day.date.format("%A, %Y-%m-%d")));
+        for feed in &day.feeds {
+            html.push_str(&format!(
+                "<h3>{}</h3>\n<ul>\n",
+                link(&feed.feed, &feed.name)
+            ));
+            for (p, unread) in &feed.posts {
+                post(&mut html, p, *unread);
+            }
+            html.push_str("</ul>\n");
+        }
+    }
+    html.push_str("</body></html>\n");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post(feed: &str, link: &str, published: &str) -> Post {
+        Post {
+            title: Some(format!("<{}>", link)),
+            published: Some(DateTime::parse_from_rfc3339(published).unwrap().into()),
+            ..Post::bare(feed, link)
+        }
+    }
+
+    #[test]
+    fn test_page_links() {
+        let post = |link: &str| post("https://a.com/feed", link, "2021-06-01T08:00:00Z");
+        let store = vec![post("https://a.com/1"), post("javascript:alert(1)")]
+            .into_iter()
+            .map(|p| (p.link.clone(), p))
+            .collect();
+        let since = crate::parse_user_date("2021-05-15").unwrap();
+        let page = page(&days(&store, &HashSet::new(), &[], since), Utc::now());
+        assert!(page.contains("<h3><a href=\"https://a.com/feed\">"));
+        assert!(page.contains("<a href=\"https://a.com/1\">&lt;https://a.com/1&gt;</a>"));
+        assert!(page.contains("<li class=\"read\">&lt;javascript:alert(1)&gt;"));
+        assert!(!page.contains("href=\"javascript"));
+    }
+
+    #[test]
+    fn test_days() {
+        let store = vec![
+            post("feed1", "post1", "2021-06-01T08:00:00Z"),
+            post("feed1", "post2", "2021-06-01T20:00:00Z"),
+            post("feed2", "post3", "2021-06-01T10:00:00Z"),
+            post("feed1", "post4", "2021-06-02T10:00:00Z"),
+            post("feed1", "post5", "2021-05-01T10:00:00Z"),
+            Post::bare("feed1", "post6"),
+        ]
+        .into_iter()
+        .map(|p| (p.link.clone(), p))
+        .collect();
+        let unread = vec!["post2".to_string()].into_iter().collect();
+        let configs = [Config {
+            feed: "feed2".to_string(),
+            name: Some("A feed".to_string()),
+            ..Config::default()
+        }];
+        let since = crate::parse_user_date("2021-05-15").unwrap();
+
+        let days = days(&store, &unread, &configs, since);
+        let dates = days.iter().map(|d| d.date.to_string()).collect::<Vec<_>>();
+        assert_eq!(vec!["2021-06-02", "2021-06-01"], dates);
+        let names = days[1]
+            .feeds
+            .iter()
+            .map(|f| f.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["A feed", "feed1"], names);
+        let posts = days[1].feeds[1]
+            .posts
+            .iter()
+            .map(|(p, unread)| (p.link.as_str(), *unread))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![("post2", true), ("post1", false)], posts);
+
+        let page = page(&days, Utc::now());
+        assert!(page.contains("<li class=\"unread\">&lt;post2&gt;"));
+        assert!(page.contains("<h2>Tuesday, 2021-06-01</h2>"));
+    }
+}
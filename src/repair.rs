@@ -0,0 +1,324 @@
+use crate::config::{self, Config, ConfigList};
+use crate::error::{Error, Result};
+use crate::health::{self, HealthStore, History};
+use crate::posts::{self, Post, PostStore};
+use crate::readlist::{self, ReadList};
+use crate::starred::{self, Starred};
+use crate::store;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Entries salvaged from a damaged data file.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Salvaged<T> {
+    pub(crate) data: T,
+    pub(crate) kept: usize,
+    pub(crate) dropped: usize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Outcome {
+    Missing,
+    Valid,
+    Repaired { kept: usize, dropped: usize },
+}
+
+/// Result of repairing a single data file.
+#[derive(Debug)]
+pub(crate) struct Report {
+    pub(crate) name: &'static str,
+    pub(crate) path: String,
+    pub(crate) outcome: Result<Outcome>,
+}
+
+/// Splits the first json array or object in text into its raw entries. Text
+/// is scanned by nesting only, so entries before a syntax error, or before
+/// the end of a truncated file, are still found.
+fn entries(text: &str) -> Vec<&str> {
+    let start = match text.find(['[', '{']) {
+        Some(start) => start + 1,
+        None => return vec![],
+    };
+    let body = &text[start..];
+    let mut entries = Vec::new();
+    let (mut depth, mut in_string, mut escaped, mut from) = (0, false, false, 0);
+    for (i, c) in body.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '[' | '{' => depth += 1,
+            ']' | '}' if depth == 0 => {
+                entries.push(&body[from..i]);
+                from = body.len();
+                break;
+            }
+            // Closing an entry ends it even without a comma after it.
+            ']' | '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    entries.push(&body[from..=i]);
+                    from = i + 1;
+                }
+            }
+            ',' if depth == 0 => {
+                entries.push(&body[from..i]);
+                from = i + 1;
+            }
+            _ => {}
+        }
+    }
+    entries.push(&body[from..]);
+    entries
+        .into_iter()
+        .map(str::trim)
+        .filter(|e| !e.is_empty())
+        .collect()
+}
+
+/// Splits raw object entry into its key and raw value.
+fn key_value(entry: &str) -> Option<(String, &str)> {
+    let mut escaped = false;
+    let end = entry.char_indices().skip(1).find_map(|(i, c)| match c {
+        _ if escaped => {
+            escaped = false;
+            None
+        }
+        '\\' => {
+            escaped = true;
+            None
+        }
+        '"' => Some(i),
+        _ => None,
+    })?;
+    let key = serde_json::from_str(&entry[..=end]).ok()?;
+    let value = entry[end + 1..].trim_start().strip_prefix(':')?;
+    Some((key, value.trim()))
+}
+
+/// Returns raw data of a versioned file, or the whole text for files
+/// written before versioning.
+fn data(text: &str) -> &str {
+    let entries = entries(text);
+    let keys = entries
+        .iter()
+        .filter_map(|e| key_value(e))
+        .collect::<HashMap<_, _>>();
+    match (keys.get("version"), keys.get("data")) {
+        (Some(_), Some(data)) if text.trim_start().starts_with('{') => data,
+        _ => text,
+    }
+}
+
+pub(crate) fn salvage_list<T: DeserializeOwned>(text: &str) -> Salvaged<Vec<T>> {
+    let mut salvaged = Salvaged {
+        data: Vec::new(),
+        kept: 0,
+        dropped: 0,
+    };
+    for entry in entries(data(text)) {
+        match serde_json::from_str(entry) {
+            Ok(item) => {
+                salvaged.data.push(item);
+                salvaged.kept += 1;
+            }
+            Err(_) => salvaged.dropped += 1,
+        }
+    }
+    salvaged
+}
+
+pub(crate) fn salvage_map<V: DeserializeOwned>(text: &str) -> Salvaged<HashMap<String, V>> {
+    let mut salvaged = Salvaged {
+        data: HashMap::new(),
+        kept: 0,
+        dropped: 0,
+    };
+    for entry in entries(data(text)) {
+        let item = key_value(entry)
+            .and_then(|(key, value)| serde_json::from_str(value).ok().map(|value| (key, value)));
+        match item {
+            Some((key, value)) => {
+                salvaged.data.insert(key, value);
+                salvaged.kept += 1;
+            }
+            None => salvaged.dropped += 1,
+        }
+    }
+    salvaged
+}
+
+/// Rewrites data file with entries salvaged from it, unless it reads fine.
+/// Damaged file is kept as the newest backup.
+fn repair_file<T, R, S, W>(path: &str, read: R, salvage: S, write: W) -> Result<Outcome>
+where
+    R: FnOnce(&str) -> Result<T>,
+    S: FnOnce(&str) -> Salvaged<T>,
+    W: FnOnce(&str, &T) -> Result<()>,
+{
+    if !Path::new(path).is_file() {
+        return Ok(Outcome::Missing);
+    }
+    match read(path) {
+        Ok(_) => return Ok(Outcome::Valid),
+        Err(Error::Parse { .. }) => {}
+        Err(e) => return Err(e),
+    }
+    let text = fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+    let salvaged = salvage(&text);
+    write(path, &salvaged.data)?;
+    Ok(Outcome::Repaired {
+        kept: salvaged.kept,
+        dropped: salvaged.dropped,
+    })
+}
+
+/// Repairs every data file made of entries which can be salvaged one by one.
+pub(crate) fn repair() -> Vec<Report> {
+    let config_path = config::config_path();
+    let readlist_path = readlist::readlist_path();
+    let posts_path = posts::posts_path();
+    let health_path = health::health_path();
+    let starred_path = starred::starred_path();
+    vec![
+        Report {
+            name: "config",
+            outcome: repair_file(
+                &config_path,
+                |p| store::read_versioned::<ConfigList>(p, config::MIGRATIONS),
+                salvage_list::<Config>,
+                |p, d| store::write_versioned(p, config::MIGRATIONS, d),
+            ),
+            path: config_path,
+        },
+        Report {
+            name: "readlist",
+            outcome: repair_file(
+                &readlist_path,
+                |p| store::read_versioned::<ReadList>(p, readlist::MIGRATIONS),
+                salvage_map::<Vec<String>>,
+                |p, d| store::write_versioned(p, readlist::MIGRATIONS, d),
+            ),
+            path: readlist_path,
+        },
+        Report {
+            name: "posts",
+            outcome: repair_file(
+                &posts_path,
+                store::read::<PostStore>,
+                salvage_map::<Post>,
+                store::write,
+            ),
+            path: posts_path,
+        },
+        Report {
+            name: "health",
+            outcome: repair_file(
+                &health_path,
+                store::read::<HealthStore>,
+                salvage_map::<History>,
+                store::write,
+            ),
+            path: health_path,
+        },
+        Report {
+            name: "starred",
+            outcome: repair_file(
+                &starred_path,
+                store::read::<Starred>,
+                salvage_map::<Vec<String>>,
+                store::write,
+            ),
+            path: starred_path,
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn feeds(configs: &[Config]) -> Vec<&str> {
+        configs.iter().map(|c| c.feed.as_str()).collect()
+    }
+
+    #[test]
+    fn test_salvage_truncated_config() {
+        let text = r#"{
+          "version": 1,
+          "data": [
+            {"feed": "feed1", "updated": null, "name": "a \"quoted\" {name}"},
+            {"feed": "feed2", "updated": "2021-01-01T00:00:00Z"},
+            {"feed": "feed3", "upd"#;
+        let salvaged = salvage_list::<Config>(text);
+        assert_eq!(vec!["feed1", "feed2"], feeds(&salvaged.data));
+        assert_eq!(
+            Some("a \"quoted\" {name}"),
+            salvaged.data[0].name.as_deref()
+        );
+        assert_eq!((2, 1), (salvaged.kept, salvaged.dropped));
+    }
+
+    #[test]
+    fn test_salvage_hand_edited_config() {
+        // Missing comma, trailing comma and an entry of wrong shape.
+        let text = r#"[
+            {"feed": "feed1", "updated": null}
+            {"feed": "feed2", "updated": null},
+            {"feed": 3, "updated": null},
+        ]"#;
+        let salvaged = salvage_list::<Config>(text);
+        assert_eq!(vec!["feed1", "feed2"], feeds(&salvaged.data));
+        assert_eq!((2, 1), (salvaged.kept, salvaged.dropped));
+    }
+
+    #[test]
+    fn test_salvage_readlist() {
+        let text = r#"{"feed1": ["post1", "post2"], "feed2": ["post3" "post4"], "feed3": ["po"#;
+        let salvaged = salvage_map::<Vec<String>>(text);
+        assert_eq!(1, salvaged.data.len());
+        assert_eq!(vec!["post1", "post2"], salvaged.data["feed1"]);
+        assert_eq!((1, 2), (salvaged.kept, salvaged.dropped));
+    }
+
+    #[test]
+    fn test_repair_file() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("read_list.json");
+        let path = path.to_str().unwrap();
+        let repair = |path: &str| {
+            repair_file(
+                path,
+                |p| store::read_versioned::<ReadList>(p, readlist::MIGRATIONS),
+                salvage_map::<Vec<String>>,
+                |p, d| store::write_versioned(p, readlist::MIGRATIONS, d),
+            )
+        };
+        assert_eq!(Outcome::Missing, repair(path).unwrap());
+
+        let damaged = r#"{"version": 1, "data": {"feed1": ["post1"], "feed2": "#;
+        fs::write(path, damaged).unwrap();
+        assert_eq!(
+            Outcome::Repaired {
+                kept: 1,
+                dropped: 1
+            },
+            repair(path).unwrap()
+        );
+        assert_eq!(
+            damaged,
+            fs::read_to_string(store::backup_path(path, 1)).unwrap()
+        );
+        assert_eq!(Outcome::Valid, repair(path).unwrap());
+    }
+}
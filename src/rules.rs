@@ -0,0 +1,290 @@
+use crate::error::{Error, Result};
+use crate::feeds::Context;
+use crate::posts::{Post, PostStore};
+use crate::readlist::ReadList;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+/// Part of the post a rule is matched against.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Field {
+    Title,
+    Author,
+    Category,
+    Link,
+}
+
+impl FromStr for Field {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "title" => Ok(Field::Title),
+            "author" => Ok(Field::Author),
+            "category" => Ok(Field::Category),
+            "link" => Ok(Field::Link),
+            _ => Err(format!("unknown rule field: {}", s)),
+        }
+    }
+}
+
+/// What happens to a post matched by a rule.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Action {
+    /// Post is dropped while fetching and never stored.
+    Skip,
+    /// Post is stored but never added to the readlist.
+    MarkRead,
+    /// Post is marked when displaying the readlist.
+    Highlight,
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(Action::Skip),
+            "mark_read" => Ok(Action::MarkRead),
+            "highlight" => Ok(Action::Highlight),
+            _ => Err(format!("unknown rule action: {}", s)),
+        }
+    }
+}
+
+/// Filter rule stored in settings. Rules without feed apply to every feed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Rule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub feed: Option<String>,
+    pub field: Field,
+    pub pattern: String,
+    #[serde(default)]
+    pub regex: bool,
+    pub action: Action,
+}
+
+/// Rule with its pattern compiled. Substring patterns are escaped so that
+/// both kinds are matched case insensitively the same way.
+struct Matcher<'a> {
+    rule: &'a Rule,
+    pattern: Regex,
+}
+
+impl<'a> Matcher<'a> {
+    fn new(rule: &'a Rule) -> Result<Self> {
+        let pattern = if rule.regex {
+            rule.pattern.clone()
+        } else {
+            regex::escape(&rule.pattern)
+        };
+        let pattern = RegexBuilder::new(&pattern)
+            .case_insensitive(true)
+            .build()
+            .map_err(|e| Error::input(format!("invalid rule pattern {}: {}", rule.pattern, e)))?;
+        Ok(Matcher { rule, pattern })
+    }
+
+    fn matches(&self, post: &Post) -> bool {
+        if self.rule.feed.as_ref().is_some_and(|f| *f != post.feed) {
+            return false;
+        }
+        let is_match = |value: &Option<String>| {
+            value
+                .as_ref()
+                .is_some_and(|v| self.pattern.is_match(v.as_str()))
+        };
+        match self.rule.field {
+            Field::Title => is_match(&post.title),
+            Field::Author => is_match(&post.author),
+            Field::Category => post.categories.iter().any(|c| self.pattern.is_match(c)),
+            Field::Link => self.pattern.is_match(&post.link),
+        }
+    }
+}
+
+fn compile(rules: &[Rule]) -> Result<Vec<Matcher<'_>>> {
+    rules.iter().map(Matcher::new).collect()
+}
+
+fn has_action(matchers: &[Matcher<'_>], post: &Post, action: Action) -> bool {
+    matchers
+        .iter()
+        .any(|m| m.rule.action == action && m.matches(post))
+}
+
+/// Drops skipped posts from fetched context and keeps posts which should be
+/// marked read out of the readlist, before context is persisted.
+pub(crate) fn apply(rules: &[Rule], mut context: Context) -> Result<Context> {
+    let matchers = compile(rules)?;
+    context
+        .posts
+        .retain(|p| !has_action(&matchers, p, Action::Skip));
+    let unread = context
+        .posts
+        .iter()
+        .filter(|p| !has_action(&matchers, p, Action::MarkRead))
+        .map(|p| p.link.clone())
+        .collect::<HashSet<_>>();
+    for to_read in context.feeds.values_mut() {
+        to_read.retain(|p| unread.contains(p));
+    }
+    Ok(context)
+}
+
+/// Finds post metadata for readlist entries. Entries without stored metadata
+/// can still be matched by link.
+fn readlist_posts(feeds: &ReadList, store: &PostStore) -> Vec<Post> {
+    feeds
+        .iter()
+        .flat_map(|(feed, to_read)| {
+            to_read.iter().map(move |link| {
+                store
+                    .get(link)
+                    .cloned()
+                    .unwrap_or_else(|| Post::bare(feed, link))
+            })
+        })
+        .collect()
+}
+
+/// Returns links of posts in readlist that should be highlighted.
+pub(crate) fn highlighted(
+    rules: &[Rule],
+    feeds: &ReadList,
+    store: &PostStore,
+) -> Result<HashSet<String>> {
+    let matchers = compile(rules)?;
+    Ok(readlist_posts(feeds, store)
+        .into_iter()
+        .filter(|p| has_action(&matchers, p, Action::Highlight))
+        .map(|p| p.link)
+        .collect())
+}
+
+/// Returns the part of readlist the rule would match, regardless of its action.
+pub(crate) fn preview(rule: &Rule, feeds: &ReadList, store: &PostStore) -> Result<ReadList> {
+    let matcher = Matcher::new(rule)?;
+    let mut matched: ReadList = HashMap::new();
+    for post in readlist_posts(feeds, store) {
+        if matcher.matches(&post) {
+            matched.entry(post.feed).or_default().push(post.link);
+        }
+    }
+    Ok(matched)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post(feed: &str, link: &str, title: &str, categories: Vec<&str>) -> Post {
+        Post {
+            title: Some(title.to_string()),
+            categories: categories.into_iter().map(String::from).collect(),
+            ..Post::bare(feed, link)
+        }
+    }
+
+    fn rule(feed: Option<&str>, field: Field, pattern: &str, regex: bool, action: Action) -> Rule {
+        Rule {
+            feed: feed.map(String::from),
+            field,
+            pattern: pattern.to_string(),
+            regex,
+            action,
+        }
+    }
+
+    fn context() -> Context {
+        let posts = vec![
+            post("feed1", "post1", "Weekly Roundup #12", vec![]),
+            post("feed1", "post2", "Sponsored: buy things", vec!["ads"]),
+            post("feed2", "post3", "Episode 4", vec!["podcast"]),
+            post("feed2", "post4", "Const generics", vec![]),
+        ];
+        let mut feeds = ReadList::new();
+        for p in &posts {
+            feeds
+                .entry(p.feed.clone())
+                .or_default()
+                .push(p.link.clone());
+        }
+        Context {
+            feeds,
+            configs: vec![],
+            posts,
+            redirects: vec![],
+            failed: vec![],
+            attempts: vec![],
+        }
+    }
+
+    #[test]
+    fn test_parse_field_and_action() {
+        assert_eq!(Ok(Field::Category), "category".parse());
+        assert_eq!(Ok(Action::MarkRead), "mark_read".parse());
+        assert!("body".parse::<Field>().is_err());
+        assert!("delete".parse::<Action>().is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        let rules = vec![
+            rule(None, Field::Title, "weekly roundup", false, Action::Skip),
+            rule(None, Field::Category, "^podcast$", true, Action::MarkRead),
+            rule(
+                Some("feed2"),
+                Field::Title,
+                "sponsored",
+                false,
+                Action::Skip,
+            ),
+        ];
+        let context = apply(&rules, context()).unwrap();
+        let links = context
+            .posts
+            .iter()
+            .map(|p| p.link.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["post2", "post3", "post4"], links);
+        assert_eq!(vec!["post2".to_string()], context.feeds["feed1"]);
+        assert_eq!(vec!["post4".to_string()], context.feeds["feed2"]);
+    }
+
+    #[test]
+    fn test_apply_invalid_regex() {
+        let rules = vec![rule(None, Field::Title, "(", true, Action::Skip)];
+        assert!(apply(&rules, context()).is_err());
+    }
+
+    #[test]
+    fn test_highlighted_without_metadata() {
+        let context = context();
+        let rules = vec![rule(None, Field::Link, "post4", false, Action::Highlight)];
+        let highlighted = highlighted(&rules, &context.feeds, &PostStore::new()).unwrap();
+        assert_eq!(1, highlighted.len());
+        assert!(highlighted.contains("post4"));
+    }
+
+    #[test]
+    fn test_preview() {
+        let context = context();
+        let store = context
+            .posts
+            .iter()
+            .map(|p| (p.link.clone(), p.clone()))
+            .collect();
+        let rule = rule(None, Field::Title, r"#\d+|episode", true, Action::Skip);
+        let matched = preview(&rule, &context.feeds, &store).unwrap();
+        assert_eq!(vec!["post1".to_string()], matched["feed1"]);
+        assert_eq!(vec!["post3".to_string()], matched["feed2"]);
+    }
+}
@@ -0,0 +1,491 @@
+use crate::error::{Error, Result};
+use crate::html;
+use crate::posts::{Post, PostStore};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+/// Name of the file in data directory.
+pub(crate) const FILE: &str = "search_index.json";
+
+pub(crate) fn index_path() -> String {
+    let index_path = Path::new(&crate::base_dir()).join(FILE);
+    String::from(index_path.to_str().unwrap())
+}
+
+/// Matches in title are worth more than matches in summary.
+const TITLE_WEIGHT: f64 = 2.0;
+
+/// Parsed form of a user query like `tokio "graceful shutdown" feed:blog before:2021-01-01`.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Query {
+    terms: Vec<String>,
+    phrases: Vec<String>,
+    feed: Option<String>,
+    author: Option<String>,
+    before: Option<DateTime<Utc>>,
+    after: Option<DateTime<Utc>>,
+}
+
+/// A post matching the query along with its relevance.
+#[derive(Debug)]
+pub(crate) struct Hit {
+    pub(crate) post: Post,
+    pub(crate) score: f64,
+    pub(crate) unread: bool,
+}
+
+/// Splits on whitespace while keeping double quoted sections together. The
+/// returned flag tells if the token was quoted.
+fn tokenize_query(query: &str) -> Vec<(String, bool)> {
+    let mut tokens = vec![];
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut was_quoted = false;
+    for c in query.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                was_quoted = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    tokens.push((current.clone(), was_quoted));
+                }
+                current.clear();
+                was_quoted = false;
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push((current, was_quoted));
+    }
+    tokens
+}
+
+pub(crate) fn parse_query(query: &str) -> Result<Query> {
+    let mut parsed = Query::default();
+    for (token, quoted) in tokenize_query(query) {
+        match token.split_once(':') {
+            Some(("feed", value)) => parsed.feed = Some(value.to_lowercase()),
+            Some(("author", value)) => parsed.author = Some(value.to_lowercase()),
+            Some(("before", value)) => parsed.before = Some(crate::parse_user_date(value)?),
+            Some(("after", value)) => parsed.after = Some(crate::parse_user_date(value)?),
+            _ if quoted => parsed.phrases.push(words(&token).join(" ")),
+            _ => parsed.terms.append(&mut words(&token)),
+        }
+    }
+    Ok(parsed)
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Words of title and body of a post, in order.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Entry {
+    title: Vec<String>,
+    body: Vec<String>,
+}
+
+impl Entry {
+    fn new(post: &Post) -> Self {
+        Entry {
+            title: words(post.title.as_deref().unwrap_or_default()),
+            body: words(&format!(
+                "{} {} {}",
+                html::strip_tags(post.summary.as_deref().unwrap_or_default()),
+                html::strip_tags(post.content.as_deref().unwrap_or_default()),
+                post.article.as_deref().unwrap_or_default()
+            )),
+        }
+    }
+}
+
+/// Full-text index of stored posts, kept in its own file and updated as
+/// posts are stored, so that searching does not tokenize every post again.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub(crate) struct Index {
+    entries: HashMap<String, Entry>,
+    /// Links of posts containing a word.
+    postings: HashMap<String, BTreeSet<String>>,
+}
+
+impl Index {
+    /// Indexes post, replacing what was indexed for its link before.
+    pub(crate) fn add(&mut self, post: &Post) {
+        self.remove(&post.link);
+        let entry = Entry::new(post);
+        for word in entry.title.iter().chain(&entry.body) {
+            self.postings
+                .entry(word.clone())
+                .or_default()
+                .insert(post.link.clone());
+        }
+        self.entries.insert(post.link.clone(), entry);
+    }
+
+    fn remove(&mut self, link: &str) {
+        let entry = match self.entries.remove(link) {
+            Some(entry) => entry,
+            None => return,
+        };
+        for word in entry.title.iter().chain(&entry.body) {
+            if let Some(links) = self.postings.get_mut(word) {
+                links.remove(link);
+                if links.is_empty() {
+                    self.postings.remove(word);
+                }
+            }
+        }
+    }
+
+    /// Indexes stored posts missing from index and drops posts no longer
+    /// stored, e.g. after the index file was lost. Returns whether anything
+    /// changed.
+    pub(crate) fn sync(&mut self, store: &PostStore) -> bool {
+        let stale = self
+            .entries
+            .keys()
+            .filter(|link| !store.contains_key(*link))
+            .cloned()
+            .collect::<Vec<_>>();
+        let missing = store
+            .values()
+            .filter(|p| !self.entries.contains_key(&p.link))
+            .collect::<Vec<_>>();
+        let changed = !stale.is_empty() || !missing.is_empty();
+        for link in stale {
+            self.remove(&link);
+        }
+        for post in missing {
+            self.add(post);
+        }
+        changed
+    }
+
+    /// Links of posts containing all words, all posts without words.
+    fn candidates(&self, words: &[&str]) -> BTreeSet<&String> {
+        let mut words = words.iter();
+        let mut candidates = match words.next() {
+            Some(word) => match self.postings.get(*word) {
+                Some(links) => links.iter().collect::<BTreeSet<_>>(),
+                None => return BTreeSet::new(),
+            },
+            None => return self.entries.keys().collect(),
+        };
+        for word in words {
+            let links = self.postings.get(*word);
+            candidates.retain(|link| links.is_some_and(|l| l.contains(*link)));
+        }
+        candidates
+    }
+}
+
+/// Reads index. Missing file means an empty index, filled on next search.
+pub(crate) fn _get(path: &str) -> Result<Index> {
+    if !Path::new(path).is_file() {
+        return Ok(Index::default());
+    }
+    crate::store::read(path)
+}
+
+/// Index of stored posts, brought up to date with them first. A damaged
+/// index is rebuilt rather than reported, as nothing is lost with it.
+pub(crate) fn _updated(path: &str, store: &PostStore) -> Result<Index> {
+    let mut index = _get(path).unwrap_or_default();
+    if index.sync(store) {
+        _replace(path, &index)?;
+    }
+    Ok(index)
+}
+
+/// Adds posts to index at path, as they are stored.
+pub(crate) fn _add(path: &str, posts: &[Post]) -> Result<()> {
+    let mut index = _get(path).unwrap_or_default();
+    for post in posts {
+        index.add(post);
+    }
+    _replace(path, &index)
+}
+
+/// Writes index, which unlike data files is kept without backups since it
+/// can always be rebuilt from stored posts.
+pub(crate) fn _replace(path: &str, index: &Index) -> Result<()> {
+    if let Some(dir) = Path::new(path).parent() {
+        fs::create_dir_all(dir).map_err(|e| Error::io(&dir.to_string_lossy(), e))?;
+    }
+    let data = serde_json::to_string(index).map_err(|e| Error::serialize(path, e))?;
+    let tmp = format!("{}.tmp", path);
+    fs::write(&tmp, data).map_err(|e| Error::io(&tmp, e))?;
+    fs::rename(&tmp, path).map_err(|e| Error::io(path, e))
+}
+
+/// Indexed words of a stored post.
+struct Document<'a> {
+    post: &'a Post,
+    title: &'a [String],
+    body: &'a [String],
+}
+
+impl<'a> Document<'a> {
+    fn contains(&self, term: &str) -> bool {
+        self.title.iter().chain(self.body.iter()).any(|w| w == term)
+    }
+
+    /// Whether words of phrase follow each other in title or body, so that
+    /// `rust lang` does not match `trust language`.
+    fn contains_phrase(&self, phrase: &str) -> bool {
+        let phrase = phrase.split(' ').map(String::from).collect::<Vec<_>>();
+        let follow = |words: &[String]| words.windows(phrase.len()).any(|w| w == phrase);
+        phrase.iter().all(|w| w.is_empty()) || follow(self.title) || follow(self.body)
+    }
+
+    fn term_frequency(&self, term: &str) -> f64 {
+        let count = |words: &[String]| words.iter().filter(|w| *w == term).count() as f64;
+        let len = (self.title.len() + self.body.len()).max(1) as f64;
+        (TITLE_WEIGHT * count(self.title) + count(self.body)) / len
+    }
+
+    fn matches_filters(&self, query: &Query) -> bool {
+        let post = self.post;
+        let contains = |value: &Option<String>, filter: &Option<String>| match filter {
+            Some(filter) => value
+                .as_ref()
+                .is_some_and(|v| v.to_lowercase().contains(filter)),
+            None => true,
+        };
+        contains(&Some(post.feed.clone()), &query.feed)
+            && contains(&post.author, &query.author)
+            && query
+                .before
+                .is_none_or(|d| post.published.is_some_and(|p| p < d))
+            && query
+                .after
+                .is_none_or(|d| post.published.is_some_and(|p| p >= d))
+    }
+}
+
+/// Returns posts matching all terms, phrases and filters of the query, most
+/// relevant first. Candidates are looked up in index, and ranked by tf-idf
+/// over title, summary, content and fetched article.
+pub(crate) fn search(
+    store: &PostStore,
+    index: &Index,
+    unread: &HashSet<String>,
+    query: &Query,
+    limit: usize,
+) -> Vec<Hit> {
+    let total = index.entries.len() as f64;
+    let mut idf: HashMap<&str, f64> = HashMap::new();
+    for term in &query.terms {
+        let df = index.postings.get(term).map_or(0, BTreeSet::len) as f64;
+        idf.insert(term, (1.0 + total / df.max(1.0)).ln());
+    }
+    let words = query
+        .terms
+        .iter()
+        .map(String::as_str)
+        .chain(query.phrases.iter().flat_map(|p| p.split(' ')))
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>();
+    let documents = index
+        .candidates(&words)
+        .into_iter()
+        .filter_map(|link| {
+            let entry = &index.entries[link];
+            Some(Document {
+                post: store.get(link)?,
+                title: &entry.title,
+                body: &entry.body,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut hits = documents
+        .iter()
+        .filter(|d| d.matches_filters(query))
+        .filter(|d| query.terms.iter().all(|t| d.contains(t)))
+        .filter(|d| query.phrases.iter().all(|p| d.contains_phrase(p)))
+        .map(|d| Hit {
+            post: d.post.clone(),
+            score: query
+                .terms
+                .iter()
+                .map(|t| d.term_frequency(t) * idf[t.as_str()])
+                .sum::<f64>()
+                + query.phrases.len() as f64,
+            unread: unread.contains(&d.post.link),
+        })
+        .collect::<Vec<_>>();
+    hits.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then(b.post.published.cmp(&a.post.published))
+    });
+    hits.truncate(limit);
+    hits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post(link: &str, title: &str, summary: &str, published: &str) -> Post {
+        Post {
+            link: link.to_string(),
+            feed: "https://blog.rust-lang.org/feed.xml".to_string(),
+            title: Some(title.to_string()),
+            summary: Some(summary.to_string()),
+            author: Some("Ferris".to_string()),
+            published: Some(crate::parse_user_date(published).unwrap()),
+            categories: vec![],
+            enclosures: vec![],
+            content: None,
+            article: None,
+        }
+    }
+
+    fn store() -> PostStore {
+        vec![
+            post(
+                "post1",
+                "Tokio cancellation",
+                "<p>cancel safety in tokio</p>",
+                "2021-03-01",
+            ),
+            post("post2", "Async book", "tokio and async-std", "2021-05-01"),
+            post(
+                "post3",
+                "Const generics",
+                "const generics mvp",
+                "2021-02-01",
+            ),
+        ]
+        .into_iter()
+        .map(|p| (p.link.clone(), p))
+        .collect()
+    }
+
+    fn index(store: &PostStore) -> Index {
+        let mut index = Index::default();
+        index.sync(store);
+        index
+    }
+
+    fn links(hits: &[Hit]) -> Vec<&str> {
+        hits.iter().map(|h| h.post.link.as_str()).collect()
+    }
+
+    #[test]
+    fn test_parse_query() {
+        let query =
+            parse_query(r#"Tokio "Cancel safety" feed:rust author:ferris before:2021-04-01"#)
+                .unwrap();
+        assert_eq!(vec!["tokio".to_string()], query.terms);
+        assert_eq!(vec!["cancel safety".to_string()], query.phrases);
+        assert_eq!(Some("rust".to_string()), query.feed);
+        assert_eq!(Some("ferris".to_string()), query.author);
+        assert_eq!(
+            Some(crate::parse_user_date("2021-04-01").unwrap()),
+            query.before
+        );
+        assert_eq!(None, query.after);
+    }
+
+    #[test]
+    fn test_parse_query_invalid_date() {
+        assert!(parse_query("before:yesterday").is_err());
+    }
+
+    #[test]
+    fn test_search_ranks_title_matches_first() {
+        let query = parse_query("tokio").unwrap();
+        let hits = search(&store(), &index(&store()), &HashSet::new(), &query, 10);
+        assert_eq!(vec!["post1", "post2"], links(&hits));
+    }
+
+    #[test]
+    fn test_search_phrase_ignores_markup() {
+        let query = parse_query(r#""cancel safety""#).unwrap();
+        let hits = search(&store(), &index(&store()), &HashSet::new(), &query, 10);
+        assert_eq!(vec!["post1"], links(&hits));
+
+        let query = parse_query(r#""in tok""#).unwrap();
+        assert!(search(&store(), &index(&store()), &HashSet::new(), &query, 10).is_empty());
+    }
+
+    #[test]
+    fn test_search_filters() {
+        let unread = vec!["post2".to_string()].into_iter().collect();
+        let query = parse_query("tokio after:2021-04-01 author:ferris").unwrap();
+        let hits = search(&store(), &index(&store()), &unread, &query, 10);
+        assert_eq!(vec!["post2"], links(&hits));
+        assert!(hits[0].unread);
+
+        let query = parse_query("feed:medium").unwrap();
+        assert!(search(&store(), &index(&store()), &unread, &query, 10).is_empty());
+    }
+
+    #[test]
+    fn test_search_decodes_entities() {
+        let mut store = store();
+        store.get_mut("post3").unwrap().summary =
+            Some("<p>rock&nbsp;&amp;&nbsp;roll</p>".to_string());
+        let query = parse_query(r#""rock roll""#).unwrap();
+        let hits = search(&store, &index(&store), &HashSet::new(), &query, 10);
+        assert_eq!(vec!["post3"], links(&hits));
+        let query = parse_query("amp").unwrap();
+        assert!(search(&store, &index(&store), &HashSet::new(), &query, 10).is_empty());
+    }
+
+    #[test]
+    fn test_search_article() {
+        let mut store = store();
+        store.get_mut("post3").unwrap().article = Some("Full text on *pinning*.".to_string());
+        let query = parse_query("pinning").unwrap();
+        let hits = search(&store, &index(&store), &HashSet::new(), &query, 10);
+        assert_eq!(vec!["post3"], links(&hits));
+    }
+
+    #[test]
+    fn test_index() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join(FILE).to_string_lossy().into_owned();
+        let mut store = store();
+        let index = _updated(&path, &store).unwrap();
+        assert_eq!(index, _get(&path).unwrap());
+        assert_eq!(2, index.postings["tokio"].len());
+
+        let mut post = store["post1"].clone();
+        post.title = Some("Cancellation".to_string());
+        post.summary = None;
+        _add(&path, &[post.clone()]).unwrap();
+        store.insert(post.link.clone(), post);
+        store.remove("post2");
+        let index = _updated(&path, &store).unwrap();
+        assert!(!index.postings.contains_key("tokio"));
+        assert_eq!(vec!["post1", "post3"], {
+            let mut links = index.entries.keys().collect::<Vec<_>>();
+            links.sort();
+            links
+        });
+
+        std::fs::write(&path, "{").unwrap();
+        assert_eq!(index, _updated(&path, &store).unwrap());
+    }
+
+    #[test]
+    fn test_search_limit() {
+        let query = parse_query("feed:rust").unwrap();
+        let hits = search(&store(), &index(&store()), &HashSet::new(), &query, 2);
+        assert_eq!(vec!["post2", "post1"], links(&hits));
+    }
+}
@@ -0,0 +1,150 @@
+use crate::digest::Digest;
+use crate::download::Download;
+use crate::error::Result;
+use crate::fever::Fever;
+use crate::network::Network;
+use crate::notify::Notifier;
+use crate::rules::Rule;
+use crate::store;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::path::Path;
+
+/// Name of the file in data directory.
+pub(crate) const FILE: &str = "settings.json";
+
+/// Returns path where user settings should reside.
+pub(crate) fn settings_path() -> String {
+    let settings_path = Path::new(&crate::base_dir()).join(FILE);
+    String::from(settings_path.to_str().unwrap())
+}
+
+/// User preferences that apply across all tracked feeds.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct Settings {
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    /// Consecutive gone / not found responses after which feed is marked dead.
+    #[serde(
+        default = "default_dead_after",
+        deserialize_with = "deserialize_dead_after"
+    )]
+    pub dead_after: u32,
+    /// Days without new posts after which feed is reported as dormant.
+    #[serde(default = "default_dormant_after_days")]
+    pub dormant_after_days: i64,
+    /// Where new posts are sent after fetching.
+    #[serde(default)]
+    pub notifiers: Vec<Notifier>,
+    /// Where `digest` sends unread posts, if it is set up.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<Digest>,
+    /// Credentials of the Fever api of `serve`, which is off without them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fever: Option<Fever>,
+    /// Where and how `download` saves enclosures.
+    #[serde(default)]
+    pub download: Download,
+    /// Proxies and certificates feeds are requested with.
+    #[serde(default, skip_serializing_if = "Network::is_default")]
+    pub network: Network,
+}
+
+fn default_dead_after() -> u32 {
+    3
+}
+
+/// Zero would mark every feed dead on its first fetch.
+fn deserialize_dead_after<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<u32, D::Error> {
+    let dead_after = u32::deserialize(deserializer)?;
+    if dead_after < 1 {
+        return Err(serde::de::Error::custom("dead_after must be at least 1"));
+    }
+    Ok(dead_after)
+}
+
+fn default_dormant_after_days() -> i64 {
+    90
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            rules: vec![],
+            dead_after: default_dead_after(),
+            dormant_after_days: default_dormant_after_days(),
+            notifiers: vec![],
+            digest: None,
+            fever: None,
+            download: Download::default(),
+            network: Network::default(),
+        }
+    }
+}
+
+pub(crate) fn setup() -> Result<()> {
+    if store::init(&settings_path(), &Settings::default())? {
+        println!("creating settings path.");
+    } else {
+        println!("settings file already exists.");
+    }
+    Ok(())
+}
+
+/// Reads settings. Missing file means user has not customized anything yet.
+pub(crate) fn get() -> Result<Settings> {
+    _get(&settings_path())
+}
+
+pub(crate) fn _get(path: &str) -> Result<Settings> {
+    if !Path::new(path).is_file() {
+        return Ok(Settings::default());
+    }
+    store::read(path)
+}
+
+/// Replaces settings with the provided `Settings`
+pub(crate) fn replace(settings: Settings) -> Result<Settings> {
+    _replace(&settings_path(), settings)
+}
+
+pub(crate) fn _replace(path: &str, settings: Settings) -> Result<Settings> {
+    store::write(path, &settings)?;
+    Ok(settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_settings_path() {
+        assert!(settings_path().starts_with('/'));
+    }
+
+    #[test]
+    fn test_get_file_does_not_exist() {
+        assert_eq!(Settings::default(), _get("some/really/fake/path").unwrap());
+    }
+
+    #[test]
+    fn test_get_missing_fields() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "{{}}").unwrap();
+        assert_eq!(
+            Settings::default(),
+            _get(file.path().to_str().unwrap()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_get_invalid_dead_after() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "{{\"dead_after\": 0}}").unwrap();
+        assert!(_get(file.path().to_str().unwrap()).is_err());
+    }
+}
@@ -0,0 +1,137 @@
+use crate::error::{Error, Result};
+use crate::posts::{self, PostStore};
+use crate::readlist::{self, ReadList};
+use crate::store;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// Starred posts by feed. Unlike readlist entries, they are kept till
+/// unstarred, whether read or not.
+pub(crate) type Starred = HashMap<String, Vec<String>>;
+
+/// Name of the file in data directory.
+pub(crate) const FILE: &str = "starred.json";
+
+pub(crate) fn starred_path() -> String {
+    let starred_path = Path::new(&crate::base_dir()).join(FILE);
+    String::from(starred_path.to_str().unwrap())
+}
+
+pub(crate) fn setup() -> Result<()> {
+    if store::init(&starred_path(), &Starred::new())? {
+        println!("creating starred path.");
+    } else {
+        println!("starred file already exists.");
+    }
+    Ok(())
+}
+
+/// Reads starred posts. Missing file means nothing was starred yet.
+pub(crate) fn get() -> Result<Starred> {
+    _get(&starred_path())
+}
+
+pub(crate) fn _get(path: &str) -> Result<Starred> {
+    if !Path::new(path).is_file() {
+        return Ok(Starred::new());
+    }
+    store::read(path)
+}
+
+pub(crate) fn _replace(path: &str, starred: Starred) -> Result<Starred> {
+    store::write(path, &starred)?;
+    Ok(starred)
+}
+
+/// Links of every starred post.
+pub(crate) fn links(starred: &Starred) -> HashSet<String> {
+    starred.values().flatten().cloned().collect()
+}
+
+/// Feed post was found in, looked up in readlist and stored posts.
+pub(crate) fn feed_of(post: &str, readlist: &ReadList, store: &PostStore) -> Option<String> {
+    readlist
+        .iter()
+        .find(|(_, links)| links.iter().any(|l| l == post))
+        .map(|(feed, _)| feed.clone())
+        .or_else(|| store.get(post).map(|p| p.feed.clone()))
+}
+
+pub(crate) fn _star(mut starred: Starred, feed: &str, post: &str) -> Starred {
+    let links = starred.entry(feed.to_string()).or_default();
+    if !links.iter().any(|l| l == post) {
+        links.push(post.to_string());
+        links.sort();
+    }
+    starred
+}
+
+pub(crate) fn _unstar(mut starred: Starred, post: &str) -> Starred {
+    for links in starred.values_mut() {
+        links.retain(|l| l != post);
+    }
+    starred.retain(|_, links| !links.is_empty());
+    starred
+}
+
+pub(crate) fn _rename_feed(mut starred: Starred, feed: &str, url: &str) -> Starred {
+    if let Some(mut links) = starred.remove(feed) {
+        let moved = starred.entry(url.to_string()).or_default();
+        moved.append(&mut links);
+        moved.sort();
+        moved.dedup();
+    }
+    starred
+}
+
+/// Stars posts, which have to be unread or stored to know their feed.
+pub(crate) fn star(posts: &[String]) -> Result<Starred> {
+    let readlist = readlist::unread()?;
+    let store = posts::get()?;
+    let mut starred = get()?;
+    for post in posts {
+        let feed = feed_of(post, &readlist, &store)
+            .ok_or_else(|| Error::input(format!("unknown post: {}", post)))?;
+        starred = _star(starred, &feed, post);
+    }
+    _replace(&starred_path(), starred)
+}
+
+pub(crate) fn unstar(posts: &[String]) -> Result<Starred> {
+    let starred = posts.iter().fold(get()?, |s, p| _unstar(s, p));
+    _replace(&starred_path(), starred)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::posts::Post;
+
+    #[test]
+    fn test_star_and_unstar() {
+        let readlist = vec![("feed1".to_string(), vec!["post1".to_string()])]
+            .into_iter()
+            .collect();
+        let store = vec![("post2".to_string(), Post::bare("feed2", "post2"))]
+            .into_iter()
+            .collect();
+        assert_eq!(
+            Some("feed1".to_string()),
+            feed_of("post1", &readlist, &store)
+        );
+        assert_eq!(
+            Some("feed2".to_string()),
+            feed_of("post2", &readlist, &store)
+        );
+        assert_eq!(None, feed_of("post3", &readlist, &store));
+
+        let starred = _star(Starred::new(), "feed1", "post1");
+        let starred = _star(starred, "feed1", "post1");
+        let starred = _star(starred, "feed2", "post2");
+        assert_eq!(vec!["post1".to_string()], starred["feed1"]);
+        let starred = _rename_feed(starred, "feed2", "feed1");
+        assert_eq!(vec!["post1", "post2"], starred["feed1"]);
+        let starred = _unstar(_unstar(starred, "post1"), "post2");
+        assert!(starred.is_empty());
+    }
+}
@@ -0,0 +1,338 @@
+use crate::error::{Error, Result};
+use fs2::FileExt;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::{self, File};
+use std::path::Path;
+
+/// Number of previous versions kept of every data file.
+pub(crate) const BACKUPS: usize = 3;
+
+/// Name of the lock file in data directory.
+pub(crate) const LOCK_FILE: &str = ".lock";
+
+/// Upgrades data of a file from one format version to the next. Migration at
+/// index `i` upgrades version `i` to `i + 1`, so the current version of a
+/// file is the number of its migrations.
+pub(crate) type Migration = fn(Value) -> serde_json::Result<Value>;
+
+/// Layout of versioned data files.
+#[derive(Serialize, Deserialize)]
+struct Envelope<T> {
+    version: u32,
+    data: T,
+}
+
+/// Reads json data file at path.
+pub(crate) fn read<T: DeserializeOwned>(path: &str) -> Result<T> {
+    let data = fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+    serde_json::from_str(data.as_str()).map_err(|e| Error::parse(path, e))
+}
+
+/// Path of the nth newest backup of data file.
+pub(crate) fn backup_path(path: &str, nth: usize) -> String {
+    format!("{}.bak.{}", path, nth)
+}
+
+/// Keeps current content of data file as its newest backup, shifting older
+/// backups and dropping the oldest. Empty files are not worth keeping.
+fn rotate_backups(path: &str) -> Result<()> {
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.len() > 0 => {}
+        _ => return Ok(()),
+    }
+    for nth in (1..BACKUPS).rev() {
+        let from = backup_path(path, nth);
+        if Path::new(&from).is_file() {
+            let to = backup_path(path, nth + 1);
+            fs::rename(&from, &to).map_err(|e| Error::io(&to, e))?;
+        }
+    }
+    let backup = backup_path(path, 1);
+    fs::copy(path, &backup).map_err(|e| Error::io(&backup, e))?;
+    Ok(())
+}
+
+/// Writes value to data file at path as pretty json, creating data directory
+/// if it does not exist yet. Data is written to a temporary file first and
+/// moved in place, so that an interrupted write never truncates the file.
+pub(crate) fn write<T: Serialize>(path: &str, value: &T) -> Result<()> {
+    if let Some(dir) = Path::new(path).parent() {
+        fs::create_dir_all(dir).map_err(|e| Error::io(&dir.to_string_lossy(), e))?;
+    }
+    let data = serde_json::to_string_pretty(value).map_err(|e| Error::serialize(path, e))?;
+    rotate_backups(path)?;
+    let tmp = format!("{}.tmp", path);
+    fs::write(&tmp, data).map_err(|e| Error::io(&tmp, e))?;
+    fs::rename(&tmp, path).map_err(|e| Error::io(path, e))
+}
+
+/// Upgrades 0.1.x files, which were written without envelope. Their data
+/// is the same as that of version 1.
+pub(crate) fn envelope(data: Value) -> serde_json::Result<Value> {
+    Ok(data)
+}
+
+/// Splits stored value into its format version and data. Files written
+/// before versioning are version 0.
+fn unwrap(value: Value) -> (u32, Value) {
+    match value {
+        Value::Object(mut object) if object.len() == 2 && object.contains_key("data") => {
+            match object.get("version").and_then(Value::as_u64) {
+                Some(version) => (version as u32, object.remove("data").unwrap()),
+                None => (0, Value::Object(object)),
+            }
+        }
+        value => (0, value),
+    }
+}
+
+/// Parses versioned data, migrating it in memory when it is in an older
+/// format. Returns data along with the version it was stored in.
+fn decode<T: DeserializeOwned>(
+    path: &str,
+    data: &str,
+    migrations: &[Migration],
+) -> Result<(T, u32)> {
+    let current = migrations.len() as u32;
+    let value = serde_json::from_str(data).map_err(|e| Error::parse(path, e))?;
+    let (version, mut value) = unwrap(value);
+    if version > current {
+        return Err(Error::Version {
+            path: path.to_string(),
+            found: version,
+            supported: current,
+        });
+    }
+    if version == current {
+        // Parsing text again keeps line and column in errors.
+        let envelope: Envelope<T> =
+            serde_json::from_str(data).map_err(|e| Error::parse(path, e))?;
+        return Ok((envelope.data, version));
+    }
+    for migration in &migrations[version as usize..] {
+        value = migration(value).map_err(|e| Error::parse(path, e))?;
+    }
+    let data = serde_json::from_value(value).map_err(|e| Error::parse(path, e))?;
+    Ok((data, version))
+}
+
+/// Reads versioned data file at path. Files in an older format are upgraded
+/// in place, keeping the original next to it as `<file>.v<version>.bak`.
+pub(crate) fn read_versioned<T>(path: &str, migrations: &[Migration]) -> Result<T>
+where
+    T: DeserializeOwned + Serialize,
+{
+    let data = fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+    let (value, version) = decode(path, &data, migrations)?;
+    if version < migrations.len() as u32 {
+        let backup = format!("{}.v{}.bak", path, version);
+        fs::write(&backup, &data).map_err(|e| Error::io(&backup, e))?;
+        write_versioned(path, migrations, &value)?;
+    }
+    Ok(value)
+}
+
+/// Writes value to data file at path in the current format version.
+pub(crate) fn write_versioned<T: Serialize>(
+    path: &str,
+    migrations: &[Migration],
+    value: &T,
+) -> Result<()> {
+    let envelope = Envelope {
+        version: migrations.len() as u32,
+        data: value,
+    };
+    write(path, &envelope)
+}
+
+/// Returns format version a data file is stored in after checking it can be
+/// read, without upgrading it.
+pub(crate) fn inspect<T: DeserializeOwned>(path: &str, migrations: &[Migration]) -> Result<u32> {
+    let data = fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+    let (_, version) = decode::<T>(path, &data, migrations)?;
+    Ok(version)
+}
+
+/// Creates data file at path with initial value, unless it already exists.
+/// Returns whether file was created.
+pub(crate) fn init<T: Serialize>(path: &str, value: &T) -> Result<bool> {
+    if Path::new(path).is_file() {
+        return Ok(false);
+    }
+    write(path, value)?;
+    Ok(true)
+}
+
+/// Same as `init`, for versioned data files.
+pub(crate) fn init_versioned<T: Serialize>(
+    path: &str,
+    migrations: &[Migration],
+    value: &T,
+) -> Result<bool> {
+    if Path::new(path).is_file() {
+        return Ok(false);
+    }
+    write_versioned(path, migrations, value)?;
+    Ok(true)
+}
+
+/// Exclusive lock on a data directory, held till dropped. Processes sharing
+/// the directory, like the cli and `serve`, take it around read-modify-write
+/// of data files so that they do not undo each other's writes.
+#[derive(Debug)]
+pub struct FileLock(File);
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        let _ = FileExt::unlock(&self.0);
+    }
+}
+
+/// Locks data directory, waiting for other processes holding it, and telling
+/// so. Lock is not reentrant: taking it again before dropping it blocks
+/// forever.
+pub(crate) fn lock(dir: &Path) -> Result<FileLock> {
+    fs::create_dir_all(dir).map_err(|e| Error::io(&dir.to_string_lossy(), e))?;
+    let path = dir.join(LOCK_FILE);
+    let path = path.to_string_lossy();
+    let file = File::create(path.as_ref()).map_err(|e| Error::io(&path, e))?;
+    match file.try_lock_exclusive() {
+        Ok(()) => return Ok(FileLock(file)),
+        Err(e) if e.kind() == fs2::lock_contended_error().kind() => {
+            eprintln!("waiting for another rss-update to finish...");
+        }
+        Err(e) => return Err(Error::io(&path, e)),
+    }
+    file.lock_exclusive().map_err(|e| Error::io(&path, e))?;
+    Ok(FileLock(file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// Version 2 of a test format, renaming `url` to `feed`.
+    fn rename_url(mut data: Value) -> serde_json::Result<Value> {
+        for item in data.as_array_mut().into_iter().flatten() {
+            if let Some(object) = item.as_object_mut() {
+                let url = object.remove("url").unwrap_or(Value::Null);
+                object.insert("feed".to_string(), url);
+            }
+        }
+        Ok(data)
+    }
+
+    const MIGRATIONS: &[Migration] = &[envelope, rename_url];
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Item {
+        feed: String,
+    }
+
+    fn path(dir: &TempDir) -> String {
+        dir.path().join("data.json").to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_init_creates_missing_dir() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("data").join("list.json");
+        let path = path.to_str().unwrap();
+        assert!(init(path, &vec!["a"]).unwrap());
+        assert!(!init(path, &Vec::<String>::new()).unwrap());
+        assert_eq!(vec!["a".to_string()], read::<Vec<String>>(path).unwrap());
+    }
+
+    #[test]
+    fn test_write_rotates_backups() {
+        let dir = TempDir::new().unwrap();
+        let path = path(&dir);
+        for n in 0..5 {
+            write(&path, &vec![n]).unwrap();
+        }
+        assert_eq!(vec![4], read::<Vec<u32>>(&path).unwrap());
+        for nth in 1..=BACKUPS {
+            let backup = read::<Vec<usize>>(&backup_path(&path, nth)).unwrap();
+            assert_eq!(vec![4 - nth], backup);
+        }
+        assert!(!Path::new(&backup_path(&path, BACKUPS + 1)).exists());
+        assert!(!Path::new(&format!("{}.tmp", path)).exists());
+    }
+
+    #[test]
+    fn test_read_versioned_unversioned_file() {
+        let dir = TempDir::new().unwrap();
+        let path = path(&dir);
+        let original = r#"[{"url": "feed1"}]"#;
+        fs::write(&path, original).unwrap();
+
+        let items = read_versioned::<Vec<Item>>(&path, MIGRATIONS).unwrap();
+        assert_eq!(
+            vec![Item {
+                feed: "feed1".to_string()
+            }],
+            items
+        );
+        assert_eq!(
+            original,
+            fs::read_to_string(format!("{}.v0.bak", path)).unwrap()
+        );
+        assert_eq!(2, inspect::<Vec<Item>>(&path, MIGRATIONS).unwrap());
+        assert_eq!(
+            items,
+            read_versioned::<Vec<Item>>(&path, MIGRATIONS).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_read_versioned_older_version() {
+        let dir = TempDir::new().unwrap();
+        let path = path(&dir);
+        fs::write(&path, r#"{"version": 1, "data": [{"url": "feed1"}]}"#).unwrap();
+        assert_eq!(1, inspect::<Vec<Item>>(&path, MIGRATIONS).unwrap());
+
+        let items = read_versioned::<Vec<Item>>(&path, MIGRATIONS).unwrap();
+        assert_eq!(
+            vec![Item {
+                feed: "feed1".to_string()
+            }],
+            items
+        );
+        assert!(Path::new(&format!("{}.v1.bak", path)).is_file());
+        assert!(!Path::new(&format!("{}.v0.bak", path)).exists());
+    }
+
+    #[test]
+    fn test_read_versioned_errors() {
+        let dir = TempDir::new().unwrap();
+        let path = path(&dir);
+        fs::write(&path, r#"{"version": 3, "data": []}"#).unwrap();
+        match read_versioned::<Vec<Item>>(&path, MIGRATIONS) {
+            Err(Error::Version {
+                found: 3,
+                supported: 2,
+                ..
+            }) => {}
+            other => panic!("expected version error, got {:?}", other),
+        }
+
+        fs::write(&path, "{\"version\": 2,\n\"data\": [{\"url\": 1}]}").unwrap();
+        match read_versioned::<Vec<Item>>(&path, MIGRATIONS) {
+            Err(Error::Parse { line: 2, .. }) => {}
+            other => panic!("expected parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_lock() {
+        let dir = TempDir::new().unwrap();
+        let held = lock(dir.path()).unwrap();
+        let other = File::open(dir.path().join(LOCK_FILE)).unwrap();
+        assert!(other.try_lock_exclusive().is_err());
+        drop(held);
+        assert!(other.try_lock_exclusive().is_ok());
+    }
+}
@@ -0,0 +1,405 @@
+use reqwest::Url;
+use scraper::{ElementRef, Html, Node};
+
+/// How a span of terminal text is shown, mapped to colors by display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Style {
+    Plain,
+    Heading,
+    Strong,
+    Emphasis,
+    Code,
+    Link,
+    /// Reference of a link to its footnote, and the footnote itself.
+    Footnote,
+    Quote,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Span {
+    pub(crate) style: Style,
+    pub(crate) text: String,
+}
+
+impl Span {
+    fn new(style: Style, text: &str) -> Self {
+        Span {
+            style,
+            text: sanitize(text),
+        }
+    }
+}
+
+/// Text of a feed with control characters other than newlines and tabs left
+/// out, so that escape sequences it holds are not run by the terminal.
+pub(crate) fn sanitize(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_control() || *c == '\n' || *c == '\t')
+        .collect()
+}
+
+pub(crate) type Line = Vec<Span>;
+
+/// Elements whose content is not shown.
+const SKIPPED: &[&str] = &[
+    "script", "style", "noscript", "head", "iframe", "form", "button", "svg", "template",
+];
+
+/// Lays out html as lines of at most `width` chars, code blocks aside.
+struct Renderer {
+    width: usize,
+    lines: Vec<Line>,
+    /// Inline content of the block being laid out.
+    inline: Vec<Span>,
+    styles: Vec<Style>,
+    /// Prefix of every line, from quotes and lists the block is in.
+    indent: Vec<Span>,
+    /// Marker of list item whose first line is yet to be laid out.
+    marker: Option<String>,
+    /// Item count of ordered lists being laid out, `None` for unordered ones.
+    lists: Vec<Option<usize>>,
+    /// Link targets, shown as footnotes after the text.
+    links: Vec<String>,
+    base: Option<Url>,
+}
+
+impl Renderer {
+    fn new(base: &str, width: usize) -> Self {
+        Renderer {
+            width: width.max(20),
+            lines: vec![],
+            inline: vec![],
+            styles: vec![Style::Plain],
+            indent: vec![],
+            marker: None,
+            lists: vec![],
+            links: vec![],
+            base: Url::parse(base).ok(),
+        }
+    }
+
+    fn style(&self) -> Style {
+        *self.styles.last().unwrap_or(&Style::Plain)
+    }
+
+    fn push(&mut self, style: Style, text: &str) {
+        self.inline.push(Span::new(style, text));
+    }
+
+    /// Separates blocks with a blank line.
+    fn blank(&mut self) {
+        self.flush();
+        if self.lines.last().is_some_and(|l| !l.is_empty()) {
+            self.lines.push(vec![]);
+        }
+    }
+
+    /// Wraps inline content laid out so far into lines.
+    fn flush(&mut self) {
+        let inline = std::mem::take(&mut self.inline);
+        // Words along with whether they follow a space.
+        let mut words = vec![];
+        let mut spaced = false;
+        for span in inline {
+            let mut parts = span.text.split(char::is_whitespace).peekable();
+            while let Some(part) = parts.next() {
+                if !part.is_empty() {
+                    words.push((spaced, Span::new(span.style, part)));
+                    spaced = false;
+                }
+                if parts.peek().is_some() {
+                    spaced = !words.is_empty();
+                }
+            }
+        }
+        if words.is_empty() {
+            return;
+        }
+        let marker = self.marker.take().unwrap_or_default();
+        let hanging = " ".repeat(marker.chars().count());
+        let prefix_len = self
+            .indent
+            .iter()
+            .map(|s| s.text.chars().count())
+            .sum::<usize>()
+            + hanging.len();
+        let mut line = self.prefix(&marker);
+        let mut len = prefix_len;
+        let mut empty = true;
+        for (spaced, word) in words {
+            let word_len = word.text.chars().count();
+            let space = usize::from(spaced && !empty);
+            if !empty && len + space + word_len > self.width {
+                self.lines
+                    .push(std::mem::replace(&mut line, self.prefix(&hanging)));
+                len = prefix_len;
+                empty = true;
+            }
+            if spaced && !empty {
+                line.push(Span::new(Style::Plain, " "));
+                len += 1;
+            }
+            len += word_len;
+            line.push(word);
+            empty = false;
+        }
+        self.lines.push(line);
+    }
+
+    fn prefix(&self, marker: &str) -> Line {
+        let mut prefix = self.indent.clone();
+        if !marker.is_empty() {
+            prefix.push(Span::new(Style::Plain, marker));
+        }
+        prefix
+    }
+
+    fn children(&mut self, element: &ElementRef<'_>) {
+        for child in element.children() {
+            match child.value() {
+                Node::Text(text) => {
+                    let style = self.style();
+                    self.push(style, text);
+                }
+                Node::Element(_) => {
+                    if let Some(child) = ElementRef::wrap(child) {
+                        self.element(&child);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn styled(&mut self, style: Style, element: &ElementRef<'_>) {
+        self.styles.push(style);
+        self.children(element);
+        self.styles.pop();
+    }
+
+    fn element(&mut self, element: &ElementRef<'_>) {
+        let name = element.value().name();
+        if SKIPPED.contains(&name) {
+            return;
+        }
+        match name {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.blank();
+                self.styled(Style::Heading, element);
+                self.blank();
+            }
+            "p" | "div" | "section" | "article" | "main" | "table" | "figure" | "header"
+            | "footer" | "dl" => {
+                self.blank();
+                self.children(element);
+                self.blank();
+            }
+            "br" | "tr" | "dt" | "dd" | "figcaption" => {
+                self.flush();
+                self.children(element);
+                self.flush();
+            }
+            "hr" => {
+                self.blank();
+                self.lines.push(vec![Span::new(
+                    Style::Plain,
+                    &"─".repeat(self.width.min(40)),
+                )]);
+                self.blank();
+            }
+            "pre" => {
+                self.blank();
+                let code = element.text().collect::<String>();
+                for line in code.trim_end().lines() {
+                    let mut spans = self.prefix("    ");
+                    spans.push(Span::new(Style::Code, line));
+                    self.lines.push(spans);
+                }
+                self.blank();
+            }
+            "code" | "kbd" | "samp" => self.styled(Style::Code, element),
+            "strong" | "b" => self.styled(Style::Strong, element),
+            "em" | "i" => self.styled(Style::Emphasis, element),
+            "a" => {
+                let href = element
+                    .value()
+                    .attr("href")
+                    .filter(|h| !h.starts_with('#') && !h.starts_with("javascript:"))
+                    .map(|h| match &self.base {
+                        Some(base) => base.join(h).map_or(h.to_string(), String::from),
+                        None => h.to_string(),
+                    });
+                self.styled(Style::Link, element);
+                if let Some(href) = href {
+                    let number = match self.links.iter().position(|l| *l == href) {
+                        Some(i) => i + 1,
+                        None => {
+                            self.links.push(href);
+                            self.links.len()
+                        }
+                    };
+                    self.push(Style::Footnote, &format!("[{}]", number));
+                }
+            }
+            "img" => {
+                if let Some(alt) = element.value().attr("alt").filter(|a| !a.trim().is_empty()) {
+                    self.push(Style::Emphasis, &format!(" [image: {}] ", alt.trim()));
+                }
+            }
+            "blockquote" => {
+                self.blank();
+                self.indent.push(Span::new(Style::Quote, "│ "));
+                self.styled(Style::Quote, element);
+                self.flush();
+                self.indent.pop();
+                self.blank();
+            }
+            "ul" | "ol" => {
+                if self.lists.is_empty() {
+                    self.blank();
+                } else {
+                    self.flush();
+                }
+                self.lists.push(Some(0).filter(|_| name == "ol"));
+                self.children(element);
+                self.flush();
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.blank();
+                }
+            }
+            "li" => {
+                self.flush();
+                let depth = self.lists.len().saturating_sub(1);
+                let marker = match self.lists.last_mut() {
+                    Some(Some(count)) => {
+                        *count += 1;
+                        format!("{}. ", count)
+                    }
+                    _ => "• ".to_string(),
+                };
+                self.indent
+                    .push(Span::new(Style::Plain, &"   ".repeat(depth.min(1))));
+                self.marker = Some(marker);
+                self.children(element);
+                self.flush();
+                self.indent.pop();
+            }
+            _ => self.children(element),
+        }
+    }
+
+    /// Lines laid out, followed by footnotes of links.
+    fn finish(mut self) -> Vec<Line> {
+        self.blank();
+        for (i, link) in self.links.iter().enumerate() {
+            self.lines.push(vec![Span::new(
+                Style::Footnote,
+                &format!("[{}] {}", i + 1, link),
+            )]);
+        }
+        while self.lines.last().is_some_and(|l| l.is_empty()) {
+            self.lines.pop();
+        }
+        self.lines
+    }
+}
+
+/// Lays out html as wrapped, styled lines. Links are numbered and listed as
+/// footnotes after the text, resolved against base url.
+pub(crate) fn render(html: &str, base: &str, width: usize) -> Vec<Line> {
+    let fragment = Html::parse_fragment(html);
+    let mut renderer = Renderer::new(base, width);
+    renderer.children(&fragment.root_element());
+    renderer.finish()
+}
+
+/// Lays out plain text, e.g. articles kept as markdown, wrapping long lines.
+pub(crate) fn render_text(text: &str, width: usize) -> Vec<Line> {
+    let mut renderer = Renderer::new("", width);
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            renderer.blank();
+        } else {
+            renderer.push(Style::Plain, line);
+            renderer.flush();
+        }
+    }
+    renderer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(lines: &[Line]) -> Vec<String> {
+        lines
+            .iter()
+            .map(|l| l.iter().map(|s| s.text.as_str()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_render() {
+        let html = r#"<h2>Cancel safety</h2>
+<p>Futures can be <em>dropped</em> at any point, see <a href="/docs">the docs</a> or
+<a href="https://tokio.rs">tokio</a>.</p>
+<pre><code>select! {
+    _ = a => {}
+}</code></pre>
+<ul><li>first</li><li>second<ol><li>nested</li></ol></li></ul>
+<blockquote>quoted</blockquote>"#;
+        let lines = render(html, "https://blog.example.com/posts/1", 30);
+        assert_eq!(
+            vec![
+                "Cancel safety",
+                "",
+                "Futures can be dropped at any",
+                "point, see the docs[1] or",
+                "tokio[2].",
+                "",
+                "    select! {",
+                "        _ = a => {}",
+                "    }",
+                "",
+                "• first",
+                "• second",
+                "   1. nested",
+                "",
+                "│ quoted",
+                "",
+                "[1] https://blog.example.com/docs",
+                "[2] https://tokio.rs/",
+            ],
+            plain(&lines)
+        );
+        assert_eq!(Style::Heading, lines[0][0].style);
+        assert_eq!(
+            Span::new(Style::Footnote, "[1]"),
+            lines[3].iter().find(|s| s.text == "[1]").cloned().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_render_text() {
+        let lines = render_text("# Title\n\nsome words to wrap here", 20);
+        assert_eq!(
+            vec!["# Title", "", "some words to wrap", "here"],
+            plain(&lines)
+        );
+    }
+
+    #[test]
+    fn test_render_strips_control_characters() {
+        let html = "<p>a\u{1b}]52;c;ZXZpbA==\u{7}b &#27;[2J\u{9b}1m</p><pre>\tc\u{1b}[0m</pre>";
+        let lines = render(html, "", 80);
+        assert_eq!(
+            vec!["a]52;c;ZXZpbA==b [2J1m", "", "    \tc[0m"],
+            plain(&lines)
+        );
+        assert_eq!(
+            vec!["title[31m"],
+            plain(&render_text("title\u{1b}[31m\u{7f}", 80))
+        );
+    }
+}